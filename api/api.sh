@@ -17,7 +17,15 @@ fi
 API="X-Api-Key:$FUJITSU_API_KEY"
 ACCEPT="Accept:application/json"
 CONTENT_TYPE="Content-type:application/json"
-BASE_URL="https://api.aispf.global.fujitsu.com"
+BASE_URL=`cat .env | grep FUJITSU_BASE_URL | cut -d '=' -f 2`
+if [ -z $BASE_URL ]; then # Use the Fujitsu API unless FUJITSU_BASE_URL is set (ex: the local mock server)
+    BASE_URL="https://api.aispf.global.fujitsu.com"
+fi
+WORKSPACE=`cat .env | grep FUJITSU_WORKSPACE | cut -d '=' -f 2`
+if [ -z $WORKSPACE ]; then # Same as --out-dir of the program, relative to ./api
+    WORKSPACE="../workspace"
+fi
+export FUJITSU_WORKSPACE=$WORKSPACE # Read by get_meta.py
 
 # POST
 # curl -H 'X-Api-Key:<API_KEY>' -H 'Accept: application/json' -H 'Content-type: application/json' -X POST -d @<JSON_FILE> <BASE_URL>/da/v3/async/qubo/solve
@@ -104,10 +112,10 @@ elif [ $cmd = "status" ]; then
     curl -H $API -H $ACCEPT -H $CONTENT_TYPE -X GET $BASE_URL/da/v3/async/jobs/result/$JOB_ID | json_pp | grep "status"
 elif [ $cmd = "get" ]; then
     check_job && get_meta
-    mkdir -p ../target/Gamma${Gamma} # Create directory if not exist
-    curl -H $API -H $ACCEPT -H $CONTENT_TYPE -X GET $BASE_URL/da/v3/async/jobs/result/$JOB_ID | json_pp > ../target/Gamma${Gamma}/${METADATA}.json
+    mkdir -p $WORKSPACE/Gamma${Gamma} # Create directory if not exist
+    curl -H $API -H $ACCEPT -H $CONTENT_TYPE -X GET $BASE_URL/da/v3/async/jobs/result/$JOB_ID | json_pp > $WORKSPACE/Gamma${Gamma}/${METADATA}.json
 elif [ $cmd = "post" ]; then
-    curl -H $API -H $ACCEPT -H $CONTENT_TYPE -X POST -d @../target/input.json $BASE_URL/da/v3/async/qubo/solve | json_pp
+    curl -H $API -H $ACCEPT -H $CONTENT_TYPE -X POST -d @$WORKSPACE/input.json $BASE_URL/da/v3/async/qubo/solve | json_pp
 elif [ $cmd = "delete" ]; then
     check_job
     curl -H $API -H $ACCEPT -H $CONTENT_TYPE -X DELETE $BASE_URL/da/v3/async/jobs/result/$JOB_ID | json_pp | grep "status"
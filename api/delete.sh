@@ -12,7 +12,10 @@ fi
 API="X-Api-Key:$FUJITSU_API_KEY"
 ACCEPT="Accept:application/json"
 CONTENT_TYPE="Content-type:application/json"
-BASE_URL="https://api.aispf.global.fujitsu.com"
+BASE_URL=`cat .env | grep FUJITSU_BASE_URL | cut -d '=' -f 2`
+if [ -z $BASE_URL ]; then # Use the Fujitsu API unless FUJITSU_BASE_URL is set (ex: the local mock server)
+    BASE_URL="https://api.aispf.global.fujitsu.com"
+fi
 
 # Get all jobs
 curl -H $API -H $ACCEPT -H $CONTENT_TYPE -X GET $BASE_URL/da/v3/async/jobs | json_pp > delete_jobs.txt
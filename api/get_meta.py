@@ -1,7 +1,9 @@
 import json
+import os
 import sys
 
 if __name__ == "__main__":
-    data = json.load(open("../target/metadata.json"))
+    workspace = os.environ.get("FUJITSU_WORKSPACE", "../workspace")  # Set by api.sh
+    data = json.load(open(os.path.join(workspace, "metadata.json")))
     arg = sys.argv[1]
     print(data[arg])
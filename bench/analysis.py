@@ -0,0 +1,90 @@
+# Benchmark of the Gamma analysis on a 1024-solution L=18 result file
+#
+# python3 ./bench/analysis.py [HEIGHT: int, default 4] [BASELINE: git revision, optional]
+# Run from the root of the repository. The sequential time uses RAYON_NUM_THREADS=1,
+# BASELINE builds another revision in a git worktree and times it on the same file.
+
+import json
+import os
+import random
+import subprocess
+import sys
+import time
+
+LENGTH = 18
+SOLUTIONS = 1024
+REPEAT = 3
+
+
+def make_result(file_path, length, height):
+    # Random configurations, the analysis does not depend on the energies
+    random.seed(0)
+    n = length * length * height
+    with open(file_path, "w") as f:
+        f.write('{"qubo_solution":{"progress":[],"result_status":true,"solutions":[')
+        for k in range(SOLUTIONS):
+            if k:
+                f.write(",")
+            config = {str(i): random.random() < 0.5 for i in range(n)}
+            f.write(json.dumps({"configuration": config, "energy": -k, "frequency": 1}))
+        f.write('],"timing":{"solve_time":"0","total_elapsed_time":"0"}},"status":"Done"}')
+
+
+def build(directory):
+    subprocess.run(["cargo", "build", "--release", "-q"], cwd=directory, check=True)
+    return os.path.join(directory, "target", "release", "fujitsu")
+
+
+def analyze_command(binary):
+    # Revisions before the subcommands take the result file with -g
+    probe = subprocess.run([binary, "analyze", "--help"], capture_output=True)
+    return [binary, "analyze"] if probe.returncode == 0 else [binary, "-g"]
+
+
+def best_time(binary, file_path, threads=None):
+    command = analyze_command(binary)
+    env = dict(os.environ)
+    if threads is not None:
+        env["RAYON_NUM_THREADS"] = str(threads)
+    times = []
+    for _ in range(REPEAT):
+        start = time.perf_counter()
+        subprocess.run(command + [file_path], env=env, stdout=subprocess.DEVNULL, check=True)
+        times.append(time.perf_counter() - start)
+    return min(times)
+
+
+def main():
+    height = int(sys.argv[1]) if len(sys.argv) > 1 else 4
+    baseline = sys.argv[2] if len(sys.argv) > 2 else None
+
+    os.makedirs("./workspace/Gamma0.5", exist_ok=True)
+    file_path = f"./workspace/Gamma0.5/Strength1.0_Lattice{LENGTH}_{LENGTH}_{height}_BENCH.json"
+    print(f"Generating {file_path} ({SOLUTIONS} solutions)...")
+    make_result(file_path, LENGTH, height)
+
+    binary = build(".")
+    subprocess.run([binary, "convert", file_path], stdout=subprocess.DEVNULL, check=True)
+    packed_path = file_path[: -len(".json")] + ".sols"
+
+    print(f"cores: {os.cpu_count()}")
+    rows = []
+    if baseline is not None:
+        worktree = "./target/bench-baseline"
+        subprocess.run(["git", "worktree", "add", "-f", worktree, baseline], check=True)
+        try:
+            rows.append((f"{baseline} (json)", best_time(build(worktree), file_path)))
+        finally:
+            subprocess.run(["git", "worktree", "remove", "--force", worktree], check=True)
+    rows.append(("1 thread (json)", best_time(binary, file_path, 1)))
+    rows.append(("all threads (json)", best_time(binary, file_path)))
+    rows.append(("1 thread (sols)", best_time(binary, packed_path, 1)))
+    rows.append(("all threads (sols)", best_time(binary, packed_path)))
+
+    reference = rows[0][1]
+    for name, seconds in rows:
+        print(f"{name:>24}: {seconds:8.3f} s  x{reference / seconds:.2f}")
+
+
+if __name__ == "__main__":
+    main()
@@ -17,11 +17,11 @@ elif [ $FILE_TYPE = "json" ]; then
 
     # python3 ./order_p.py [PATH_TO_JSON: json file] [OUTPUT_RESULT: bool] [PLOT_RESULT: bool]
     echo "Loading..."
-    python3 ./order_p.py $FILE_PATH true false > ../target/Gamma${Gamma}/${METADATA}.txt
+    python3 ./order_p.py $FILE_PATH true false > ../workspace/Gamma${Gamma}/${METADATA}.txt
 
     read -p "Do you want to plot the result? (y/n) " yn
     if [ $yn = "y" ]; then
-        python3 ./plot.py ../target/Gamma${Gamma}/${METADATA}.txt
+        python3 ./plot.py ../workspace/Gamma${Gamma}/${METADATA}.txt
     fi
 else
     echo "File type error!"
@@ -17,7 +17,7 @@ def main():
         return
 
 
-# Json file formet ex: ../target/Gamma0.0/Strength1.0_Lattice18_18_1_Time600.json
+# Json file formet ex: ../workspace/Gamma0.0/Strength1.0_Lattice18_18_1_Time600.json
 
 
 def get_file_type(file_path):
@@ -103,7 +103,7 @@ def get_order_parameter(L: int, length_of_qubo: int, output: bool) -> list[list[
 
 
 def split_filename(filename: str) -> dict[str, float]:
-    # filename e.x.: ../target/Gamma0.0/Strength1.0_Lattice18_18_1_Time600.json
+    # filename e.x.: ../workspace/Gamma0.0/Strength1.0_Lattice18_18_1_Time600.json
     args: list[str] = filename.split("/")
     dataset: dict[str, float] = {}
     dataset["Gamma"] = float(args[2].lstrip("Gamma"))
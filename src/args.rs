@@ -1,79 +1,509 @@
+use crate::da3_params::read_run_file;
+use crate::error::{Error, Result};
+use crate::initial_state::STATES;
+use crate::run_config::{config_args, config_da3};
+use crate::sqa::layer_strength;
 use crate::Jxx;
+use serde::Serialize;
+use serde_json::Value;
 use structopt::StructOpt;
 
-#[allow(non_snake_case)]
+const SUBCOMMANDS: [&str; 7] = [
+    "generate",
+    "analyze",
+    "guidance",
+    "solve",
+    "submit",
+    "convert",
+    "mock-server",
+];
+const SOLVERS: [&str; 6] = ["sqa", "exact", "tabu", "bsb", "dsb", "da"];
+const EXPORT_FORMATS: [&str; 4] = ["qubo", "sparse", "mtx", "bqm"];
+const FORMS: [&str; 2] = ["qubo", "ising"];
+const SELECTIONS: [&str; 6] = [
+    "random",
+    "lowest-energy",
+    "highest-frequency",
+    "order",
+    "c6",
+    "nearest",
+];
+
 #[derive(Debug, StructOpt)]
 pub struct Options {
+    #[structopt(subcommand)]
+    pub cmd: Command,
+}
+
+#[derive(Debug, StructOpt)]
+#[allow(clippy::large_enum_variant)] // Parsed once, the generate flags are most of it
+pub enum Command {
+    #[structopt(name = "generate")]
+    /// Generate the request of the lattice model (the default without a subcommand)
+    Generate(GenerateArgs),
+    #[structopt(name = "analyze")]
+    /// Write the Gamma analysis of the solutions of a result file
+    Analyze(AnalyzeArgs),
+    #[structopt(name = "guidance")]
+    /// Use a solution of a result file as the guidance_config of the request
+    Guidance(GuidanceArgs),
+    #[structopt(name = "solve")]
+    /// Solve, convert, export or verify a request file or an external instance
+    Solve(SolveArgs),
+    #[structopt(name = "submit")]
+    /// Post the request to the DA3 API, or get the result of a job
+    Submit(SubmitArgs),
+    #[structopt(name = "convert")]
+    /// Convert a result between the DA JSON and the packed .sols form
+    Convert {
+        /// Result file (.json or .sols)
+        path: String,
+    },
+    #[structopt(name = "mock-server")]
+    /// Serve a local mock of the DA3 async API, solving jobs with the DA emulator
+    MockServer {
+        #[structopt(short = "p", long = "port", default_value = "8080")]
+        /// Port to listen on (localhost only)
+        port: u16,
+        #[structopt(long = "api-key", env = "FUJITSU_API_KEY")]
+        /// Value of the X-Api-Key header the requests must carry
+        api_key: String,
+    },
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, StructOpt, Serialize)]
+pub struct GenerateArgs {
+    #[structopt(long = "config", value_name = "file")]
+    /// TOML or JSON run file, overridden by the flags
+    pub config_path: Option<String>,
+    #[structopt(flatten)]
+    #[serde(flatten)]
+    pub workspace: WorkspaceArgs,
     #[structopt(short = "J", long = "J")]
     /// J_{i,j} of x_i, x_j
     pub J: Option<f64>,
     #[structopt(short = "G", long = "gamma")]
     /// Gamma of the Hamiltonian
     pub Gamma: Option<f64>,
+    #[structopt(long = "layer-strength", value_name = "K")]
+    /// J_{i,j} between the layers [default: -ln(tanh Gamma) / 2 with --gamma, 1.0 without]
+    pub layer_strength: Option<f64>,
     #[structopt(short = "L", long = "length")]
     /// Side length of the triangular Lattice
     pub L: Option<i32>,
     #[structopt(short = "H", long = "height")]
     /// Height of the triangular Lattice
     pub H: Option<i32>,
-    #[structopt(short = "T", long = "time")]
-    /// Time limit of the Fujitsu request
-    pub T: Option<i32>,
     #[structopt(short = "u", long = "use-random")]
     /// Use random strength for each node
     pub use_random: bool,
+    #[structopt(long = "disorder-seed", value_name = "seed")]
+    /// Seed of the random strengths of --use-random
+    pub disorder_seed: Option<u64>,
     #[structopt(short = "d", long = "debug-output")]
     /// Output debug information
     pub debug_output: bool,
     #[structopt(short = "w", long = "without-cycle")]
     /// Without cycle
     pub without_cycle: bool,
-    #[structopt(short = "g", long = "gamma-analysis")]
-    /// Gamma analysis target file
-    pub file_path: Option<String>,
-    #[structopt(long = "guidance-config")]
-    /// Use guidance config
+    #[structopt(long = "constraints")]
+    /// Add the constraints of this JSON file to the generated problem
+    pub constraints_path: Option<String>,
+    #[structopt(long = "magnetization", value_name = "M", allow_hyphen_values = true)]
+    /// Fix the total magnetisation of each layer
+    pub magnetization: Option<i32>,
+    #[structopt(
+        long = "sub-magnetization",
+        value_name = "R,G,B",
+        allow_hyphen_values = true
+    )]
+    /// Fix the magnetisation of each sub-lattice of each layer
+    pub sub_magnetization: Option<String>,
+    #[structopt(
+        long = "magnetization-mode",
+        value_name = "mode",
+        default_value = "objective",
+        possible_values = &["objective", "penalty", "inequalities"]
+    )]
+    /// Magnetisation constraint
+    pub magnetization_mode: String,
+    #[structopt(long = "penalty-weight", value_name = "w")]
+    /// Weight of the magnetisation penalty [default: scale x max|J|]
+    pub penalty_weight: Option<f64>,
+    #[structopt(long = "penalty-scale", value_name = "scale")]
+    /// Magnetisation penalty relative to max|J| [default: 2 x couplings per spin]
+    pub penalty_scale: Option<f64>,
+    #[structopt(long = "fix", value_name = "sites=spin,...")]
+    /// Fixed spins, sites: red, green, blue, row<i>, col<j> or an index, spin: up or down (ex: blue=down,row0=up)
+    pub fix: Option<String>,
+    #[structopt(
+        long = "fix-mode",
+        value_name = "mode",
+        default_value = "fixed-config",
+        possible_values = &["fixed-config", "substitute"]
+    )]
+    /// Fixed spins as fixed_config of fujitsuDA3, or substituted out of the problem
+    pub fix_mode: String,
+    #[structopt(long = "initial-state", value_name = "state", possible_values = &STATES)]
+    /// Synthetic state written as guidance_config, and the start of the da, tabu and sqa solvers
+    pub initial_state: Option<String>,
+    #[structopt(long = "form", possible_values = &FORMS)]
+    /// Form of the written request, qubo if missing
+    pub form: Option<String>,
+    #[structopt(long = "export", possible_values = &EXPORT_FORMATS)]
+    /// Also export the problem next to its request file
+    pub export: Option<String>,
+    #[structopt(long = "scale-bits", value_name = "bits")]
+    /// Round the coefficients to signed integers of 2 to 53 bits, the scale factor goes to the metadata
+    pub scale_bits: Option<u32>,
+    #[structopt(flatten)]
+    #[serde(flatten)]
+    pub da3: Da3Args,
+    #[structopt(flatten)]
+    #[serde(flatten)]
+    pub solver: SolverArgs,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct AnalyzeArgs {
+    /// Result file (.json or .sols)
+    pub file_path: String,
+    #[structopt(long = "problem", value_name = "file")]
+    /// Also recompute the energies of the solutions on this problem
+    pub problem_path: Option<String>,
+    #[structopt(flatten)]
+    pub workspace: WorkspaceArgs, // Gamma<G>/ of the analysis
+}
+
+#[derive(Debug, StructOpt)]
+pub struct GuidanceArgs {
+    #[structopt(required_unless = "state")]
+    /// Result file (.json or .sols) of the same lattice as the request
     pub guidance_path: Option<String>,
+    #[structopt(long = "state", possible_values = &STATES, conflicts_with = "guidance-path")]
+    /// Use a synthetic state of the lattice instead of a result
+    pub state: Option<String>,
+    #[structopt(
+        long = "select",
+        value_name = "strategy",
+        default_value = "random",
+        possible_values = &SELECTIONS
+    )]
+    /// Solution used: random, lowest energy, highest frequency, highest |psi|^2 (order), c6 closest to
+    /// --c6-target, or nearest to --reference
+    pub select: String,
+    #[structopt(long = "c6-target", value_name = "c6", allow_hyphen_values = true)]
+    /// Target c6 of --select c6
+    pub c6_target: Option<f64>,
+    #[structopt(long = "reference", value_name = "file")]
+    /// Reference state of --select nearest, a result file (its lowest energy) or {"index": bool, ...}
+    pub reference_path: Option<String>,
+    #[structopt(long = "seed")]
+    /// Seed of --select random and of the random spins of --state
+    pub seed: Option<u64>,
+    #[structopt(long = "transfer")]
+    /// Map a result of another Gamma, of a divisor of L (tiled) or of H = 1 (replicated) onto the request
+    pub transfer: bool,
+    #[structopt(flatten)]
+    pub workspace: WorkspaceArgs, // metadata.json and input.json
 }
 
-pub fn get_options() -> Options {
-    Options::from_args()
+#[derive(Debug, StructOpt)]
+pub struct SolveArgs {
+    /// Request, .qubo, .mtx, BQM, Gset or sparse file
+    pub input_path: String,
+    #[structopt(flatten)]
+    pub workspace: WorkspaceArgs, // Request converted from an external instance
+    #[structopt(long = "verify", value_name = "result")]
+    /// Check the energies of this result against the problem
+    pub verify_path: Option<String>,
+    #[structopt(long = "form", possible_values = &FORMS)]
+    /// Also write the problem in this form next to it
+    pub form: Option<String>,
+    #[structopt(long = "export", possible_values = &EXPORT_FORMATS)]
+    /// Also export the problem next to it
+    pub export: Option<String>,
+    #[structopt(flatten)]
+    pub da3: Da3Args,
+    #[structopt(flatten)]
+    pub solver: SolverArgs,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct SubmitArgs {
+    /// Request to post [default: <out-dir>/input.json]
+    pub request_path: Option<String>,
+    #[structopt(long = "job", value_name = "job_id")]
+    /// Get the result of this job instead of posting
+    pub job_id: Option<String>,
+    #[structopt(long = "wait")]
+    /// Wait for the result and save it
+    pub wait: bool,
+    #[structopt(long = "poll", value_name = "sec", default_value = "10")]
+    /// Seconds between two checks of the job status
+    pub poll: u64,
+    #[structopt(
+        long = "base-url",
+        value_name = "url",
+        env = "FUJITSU_BASE_URL",
+        default_value = "https://api.aispf.global.fujitsu.com"
+    )]
+    /// API root, ex: http://127.0.0.1:8080 for the mock server
+    pub base_url: String,
+    #[structopt(long = "api-key", env = "FUJITSU_API_KEY", hide_env_values = true)]
+    /// Value of the X-Api-Key header
+    pub api_key: String,
+    #[structopt(flatten)]
+    pub workspace: WorkspaceArgs, // input.json, metadata.json and Gamma<G>/ of the result
 }
 
-pub fn get_jxx(jxx: &mut Jxx, options: Options) {
-    const TEN_DECIMAL_PLACES: f64 = 100000.0 * 100000.0; // Ten decimal places
-    match &options.J {
-        Some(j) => jxx.j = *j,
-        None => (),
+#[derive(Debug, StructOpt, Serialize)]
+pub struct WorkspaceArgs {
+    #[structopt(
+        long = "out-dir",
+        visible_alias = "workspace",
+        value_name = "dir",
+        default_value = "./workspace"
+    )]
+    /// Workspace of the request, metadata, config, results and analyses
+    pub out_dir: String,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, StructOpt, Serialize)]
+pub struct Da3Args {
+    #[structopt(skip)]
+    #[serde(skip)]
+    pub config_da3: Option<(String, Value)>, // fujitsuDA3 table of the --config run file, with its path
+    #[structopt(short = "T", long = "time")]
+    /// Time limit of the request and of the tabu search, 1 to 3600 [default: 10]
+    pub T: Option<i32>,
+    #[structopt(long = "da3-params", value_name = "file")]
+    /// TOML or JSON file of fujitsuDA3 parameters, overridden by the flags
+    pub da3_params_path: Option<String>,
+    #[structopt(long = "num-run", value_name = "n")]
+    /// fujitsuDA3.num_run, 1 to 16 (num_run x num_group <= 16)
+    pub num_run: Option<i64>,
+    #[structopt(long = "num-group", value_name = "n")]
+    /// fujitsuDA3.num_group, 1 to 16
+    pub num_group: Option<i64>,
+    #[structopt(long = "num-output-solution", value_name = "n")]
+    /// fujitsuDA3.num_output_solution, 1 to 1024 [default: 1024]
+    pub num_output_solution: Option<i64>,
+    #[structopt(long = "gs-level", value_name = "level")]
+    /// fujitsuDA3.gs_level, 0 to 100 [default: 100]
+    pub gs_level: Option<i64>,
+    #[structopt(long = "gs-cutoff", value_name = "n")]
+    /// fujitsuDA3.gs_cutoff, 0 to 1000000 [default: 100000]
+    pub gs_cutoff: Option<i64>,
+    #[structopt(long = "bit-precision", value_name = "bits")]
+    /// fujitsuDA3.bit_precision, 1 to 64
+    pub bit_precision: Option<i64>,
+}
+
+#[derive(Debug, StructOpt, Serialize)]
+pub struct SolverArgs {
+    #[structopt(long = "solver", possible_values = &SOLVERS)]
+    /// Run a local solver on the problem (sqa needs the lattice of generate)
+    pub solver: Option<String>,
+    #[structopt(long = "sweeps", default_value = "1000")]
+    /// Number of Monte Carlo sweeps of the local solver
+    pub sweeps: i32,
+    #[structopt(long = "gamma-start")]
+    /// Gamma at the first sweep of the annealing, annealed down to --gamma
+    pub gamma_start: Option<f64>,
+    #[structopt(long = "temp-start", default_value = "1.0")]
+    /// Temperature at the first sweep of the annealing
+    pub temp_start: f64,
+    #[structopt(long = "temp-end", default_value = "1.0")]
+    /// Temperature at the last sweep of the annealing
+    pub temp_end: f64,
+    #[structopt(long = "wolff", default_value = "0")]
+    /// Wolff cluster updates (in-plane) per sweep
+    pub wolff: i32,
+    #[structopt(long = "worldline", default_value = "0")]
+    /// Imaginary-time line updates (along layer_up) per sweep
+    pub worldline: i32,
+    #[structopt(long = "max-variables", default_value = "30")]
+    /// Largest number of variables the exact solver enumerates
+    pub max_variables: usize,
+    #[structopt(long = "tenure")]
+    /// Iterations a flipped variable stays tabu [default: N / 8 + 1]
+    pub tenure: Option<usize>,
+    #[structopt(long = "iterations", default_value = "10000")]
+    /// Iterations of each tabu search restart
+    pub iterations: usize,
+    #[structopt(long = "restarts", default_value = "10")]
+    /// Restarts of the tabu search
+    pub restarts: usize,
+    #[structopt(long = "steps", default_value = "1000")]
+    /// Time steps of the simulated bifurcation
+    pub steps: usize,
+    #[structopt(long = "dt", default_value = "1.0")]
+    /// Time step of the simulated bifurcation
+    pub dt: f64,
+    #[structopt(long = "pump-exponent", default_value = "1.0")]
+    /// Pump schedule of the simulated bifurcation, a(t) = (t / T)^exponent
+    pub pump_exponent: f64,
+    #[structopt(long = "agents", default_value = "16")]
+    /// Agents of the simulated bifurcation run in parallel
+    pub agents: usize,
+    #[structopt(long = "cycles", default_value = "100")]
+    /// Annealing cycles of each replica group of the da emulator, ended early by the time limit
+    pub cycles: usize,
+    #[structopt(long = "packed")]
+    /// Save the result of the local solver as a packed .sols file instead of the DA JSON
+    pub packed: bool,
+    #[structopt(long = "seed")]
+    /// Seed of the local solver
+    pub seed: Option<u64>,
+}
+
+/*
+ * Without a subcommand, the arguments are those of `generate`:
+ *   fujitsu -L 18 -H 6 -G 0.5  ==  fujitsu generate -L 18 -H 6 -G 0.5
+ * The keys of the `generate --config` run file fill the flags which are not given.
+ */
+pub fn get_options() -> Result<Options> {
+    let mut args: Vec<String> = std::env::args().collect();
+    let explicit: bool = match args.get(1) {
+        Some(first) => {
+            SUBCOMMANDS.contains(&first.as_str())
+                || ["help", "-h", "--help", "-V", "--version"].contains(&first.as_str())
+        }
+        None => false,
+    };
+    if !explicit {
+        args.insert(1, "generate".to_string());
+    }
+
+    let matches = Options::clap().get_matches_from(&args);
+    let options: Options = Options::from_clap(&matches);
+    let config_path: String = match &options.cmd {
+        Command::Generate(GenerateArgs {
+            config_path: Some(config_path),
+            ..
+        }) => config_path.clone(),
+        _ => return check_inequalities(options),
+    };
+
+    let config: Value = read_run_file(&config_path)?;
+    let generate_matches = matches.subcommand_matches("generate").unwrap();
+    // Right after `generate`, the flags of the run file are parsed as if they were given first
+    args.splice(2..2, config_args(&config_path, &config, generate_matches)?);
+    let mut options: Options = Options::from_iter(&args);
+    if let Command::Generate(generate) = &mut options.cmd {
+        generate.da3.config_da3 = config_da3(&config).map(|table| (config_path, table));
     }
-    match &options.Gamma {
-        Some(gamma) => {
-            if *gamma == 0.0 {
-                jxx.jl = 0.0;
-            } else {
-                let jl: f64 = -(0.5) * gamma.tanh().ln();
-                jxx.jl = (jl * TEN_DECIMAL_PLACES).round() / TEN_DECIMAL_PLACES;
+    check_inequalities(options)
+}
+
+// The inequalities are only handled by the Digital Annealer API, a local solver cannot minimise them
+fn check_inequalities(options: Options) -> Result<Options> {
+    if let Command::Generate(generate) = &options.cmd {
+        let constrained: bool =
+            generate.magnetization.is_some() || generate.sub_magnetization.is_some();
+        if let Some(solver) = &generate.solver.solver {
+            if constrained && generate.magnetization_mode == "inequalities" {
+                return Err(Error::parameter(
+                    "--magnetization-mode",
+                    format!(
+                        "inequalities are only handled by the Digital Annealer API, not by --solver {} (use penalty)",
+                        solver
+                    ),
+                ));
             }
-            jxx.gamma = *gamma;
         }
-        None => (),
     }
-    match &options.L {
-        Some(l) => {
-            if (*l % 3 != 0) || (*l <= 0) {
-                panic!("L must be a multiple of 3 and greater than 0.");
-            }
-            jxx.l = *l
+    Ok(options)
+}
+
+pub fn get_jxx(jxx: &mut Jxx, options: &GenerateArgs) -> Result<()> {
+    if let Some(j) = &options.J {
+        jxx.j = *j;
+    }
+    if let Some(gamma) = &options.Gamma {
+        if *gamma == 0.0 {
+            jxx.jl = 0.0;
+        } else {
+            jxx.jl = layer_strength(*gamma); // Unrounded, --scale-bits rounds the coefficients
         }
-        None => (),
+        jxx.gamma = *gamma;
     }
-    match &options.H {
-        Some(h) => {
-            if *h <= 0 {
-                panic!("H must be greater than or equal to 0.");
-            }
-            jxx.h = *h
+    if let Some(layer_strength) = &options.layer_strength {
+        jxx.jl = *layer_strength;
+    }
+    if let Some(l) = &options.L {
+        if (*l % 3 != 0) || (*l <= 0) {
+            return Err(Error::parameter(
+                "-L",
+                format!("{} is not a positive multiple of 3", l),
+            ));
+        }
+        jxx.l = *l
+    }
+    if let Some(h) = &options.H {
+        if *h <= 0 {
+            return Err(Error::parameter("-H", format!("{} is not positive", h)));
         }
-        None => (),
+        jxx.h = *h
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate(args: &[&str]) -> Result<Options> {
+        check_inequalities(Options::from_iter(
+            ["fujitsu", "generate"].iter().chain(args),
+        ))
+    }
+
+    #[test]
+    fn local_solvers_refuse_the_inequalities() {
+        let inequalities = [
+            "--magnetization",
+            "1",
+            "--magnetization-mode",
+            "inequalities",
+        ];
+        assert!(generate(&inequalities).is_ok());
+        assert!(matches!(
+            generate(&[&inequalities[..], &["--solver", "tabu"]].concat()),
+            Err(Error::Parameter { .. })
+        ));
+        assert!(generate(&["--magnetization-mode", "inequalities", "--solver", "tabu"]).is_ok());
+        assert!(generate(&[
+            "--magnetization",
+            "1",
+            "--magnetization-mode",
+            "penalty",
+            "--solver",
+            "da"
+        ])
+        .is_ok());
+    }
+
+    #[test]
+    fn layer_strength_is_unrounded() {
+        let mut jxx = Jxx {
+            j: 1.0,
+            jl: 0.0,
+            l: 3,
+            h: 2,
+            gamma: 0.0,
+        };
+        get_jxx(
+            &mut jxx,
+            &GenerateArgs::from_iter(["generate", "-G", "0.3"]),
+        )
+        .unwrap();
+        assert_eq!(jxx.jl, layer_strength(0.3));
+        assert_ne!(jxx.jl, (jxx.jl * 1e10).round() / 1e10);
+        get_jxx(&mut jxx, &GenerateArgs::from_iter(["generate", "-G", "0"])).unwrap();
+        assert_eq!(jxx.jl, 0.0);
     }
 }
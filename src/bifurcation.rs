@@ -0,0 +1,211 @@
+// Purpose: Simulated bifurcation machine (ballistic and discrete) on the Ising form of the problem
+use crate::error::{Error, Result};
+use crate::ising::Ising;
+use crate::random::get_rng;
+use crate::solution::{qubo_solution, Solution};
+use rand::prelude::*;
+use rayon::prelude::*;
+use serde_json::{json, Value};
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Variant {
+    Ballistic, // bSB: the force uses the positions x
+    Discrete,  // dSB: the force uses sign(x)
+}
+
+#[derive(Debug)]
+pub struct SbParams {
+    pub variant: Variant,
+    pub steps: usize,       // Number of time steps
+    pub dt: f64,            // Time step
+    pub pump_exponent: f64, // Pump schedule a(t) = a0 (t / T)^exponent
+    pub agents: usize,      // Number of independent agents run in parallel
+    pub seed: Option<u64>,  // Seed of the random number generator (agent k uses seed + k)
+}
+
+impl SbParams {
+    // Check the parameters against the problem before solving it
+    pub fn check(&self, ising: &Ising) -> Result<()> {
+        if ising.len() < 2 {
+            return Err(Error::content(
+                "/binary_polynomial",
+                "needs at least 2 spins",
+            ));
+        }
+        if self.agents == 0 {
+            return Err(Error::parameter("--agents", "needs at least 1 agent"));
+        }
+        if self.steps == 0 {
+            return Err(Error::parameter("--steps", "needs at least 1 step"));
+        }
+        Ok(())
+    }
+}
+
+/*
+ * SB minimises -1/2 \sum J'_{i, j} x_i x_j - \sum h'_i x_i, so J' = -J and h' = -h of Ising.
+ * Symplectic Euler update of each step (a0 = 1):
+ *   y_i += dt * ( -(a0 - a(t)) x_i + c0 ( \sum_j J'_{i, j} f(x_j) + h'_i ) ),  f = x (bSB) or sign (dSB)
+ *   x_i += dt * a0 * y_i
+ *   |x_i| > 1 -> x_i = sign(x_i), y_i = 0 (inelastic walls)
+ * with c0 = 0.5 / (sigma_J sqrt(N)).
+ */
+fn run_agent(
+    ising: &Ising,
+    adjacency: &[Vec<(usize, f64)>],
+    params: &SbParams,
+    c0: f64,
+    seed: Option<u64>,
+) -> Vec<f64> {
+    const A0: f64 = 1.0;
+    let n: usize = ising.len();
+    let mut rng = get_rng(seed);
+    let mut x: Vec<f64> = (0..n).map(|_| rng.gen_range(-0.1..0.1)).collect();
+    let mut y: Vec<f64> = (0..n).map(|_| rng.gen_range(-0.1..0.1)).collect();
+    let mut force: Vec<f64> = vec![0.0; n];
+
+    for step in 0..params.steps {
+        let a: f64 = A0 * (step as f64 / params.steps as f64).powf(params.pump_exponent);
+
+        for i in 0..n {
+            let mut sum: f64 = -ising.h[i];
+            for (j, coupling) in &adjacency[i] {
+                let xj: f64 = match params.variant {
+                    Variant::Ballistic => x[*j],
+                    Variant::Discrete => x[*j].signum(),
+                };
+                sum -= coupling * xj;
+            }
+            force[i] = sum;
+        }
+
+        for i in 0..n {
+            y[i] += params.dt * (-(A0 - a) * x[i] + c0 * force[i]);
+            x[i] += params.dt * A0 * y[i];
+            if x[i].abs() > 1.0 {
+                x[i] = x[i].signum();
+                y[i] = 0.0;
+            }
+        }
+    }
+
+    x.iter()
+        .map(|v| if *v >= 0.0 { 1.0 } else { -1.0 })
+        .collect()
+}
+
+pub fn simulated_bifurcation(ising: &Ising, params: &SbParams) -> Value {
+    let n: usize = ising.len();
+    if n < 2 || params.agents == 0 || params.steps == 0 {
+        panic!("Simulated bifurcation needs at least 2 spins, 1 agent and 1 step.");
+    }
+
+    // sigma_J: root mean square of J' over every ordered pair
+    let sum_square: f64 = ising.j.iter().map(|(_, _, j)| 2.0 * j * j).sum();
+    let sigma: f64 = (sum_square / (n * (n - 1)) as f64).sqrt();
+    let c0: f64 = if sigma > 0.0 {
+        0.5 / (sigma * (n as f64).sqrt())
+    } else {
+        0.5 / (n as f64).sqrt()
+    };
+    let adjacency: Vec<Vec<(usize, f64)>> = ising.adjacency();
+
+    let start = Instant::now();
+    let spins: Vec<Vec<f64>> = (0..params.agents)
+        .into_par_iter()
+        .map(|agent| {
+            let seed: Option<u64> = params.seed.map(|s| s.wrapping_add(agent as u64));
+            run_agent(ising, &adjacency, params, c0, seed)
+        })
+        .collect();
+
+    let solutions: Vec<Solution> = spins
+        .iter()
+        .map(|s| Solution {
+            configuration: s.iter().map(|v| *v > 0.0).collect(),
+            energy: ising.energy(s),
+            frequency: 1,
+        })
+        .collect();
+
+    let best: f64 = solutions
+        .iter()
+        .map(|solution| solution.energy)
+        .fold(f64::INFINITY, f64::min);
+    println!("best energy: {}", best);
+
+    let progress: Vec<Value> = vec![json!({"energy": best, "time": start.elapsed().as_secs_f64()})];
+    qubo_solution(&ising.variables, solutions, progress, start.elapsed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solution_set::SolutionSet;
+
+    fn params(variant: Variant) -> SbParams {
+        SbParams {
+            variant,
+            steps: 1000,
+            dt: 0.5,
+            pump_exponent: 1.0,
+            agents: 8,
+            seed: Some(2),
+        }
+    }
+
+    // Ferromagnetic ring of n spins, J = -1: both aligned states have the energy -n
+    fn ring(n: usize) -> Ising {
+        Ising {
+            variables: (0..n as i32).collect(),
+            h: vec![0.0; n],
+            j: (0..n)
+                .map(|i| (i.min((i + 1) % n), i.max((i + 1) % n), -1.0))
+                .collect(),
+            offset: 0.0,
+        }
+    }
+
+    #[test]
+    fn both_variants_align_a_ferromagnetic_ring() {
+        for variant in [Variant::Ballistic, Variant::Discrete] {
+            let ising: Ising = ring(10);
+            let set: SolutionSet =
+                SolutionSet::from_value(&simulated_bifurcation(&ising, &params(variant))).unwrap();
+            assert_eq!(set.energies[0], -10.0);
+            assert_eq!(set.frequencies.iter().sum::<i32>(), 8);
+        }
+    }
+
+    #[test]
+    fn field_chooses_the_direction() {
+        let mut ising: Ising = ring(6);
+        ising.h = vec![0.5; 6]; // h s favours s = -1
+        ising.offset = 1.0;
+        let set: SolutionSet =
+            SolutionSet::from_value(&simulated_bifurcation(&ising, &params(Variant::Ballistic)))
+                .unwrap();
+        assert_eq!(set.configuration(0), vec![false; 6]);
+        assert_eq!(set.energies[0], -6.0 - 3.0 + 1.0);
+    }
+
+    #[test]
+    fn same_seed_same_result() {
+        let ising: Ising = ring(7);
+        let first: Value = simulated_bifurcation(&ising, &params(Variant::Discrete));
+        let second: Value = simulated_bifurcation(&ising, &params(Variant::Discrete));
+        assert_eq!(
+            first["qubo_solution"]["solutions"],
+            second["qubo_solution"]["solutions"]
+        );
+    }
+
+    #[test]
+    fn check_rejects_a_single_spin() {
+        assert!(params(Variant::Ballistic).check(&ring(1)).is_err());
+        let mut no_agent: SbParams = params(Variant::Ballistic);
+        no_agent.agents = 0;
+        assert!(no_agent.check(&ring(3)).is_err());
+    }
+}
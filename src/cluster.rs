@@ -0,0 +1,340 @@
+// Purpose: Cluster Monte Carlo updates on the layered lattice (Wolff and imaginary-time line updates)
+use crate::lattice::Lattice;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use serde_json::{json, Value};
+use std::fmt;
+
+/*
+ * Both moves build a cluster from one kind of bond and accept the flip of the whole
+ * cluster with the Metropolis rule on the energy of the other kind of bond.
+ * - Wolff: grows over the in-plane bonds of one layer, p_add = 1 - exp(-2|K| / T) for
+ *   satisfied bonds (K s_i s_j < 0), accepted on the change of -K' s_{i, n} s_{i, n+1}.
+ * - Worldline: grows along the layer_up chain of one site, p_add = 1 - exp(-2K' / T)
+ *   for parallel spins, accepted on the change of the in-plane energy. With K' < 0
+ *   (--layer-strength) no bond is added, and the change of -K' s_{i, n} s_{i, n+1} of the
+ *   single site is accepted with the in-plane one.
+ */
+
+#[derive(Debug, Default)]
+pub struct MoveStats {
+    pub attempted: u64,    // Number of proposed moves
+    pub accepted: u64,     // Number of accepted moves
+    pub cluster_size: u64, // Sum of the sizes of the proposed clusters
+}
+
+impl MoveStats {
+    pub fn acceptance(&self) -> f64 {
+        if self.attempted == 0 {
+            return 0.0;
+        }
+        self.accepted as f64 / self.attempted as f64
+    }
+    pub fn mean_cluster_size(&self) -> f64 {
+        if self.attempted == 0 {
+            return 0.0;
+        }
+        self.cluster_size as f64 / self.attempted as f64
+    }
+    pub fn to_value(&self) -> Value {
+        json!({
+            "attempted": self.attempted,
+            "accepted": self.accepted,
+            "acceptance": self.acceptance(),
+            "mean_cluster_size": self.mean_cluster_size(),
+        })
+    }
+}
+
+impl fmt::Display for MoveStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "attempted: {}, accepted: {}, acceptance: {:.4}, mean cluster size: {:.2}",
+            self.attempted,
+            self.accepted,
+            self.acceptance(),
+            self.mean_cluster_size()
+        )
+    }
+}
+
+// Reusable buffers of the cluster moves
+pub struct ClusterBuffer {
+    in_cluster: Vec<bool>,
+    members: Vec<usize>,
+}
+
+impl ClusterBuffer {
+    pub fn new(size: usize) -> ClusterBuffer {
+        ClusterBuffer {
+            in_cluster: vec![false; size],
+            members: Vec::new(),
+        }
+    }
+    fn push(&mut self, index: usize) {
+        self.in_cluster[index] = true;
+        self.members.push(index);
+    }
+    fn clear(&mut self) {
+        for index in self.members.drain(..) {
+            self.in_cluster[index] = false;
+        }
+    }
+}
+
+fn metropolis(delta_e: f64, temperature: f64, rng: &mut StdRng) -> bool {
+    delta_e <= 0.0 || rng.gen::<f64>() < (-delta_e / temperature).exp()
+}
+
+// One Wolff cluster move inside a randomly chosen layer, returns true if flipped
+pub fn wolff_update(
+    lattice: &Lattice,
+    spins: &mut [f64],
+    k_layer: f64,
+    temperature: f64,
+    rng: &mut StdRng,
+    buffer: &mut ClusterBuffer,
+    stats: &mut MoveStats,
+) -> bool {
+    let seed: usize = rng.gen_range(0..spins.len());
+    buffer.push(seed);
+
+    let mut cursor: usize = 0;
+    while cursor < buffer.members.len() {
+        let current: usize = buffer.members[cursor];
+        for (neighbor, k) in lattice.in_plane_neighbors(current as i32) {
+            if buffer.in_cluster[neighbor] || k * spins[current] * spins[neighbor] >= 0.0 {
+                continue;
+            }
+            if rng.gen::<f64>() < 1.0 - (-2.0 * k.abs() / temperature).exp() {
+                buffer.push(neighbor);
+            }
+        }
+        cursor += 1;
+    }
+
+    // The cluster stays in one layer, so every vertical bond of a member crosses the boundary
+    let delta_e: f64 = buffer
+        .members
+        .iter()
+        .map(|m| 2.0 * k_layer * spins[*m] * lattice.layer_field(spins, *m as i32))
+        .sum();
+
+    let accepted: bool = metropolis(delta_e, temperature, rng);
+    record(spins, buffer, stats, accepted)
+}
+
+// One imaginary-time line update along the layer_up chain of a random site
+pub fn worldline_update(
+    lattice: &Lattice,
+    spins: &mut [f64],
+    k_layer: f64,
+    temperature: f64,
+    rng: &mut StdRng,
+    buffer: &mut ClusterBuffer,
+    stats: &mut MoveStats,
+) -> bool {
+    let seed: usize = rng.gen_range(0..spins.len());
+    buffer.push(seed);
+
+    let p_add: f64 = 1.0 - (-2.0 * k_layer.max(0.0) / temperature).exp();
+    let mut cursor: usize = 0;
+    while cursor < buffer.members.len() {
+        let current: usize = buffer.members[cursor];
+        // With H = 2 both bonds lead to the same node and are tried independently
+        for neighbor in [
+            lattice.nodes[current].layer_up as usize,
+            lattice.get_layer_down(current as i32) as usize,
+        ] {
+            if buffer.in_cluster[neighbor] || spins[current] != spins[neighbor] {
+                continue;
+            }
+            if rng.gen::<f64>() < p_add {
+                buffer.push(neighbor);
+            }
+        }
+        cursor += 1;
+    }
+
+    // Members live in different layers, so no in-plane bond is inside the cluster.
+    // The vertical bonds are left to the acceptance when the cluster cannot grow along them
+    let k_boundary: f64 = k_layer.min(0.0);
+    let delta_e: f64 = buffer
+        .members
+        .iter()
+        .map(|m| {
+            -2.0 * spins[*m] * lattice.in_plane_field(spins, *m as i32)
+                + 2.0 * k_boundary * spins[*m] * lattice.layer_field(spins, *m as i32)
+        })
+        .sum();
+
+    let accepted: bool = metropolis(delta_e, temperature, rng);
+    record(spins, buffer, stats, accepted)
+}
+
+fn record(
+    spins: &mut [f64],
+    buffer: &mut ClusterBuffer,
+    stats: &mut MoveStats,
+    accepted: bool,
+) -> bool {
+    stats.attempted += 1;
+    stats.cluster_size += buffer.members.len() as u64;
+    if accepted {
+        stats.accepted += 1;
+        for m in &buffer.members {
+            spins[*m] = -spins[*m];
+        }
+    }
+    buffer.clear();
+    accepted
+}
+
+/*
+ * Integrated autocorrelation time of a series (in units of its samples)
+ * tau = 1/2 + \sum_{t = 1}^{W} rho(t), with the window W chosen as the first W >= 6 tau.
+ */
+pub fn autocorrelation_time(series: &[f64]) -> f64 {
+    let n: usize = series.len();
+    if n < 2 {
+        return 0.0;
+    }
+    let mean: f64 = series.iter().sum::<f64>() / n as f64;
+    let variance: f64 = series.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n as f64;
+    if variance == 0.0 {
+        return 0.0;
+    }
+
+    let mut tau: f64 = 0.5;
+    for t in 1..n {
+        let rho: f64 = (0..n - t)
+            .map(|i| (series[i] - mean) * (series[i + t] - mean))
+            .sum::<f64>()
+            / ((n - t) as f64 * variance);
+        tau += rho;
+        if t as f64 >= 6.0 * tau {
+            break;
+        }
+    }
+    tau
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::get_rng;
+    use crate::{create_vector, Jxx};
+
+    const K_LAYER: f64 = 0.5;
+
+    fn lattice() -> Lattice {
+        lattice_with(K_LAYER)
+    }
+
+    fn lattice_with(k_layer: f64) -> Lattice {
+        create_vector(&Jxx {
+            j: 1.0,
+            jl: k_layer,
+            l: 6,
+            h: 4,
+            gamma: 0.3,
+        })
+    }
+
+    // Near T = 0 a move is only accepted when it does not raise H_eff
+    fn check_never_raises_the_energy(
+        k_layer: f64,
+        update: fn(
+            &Lattice,
+            &mut [f64],
+            f64,
+            f64,
+            &mut StdRng,
+            &mut ClusterBuffer,
+            &mut MoveStats,
+        ) -> bool,
+    ) -> MoveStats {
+        let lattice: Lattice = lattice_with(k_layer);
+        let mut rng: StdRng = get_rng(Some(7));
+        let mut spins: Vec<f64> = (0..lattice.nodes.len())
+            .map(|_| if rng.gen::<bool>() { 1.0 } else { -1.0 })
+            .collect();
+        let mut buffer = ClusterBuffer::new(spins.len());
+        let mut stats = MoveStats::default();
+        for _ in 0..500 {
+            let before: f64 = lattice.hamiltonian_energy(&spins);
+            update(
+                &lattice,
+                &mut spins,
+                k_layer,
+                1e-6,
+                &mut rng,
+                &mut buffer,
+                &mut stats,
+            );
+            assert!(lattice.hamiltonian_energy(&spins) <= before + 1e-9);
+        }
+        assert!(buffer.members.is_empty() && !buffer.in_cluster.contains(&true));
+        stats
+    }
+
+    #[test]
+    fn wolff_never_raises_the_energy_near_zero_temperature() {
+        let stats: MoveStats = check_never_raises_the_energy(K_LAYER, wolff_update);
+        assert_eq!(stats.attempted, 500);
+        assert!(stats.accepted > 0);
+    }
+
+    #[test]
+    fn worldline_never_raises_the_energy_near_zero_temperature() {
+        let stats: MoveStats = check_never_raises_the_energy(K_LAYER, worldline_update);
+        assert!(stats.accepted > 0);
+    }
+
+    #[test]
+    fn negative_layer_strength_is_in_the_acceptance() {
+        for update in [wolff_update, worldline_update] {
+            let stats: MoveStats = check_never_raises_the_energy(-K_LAYER, update);
+            assert!(stats.accepted > 0);
+        }
+    }
+
+    #[test]
+    fn worldline_takes_the_whole_parallel_chain() {
+        let lattice: Lattice = lattice();
+        let mut spins: Vec<f64> = vec![1.0; lattice.nodes.len()];
+        let mut rng: StdRng = get_rng(Some(1));
+        let mut buffer = ClusterBuffer::new(spins.len());
+        let mut stats = MoveStats::default();
+        for _ in 0..20 {
+            worldline_update(
+                &lattice,
+                &mut spins,
+                K_LAYER,
+                1e-6,
+                &mut rng,
+                &mut buffer,
+                &mut stats,
+            );
+        }
+        assert_eq!(stats.mean_cluster_size(), lattice.h as f64);
+    }
+
+    #[test]
+    fn autocorrelation_time_of_constant_and_correlated_series() {
+        assert_eq!(autocorrelation_time(&[1.0]), 0.0);
+        assert_eq!(autocorrelation_time(&[2.0; 50]), 0.0);
+        // AR(1) series x_{i+1} = 0.9 x_i + noise: tau = (1 + 0.9) / (2 (1 - 0.9)) = 9.5
+        let mut rng: StdRng = get_rng(Some(3));
+        let mut x: f64 = 0.0;
+        let series: Vec<f64> = (0..20000)
+            .map(|_| {
+                x = 0.9 * x + rng.gen::<f64>() - 0.5;
+                x
+            })
+            .collect();
+        let tau: f64 = autocorrelation_time(&series);
+        assert!(tau > 7.0 && tau < 12.0, "tau: {}", tau);
+    }
+}
@@ -0,0 +1,263 @@
+// Purpose: fujitsuDA3 parameters of the request, from the flags and a TOML/JSON run file, validated against the API ranges
+use crate::args::Da3Args;
+use crate::error::{Error, Result};
+use crate::request::Request;
+use serde_json::{json, Map, Value};
+use std::fs;
+
+/*
+ * Integer parameters of the DA3 API: (name, minimum, maximum, value written when not set)
+ * The written defaults are the ones this program always sent, the others are left to the API.
+ * num_run x num_group must not exceed 16 (API defaults: num_run 16, num_group 1).
+ */
+const INTEGER_PARAMS: [(&str, i64, i64, Option<i64>); 14] = [
+    ("time_limit_sec", 1, 3600, Some(10)),
+    ("num_run", 1, 16, None),
+    ("num_group", 1, 16, None),
+    ("num_output_solution", 1, 1024, Some(1024)),
+    ("gs_level", 0, 100, Some(100)),
+    ("gs_cutoff", 0, 1000000, Some(100000)),
+    ("one_hot_level", 3, 100, None),
+    ("one_hot_cutoff", 0, 1000000, None),
+    ("internal_penalty", 0, 1, None),
+    ("penalty_auto_mode", 0, 10000, None),
+    ("penalty_coef", 1, i64::MAX, None),
+    ("penalty_inc_rate", 100, 200, None),
+    ("max_penalty_coef", 0, i64::MAX, None),
+    ("bit_precision", 1, 64, None), // Bits of the integer coefficients
+];
+const CONFIG_PARAMS: [&str; 2] = ["guidance_config", "fixed_config"]; // {"index": bool, ...}
+const FLOAT_PARAMS: [&str; 1] = ["target_energy"]; // Stop once this energy is reached
+
+pub struct Da3Params {
+    pub values: Map<String, Value>, // Resolved parameters, written to fujitsuDA3
+}
+
+// Read a run file, TOML (*.toml) or JSON, as a JSON value
+pub fn read_run_file(path: &str) -> Result<Value> {
+    let text: String = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+    if path.ends_with(".toml") {
+        toml::from_str::<Value>(&text).map_err(|e| Error::syntax(path, e))
+    } else {
+        serde_json::from_str(&text).map_err(|e| Error::syntax(path, e))
+    }
+}
+
+fn parameter_name(name: &str) -> String {
+    format!("fujitsuDA3.{}", name)
+}
+
+fn check_integer(name: &str, value: &Value, min: i64, max: i64) -> Result<()> {
+    match value.as_i64() {
+        Some(number) if number >= min && number <= max => Ok(()),
+        _ => Err(Error::parameter(
+            &parameter_name(name),
+            format!("{} is not an integer in [{}, {}]", value, min, max),
+        )),
+    }
+}
+
+fn check_config(name: &str, value: &Value) -> Result<()> {
+    let config = value
+        .as_object()
+        .ok_or_else(|| Error::parameter(&parameter_name(name), "not a map of index to bool"))?;
+    for (key, bit) in config {
+        if key.parse::<u32>().is_err() || !bit.is_boolean() {
+            return Err(Error::parameter(
+                &parameter_name(name),
+                format!("{:?}: {} is not an index to a bool", key, bit),
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Values written when not set
+fn defaults() -> Map<String, Value> {
+    let mut values: Map<String, Value> = Map::new();
+    for (name, _, _, default) in INTEGER_PARAMS {
+        if let Some(default) = default {
+            values.insert(name.to_string(), json!(default));
+        }
+    }
+    values
+}
+
+impl Da3Params {
+    /*
+     * Defaults < [fujitsuDA3] of --config < run file (`--da3-params`, keys at the top or under [fujitsuDA3]) < flags
+     * ex: run.toml
+     *   [fujitsuDA3]
+     *   time_limit_sec = 60
+     *   num_run = 8
+     *   num_group = 2
+     *   fixed_config = { "0" = true }
+     */
+    pub fn from_options(options: &Da3Args) -> Result<Da3Params> {
+        Da3Params::resolve(defaults(), options)
+    }
+
+    /*
+     * Parameters of an existing request (solve): defaults < its own fujitsuDA3 < run file < flags
+     * Only the parameters given explicitly replace the ones of the request, and the merged set is
+     * validated as a whole, so a flag conflicting with the request (ex: --num-run 16 with its
+     * num_group = 2) is refused.
+     */
+    pub fn for_request(options: &Da3Args, own: &Value) -> Result<Da3Params> {
+        let mut values: Map<String, Value> = defaults();
+        match own {
+            Value::Null => (),
+            Value::Object(own) => values.extend(own.clone()),
+            _ => return Err(Error::content("/fujitsuDA3", "not a map of parameters")),
+        }
+        Da3Params::resolve(values, options)
+    }
+
+    // `values` under the run files and the flags of `options`
+    fn resolve(mut values: Map<String, Value>, options: &Da3Args) -> Result<Da3Params> {
+        let mut tables: Vec<(String, Value)> = Vec::new();
+        if let Some(table) = &options.config_da3 {
+            tables.push(table.clone());
+        }
+        if let Some(path) = &options.da3_params_path {
+            let file: Value = read_run_file(path)?;
+            tables.push((
+                path.clone(),
+                file.get("fujitsuDA3").unwrap_or(&file).clone(),
+            ));
+        }
+        for (path, table) in tables {
+            match table.as_object() {
+                Some(table) => values.extend(table.clone()),
+                None => return Err(Error::syntax(&path, "not a table of fujitsuDA3 parameters")),
+            }
+        }
+
+        let flags: [(&str, Option<i64>); 7] = [
+            ("time_limit_sec", options.T.map(i64::from)),
+            ("num_run", options.num_run),
+            ("num_group", options.num_group),
+            ("num_output_solution", options.num_output_solution),
+            ("gs_level", options.gs_level),
+            ("gs_cutoff", options.gs_cutoff),
+            ("bit_precision", options.bit_precision),
+        ];
+        for (name, flag) in flags {
+            if let Some(value) = flag {
+                values.insert(name.to_string(), json!(value));
+            }
+        }
+
+        let params = Da3Params { values };
+        params.validate()?;
+        Ok(params)
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (name, value) in &self.values {
+            let name: &str = name.as_str();
+            if let Some((_, min, max, _)) = INTEGER_PARAMS.iter().find(|param| param.0 == name) {
+                check_integer(name, value, *min, *max)?;
+            } else if CONFIG_PARAMS.contains(&name) {
+                check_config(name, value)?;
+            } else if FLOAT_PARAMS.contains(&name) {
+                if !value.is_number() {
+                    return Err(Error::parameter(
+                        &parameter_name(name),
+                        format!("{} is not a number", value),
+                    ));
+                }
+            } else {
+                return Err(Error::parameter(&parameter_name(name), "unknown parameter"));
+            }
+        }
+        let get = |name: &str, default: i64| -> i64 {
+            self.values
+                .get(name)
+                .and_then(Value::as_i64)
+                .unwrap_or(default)
+        };
+        let (num_run, num_group): (i64, i64) = (get("num_run", 16), get("num_group", 1));
+        if num_run * num_group > 16 {
+            return Err(Error::parameter(
+                "fujitsuDA3.num_run x num_group",
+                format!("{} x {} exceeds 16", num_run, num_group),
+            ));
+        }
+        Ok(())
+    }
+
+    // Time limit actually sent to the API
+    pub fn time_limit_sec(&self) -> i64 {
+        self.values["time_limit_sec"].as_i64().unwrap()
+    }
+
+    // Write the parameters to the fujitsuDA3 of the request, over the ones it has
+    pub fn apply(&self, request: &mut Request) {
+        request.da3.extend(self.values.clone());
+    }
+
+    // The parameters for the metadata, each configuration by its number of variables
+    pub fn to_metadata(&self) -> Value {
+        let summary: Map<String, Value> = self
+            .values
+            .iter()
+            .map(|(name, value)| match value.as_object() {
+                Some(config) => (name.clone(), json!(format!("{} variables", config.len()))),
+                None => (name.clone(), value.clone()),
+            })
+            .collect();
+        Value::Object(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use structopt::StructOpt;
+
+    fn for_request(flags: &[&str], own: Value) -> Result<Da3Params> {
+        let options = Da3Args::from_iter(["solve"].iter().chain(flags));
+        Da3Params::for_request(&options, &own)
+    }
+
+    #[test]
+    fn request_values_are_kept() {
+        let params = for_request(
+            &[],
+            json!({"time_limit_sec": 3, "num_run": 8, "num_group": 2}),
+        )
+        .unwrap();
+        assert_eq!(params.time_limit_sec(), 3);
+        assert_eq!(params.values["num_group"], json!(2));
+        assert_eq!(params.values["gs_level"], json!(100)); // Missing, the default
+    }
+
+    #[test]
+    fn explicit_flags_replace_them() {
+        let params = for_request(
+            &["-T", "30", "--gs-level", "5"],
+            json!({"time_limit_sec": 3}),
+        )
+        .unwrap();
+        assert_eq!(params.time_limit_sec(), 30);
+        assert_eq!(params.values["gs_level"], json!(5));
+        assert_eq!(for_request(&[], Value::Null).unwrap().time_limit_sec(), 10);
+    }
+
+    #[test]
+    fn conflicts_are_refused() {
+        assert!(matches!(
+            for_request(&["--num-run", "16"], json!({"num_run": 8, "num_group": 2})),
+            Err(Error::Parameter { .. })
+        ));
+        assert!(matches!(
+            for_request(&[], json!({"time_limit_sec": 0})),
+            Err(Error::Parameter { .. })
+        ));
+        assert!(matches!(
+            for_request(&[], json!([1])),
+            Err(Error::Content { .. })
+        ));
+    }
+}
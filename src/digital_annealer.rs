@@ -0,0 +1,443 @@
+// Purpose: Local emulator of the Digital Annealer (parallel-trial Monte Carlo with offset escape)
+use crate::error::{Error, Result};
+use crate::polynomial::Polynomial;
+use crate::random::get_rng;
+use crate::solution::{qubo_solution, Solution};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+const FINAL_TEMPERATURE_RATIO: f64 = 1e-3; // T at the end of a cycle, relative to the initial T
+const OFFSET_RATE: f64 = 0.1; // Offset added when no flip is accepted, relative to T
+const DEFAULT_CYCLES: usize = 100; // Annealing cycles of each group, without --cycles
+
+#[derive(Debug)]
+pub struct DaParams {
+    pub time_limit_sec: i32,                       // Time limit of the whole run
+    pub gs_level: i32,                             // Initial T, in % of the largest |Delta E|
+    pub gs_cutoff: i32,                            // Iterations without a new best ending a cycle
+    pub num_output_solution: usize, // Number of (lowest, distinct) solutions returned
+    pub num_group: usize,           // Replica groups, each sharing its best state
+    pub num_run: usize,             // Replicas of each group
+    pub cycles: usize,              // Annealing cycles of each group, ended early by time_limit_sec
+    pub seed: Option<u64>,          // Seed of the random number generator
+    pub guidance_config: Option<Vec<(i32, bool)>>, // Initial configuration
+    pub fixed_config: Option<Vec<(i32, bool)>>, // Variables which never flip
+}
+
+// `{"index": bool, ...}` of the fujitsuDA3 parameter `name`, if any
+fn config_from_value(da3: &Value, name: &str) -> Result<Option<Vec<(i32, bool)>>> {
+    let config = match da3[name].as_object() {
+        Some(config) => config,
+        None => return Ok(None),
+    };
+    config
+        .iter()
+        .map(|(key, value)| match (key.parse(), value.as_bool()) {
+            (Ok(index), Some(value)) => Ok((index, value)),
+            _ => Err(Error::content(
+                format!("/fujitsuDA3/{}/{}", name, key),
+                "not a bool of a variable index",
+            )),
+        })
+        .collect::<Result<Vec<(i32, bool)>>>()
+        .map(Some)
+}
+
+// Value of a configuration at each position of the polynomial, None where it sets nothing
+fn positions(config: &[(i32, bool)], polynomial: &Polynomial) -> Vec<Option<bool>> {
+    let mut values: Vec<Option<bool>> = vec![None; polynomial.len()];
+    for (index, value) in config {
+        match polynomial.variables.binary_search(index) {
+            Ok(position) => values[position] = Some(*value),
+            Err(_) => panic!("variable {} is not in the binary_polynomial.", index),
+        }
+    }
+    values
+}
+
+impl DaParams {
+    // Read the `fujitsuDA3` object of a request, missing keys fall back to the DA3 defaults
+    pub fn from_value(fujitsu: &Value, seed: Option<u64>) -> Result<DaParams> {
+        let da3 = &fujitsu["fujitsuDA3"];
+        let get = |key: &str, default: i64| -> i64 { da3[key].as_i64().unwrap_or(default) };
+
+        let guidance_config: Option<Vec<(i32, bool)>> = config_from_value(da3, "guidance_config")?;
+        let fixed_config: Option<Vec<(i32, bool)>> = config_from_value(da3, "fixed_config")?;
+
+        Ok(DaParams {
+            time_limit_sec: get("time_limit_sec", 10) as i32,
+            gs_level: get("gs_level", 5) as i32,
+            gs_cutoff: get("gs_cutoff", 8000) as i32,
+            num_output_solution: get("num_output_solution", 5) as usize,
+            num_group: get("num_group", 1) as usize,
+            num_run: get("num_run", 16) as usize,
+            cycles: DEFAULT_CYCLES,
+            seed,
+            guidance_config,
+            fixed_config,
+        })
+    }
+
+    // Check the parameters against the polynomial before solving it
+    pub fn check(&self, polynomial: &Polynomial) -> Result<()> {
+        if polynomial.len() == 0 {
+            return Err(Error::content("/binary_polynomial", "no variable"));
+        }
+        if self.num_group == 0 || self.num_run == 0 {
+            return Err(Error::parameter(
+                "fujitsuDA3.num_run x num_group",
+                "needs at least 1 group and 1 run",
+            ));
+        }
+        if self.cycles == 0 {
+            return Err(Error::parameter("--cycles", "needs at least 1 cycle"));
+        }
+        let configs = [
+            ("guidance_config", &self.guidance_config),
+            ("fixed_config", &self.fixed_config),
+        ];
+        for (name, config) in configs {
+            for (index, _) in config.iter().flatten() {
+                if polynomial.variables.binary_search(index).is_err() {
+                    return Err(Error::content(
+                        format!("/fujitsuDA3/{}/{}", name, index),
+                        "not a variable of the binary_polynomial",
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Value of the guidance at each position of the polynomial, None where it sets nothing
+    pub fn initial_positions(&self, polynomial: &Polynomial) -> Option<Vec<Option<bool>>> {
+        self.guidance_config
+            .as_ref()
+            .map(|config| positions(config, polynomial))
+    }
+}
+
+struct Replica {
+    x: Vec<bool>,
+    energy: f64,
+    delta: Vec<f64>,  // Energy change of flipping each variable
+    fixed: Vec<bool>, // Variables of the fixed_config, never flipped
+    rng: StdRng,
+}
+
+impl Replica {
+    fn new(polynomial: &Polynomial, x: Vec<bool>, fixed: &[bool], rng: StdRng) -> Replica {
+        let energy: f64 = polynomial.energy(&x);
+        let delta: Vec<f64> = (0..x.len()).map(|i| polynomial.flip_delta(&x, i)).collect();
+        Replica {
+            x,
+            energy,
+            delta,
+            fixed: fixed.to_vec(),
+            rng,
+        }
+    }
+
+    fn flip(&mut self, polynomial: &Polynomial, neighbors: &[Vec<usize>], var: usize) {
+        self.energy += self.delta[var];
+        self.x[var] = !self.x[var];
+        self.delta[var] = -self.delta[var];
+        for other in &neighbors[var] {
+            self.delta[*other] = polynomial.flip_delta(&self.x, *other);
+        }
+    }
+}
+
+/*
+ * One annealing cycle of a replica: every iteration evaluates every single flip (the parallel
+ * trial), accepts each with min(1, exp(-(Delta E - E_off) / T)) and flips one of the accepted
+ * at random. When none is accepted E_off grows by OFFSET_RATE * T (escape from local minima), and is reset
+ * after a flip. T decays geometrically from t0 to t0 * FINAL_TEMPERATURE_RATIO; the cycle ends
+ * early after gs_cutoff iterations without a new best, or at the deadline if there is one.
+ * Returns the best configuration.
+ */
+fn anneal_cycle(
+    polynomial: &Polynomial,
+    neighbors: &[Vec<usize>],
+    replica: &mut Replica,
+    t0: f64,
+    iterations: usize,
+    gs_cutoff: usize,
+    deadline: Option<Instant>,
+) -> (f64, Vec<bool>) {
+    let decay: f64 = FINAL_TEMPERATURE_RATIO.powf(1.0 / iterations as f64);
+    let (mut best, mut best_x): (f64, Vec<bool>) = (replica.energy, replica.x.clone());
+    let (mut temperature, mut offset): (f64, f64) = (t0, 0.0);
+    let mut since_best: usize = 0;
+    let mut accepted: Vec<usize> = Vec::new();
+
+    for iteration in 0..iterations {
+        let timed_out = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        if since_best >= gs_cutoff || (iteration % 256 == 0 && timed_out()) {
+            break;
+        }
+
+        accepted.clear();
+        for (i, delta) in replica.delta.iter().enumerate() {
+            if replica.fixed[i] {
+                continue;
+            }
+            let d: f64 = delta - offset;
+            if d <= 0.0 || replica.rng.gen::<f64>() < (-d / temperature).exp() {
+                accepted.push(i);
+            }
+        }
+
+        if accepted.is_empty() {
+            offset += OFFSET_RATE * temperature;
+        } else {
+            let var: usize = accepted[replica.rng.gen_range(0..accepted.len())];
+            replica.flip(polynomial, neighbors, var);
+            offset = 0.0;
+        }
+
+        if replica.energy < best {
+            best = replica.energy;
+            best_x.clone_from(&replica.x);
+            since_best = 0;
+        } else {
+            since_best += 1;
+        }
+        temperature *= decay;
+    }
+
+    (polynomial.energy(&best_x), best_x)
+}
+
+// Add a configuration to the `limit` lowest distinct ones, sorted by energy
+fn keep_best(best: &mut Vec<Solution>, energy: f64, x: Vec<bool>, limit: usize) {
+    if let Some(solution) = best.iter_mut().find(|solution| solution.configuration == x) {
+        solution.frequency += 1;
+        return;
+    }
+    if best.len() >= limit
+        && best
+            .last()
+            .is_some_and(|last| energy.total_cmp(&last.energy).is_ge())
+    {
+        return;
+    }
+    let position: usize =
+        best.partition_point(|solution| solution.energy.total_cmp(&energy).is_le());
+    best.insert(
+        position,
+        Solution {
+            configuration: x,
+            energy,
+            frequency: 1,
+        },
+    );
+    best.truncate(limit);
+}
+
+/*
+ * Up to `cycles` annealing cycles of every group, stopped by time_limit_sec after the first one
+ * (a zero time limit still runs one cycle). Only the num_output_solution lowest distinct
+ * configurations are kept between the cycles.
+ */
+pub fn digital_annealer(polynomial: &Polynomial, params: &DaParams) -> Value {
+    let n: usize = polynomial.len();
+    if n == 0 || params.num_group == 0 || params.num_run == 0 || params.cycles == 0 {
+        panic!(
+            "The Digital Annealer emulator needs at least 1 variable, 1 group, 1 run and 1 cycle."
+        );
+    }
+    let neighbors: Vec<Vec<usize>> = polynomial.neighbors();
+    let iterations: usize = (10 * n).max(1000);
+    let gs_cutoff: usize = params.gs_cutoff.max(1) as usize;
+
+    // Initial configuration: the fixed values, then the guidance, random for the variables neither sets
+    let mut rng = get_rng(params.seed);
+    let guidance: Option<Vec<Option<bool>>> = params.initial_positions(polynomial);
+    let fixed_values: Vec<Option<bool>> = match &params.fixed_config {
+        Some(config) => positions(config, polynomial),
+        None => vec![None; n],
+    };
+    let fixed: Vec<bool> = fixed_values.iter().map(Option::is_some).collect();
+    let initial_state = |rng: &mut StdRng| -> Vec<bool> {
+        (0..n)
+            .map(|i| match (fixed_values[i], &guidance) {
+                (Some(value), _) => value,
+                (None, Some(initial)) => initial[i].unwrap_or_else(|| rng.gen()),
+                (None, None) => rng.gen(),
+            })
+            .collect()
+    };
+
+    let start = Instant::now();
+    let deadline: Instant = start + Duration::from_secs(params.time_limit_sec.max(0) as u64);
+
+    let mut groups: Vec<Vec<Replica>> = (0..params.num_group)
+        .map(|_| {
+            (0..params.num_run)
+                .map(|_| {
+                    let replica_rng = StdRng::seed_from_u64(rng.gen());
+                    Replica::new(polynomial, initial_state(&mut rng), &fixed, replica_rng)
+                })
+                .collect()
+        })
+        .collect();
+
+    // Initial temperature from the largest single-flip |Delta E| of the first replica
+    let max_delta: f64 = groups[0][0]
+        .delta
+        .iter()
+        .fold(0.0_f64, |m, d| m.max(d.abs()))
+        .max(f64::MIN_POSITIVE);
+    let t0: f64 = max_delta * params.gs_level.clamp(1, 100) as f64 / 100.0;
+
+    let limit: usize = params.num_output_solution.max(1);
+    let mut found: Vec<Solution> = Vec::new();
+    let mut progress: Vec<Value> = Vec::new();
+    let mut overall_best: f64 = f64::INFINITY;
+
+    for cycle in 0..params.cycles {
+        if cycle > 0 && Instant::now() >= deadline {
+            println!("Time limit reached after {} cycles", cycle);
+            break;
+        }
+        // The first cycle always runs to its end
+        let cycle_deadline: Option<Instant> = (cycle > 0).then_some(deadline);
+        for group in groups.iter_mut() {
+            // Replicas of a group anneal in parallel
+            let results: Vec<(f64, Vec<bool>)> = group
+                .par_iter_mut()
+                .map(|replica| {
+                    anneal_cycle(
+                        polynomial,
+                        &neighbors,
+                        replica,
+                        t0,
+                        iterations,
+                        gs_cutoff,
+                        cycle_deadline,
+                    )
+                })
+                .collect();
+
+            // The group restarts every replica from its best state
+            let (group_best, group_best_x): &(f64, Vec<bool>) =
+                results.iter().min_by(|a, b| a.0.total_cmp(&b.0)).unwrap();
+            for replica in group.iter_mut() {
+                let replica_rng = StdRng::seed_from_u64(replica.rng.gen());
+                *replica = Replica::new(polynomial, group_best_x.clone(), &fixed, replica_rng);
+            }
+
+            if *group_best < overall_best {
+                overall_best = *group_best;
+                progress
+                    .push(json!({"energy": overall_best, "time": start.elapsed().as_secs_f64()}));
+            }
+            for (energy, x) in results {
+                keep_best(&mut found, energy, x, limit);
+            }
+        }
+    }
+
+    println!("best energy: {}", overall_best);
+    qubo_solution(&polynomial.variables, found, progress, start.elapsed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polynomial::tests::{configurations, random_polynomial};
+    use crate::solution_set::SolutionSet;
+
+    fn params(time_limit_sec: i32, cycles: usize) -> DaParams {
+        DaParams {
+            time_limit_sec,
+            gs_level: 50,
+            gs_cutoff: 8000,
+            num_output_solution: 4,
+            num_group: 2,
+            num_run: 4,
+            cycles,
+            seed: Some(9),
+            guidance_config: None,
+            fixed_config: None,
+        }
+    }
+
+    fn lowest(polynomial: &Polynomial, fixed: &[(usize, bool)]) -> f64 {
+        configurations(polynomial.len())
+            .filter(|x| fixed.iter().all(|(p, value)| x[*p] == *value))
+            .map(|x| polynomial.energy(&x))
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    #[test]
+    fn keep_best_keeps_the_lowest_distinct_configurations() {
+        let mut best: Vec<Solution> = Vec::new();
+        for (energy, x) in [
+            (3.0, 3),
+            (1.0, 1),
+            (2.0, 2),
+            (1.0, 1),
+            (0.5, 5),
+            (f64::NAN, 6),
+        ] {
+            keep_best(
+                &mut best,
+                energy,
+                vec![x == 1, x == 2, x == 3, x == 5, x == 6],
+                3,
+            );
+        }
+        let energies: Vec<f64> = best.iter().map(|solution| solution.energy).collect();
+        assert_eq!(energies, vec![0.5, 1.0, 2.0]); // A NaN energy sorts last
+        let frequency: i32 = best
+            .iter()
+            .find(|solution| solution.energy == 1.0)
+            .unwrap()
+            .frequency;
+        assert_eq!(frequency, 2);
+    }
+
+    #[test]
+    fn zero_time_limit_runs_one_cycle() {
+        let polynomial: Polynomial = random_polynomial(10, 1);
+        let set: SolutionSet =
+            SolutionSet::from_value(&digital_annealer(&polynomial, &params(0, 100))).unwrap();
+        assert!(set.len() > 0 && set.len() <= 4);
+        assert!((set.energies[0] - lowest(&polynomial, &[])).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stops_after_the_cycle_budget() {
+        let polynomial: Polynomial = random_polynomial(10, 2);
+        let start = Instant::now();
+        let set: SolutionSet =
+            SolutionSet::from_value(&digital_annealer(&polynomial, &params(3600, 3))).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(60));
+        // Sorted, distinct and with the energy of their configuration
+        for k in 0..set.len() {
+            assert!((polynomial.energy(&set.configuration(k)) - set.energies[k]).abs() < 1e-9);
+            assert!(k == 0 || set.energies[k - 1] <= set.energies[k]);
+            assert!((0..k).all(|other| set.configuration(other) != set.configuration(k)));
+        }
+    }
+
+    #[test]
+    fn fixed_variables_never_flip() {
+        let polynomial: Polynomial = random_polynomial(9, 3);
+        let mut params: DaParams = params(0, 2);
+        params.fixed_config = Some(vec![(0, true), (4, false)]);
+        let set: SolutionSet =
+            SolutionSet::from_value(&digital_annealer(&polynomial, &params)).unwrap();
+        for k in 0..set.len() {
+            let x: Vec<bool> = set.configuration(k);
+            assert!(x[0] && !x[4]);
+        }
+        let expected: f64 = lowest(&polynomial, &[(0, true), (4, false)]);
+        assert!((set.energies[0] - expected).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,131 @@
+// Purpose: Error type of the crate, with the context of each failure and the exit code of each kind
+use std::fmt;
+
+/*
+ * Exit codes:
+ *   1 - energies of a result do not match its problem (`solve --verify`, `solve --solver`)
+ *   2 - invalid parameter value (flag, fujitsuDA3 parameter)
+ *   3 - file or directory not readable / writable
+ *   4 - invalid file content (syntax, or the value at a JSON pointer)
+ *   5 - API request failed
+ * Panics (exit code 101) are left for the internal invariants only.
+ */
+#[derive(Debug)]
+pub enum Error {
+    Mismatch(String), // Verification failure
+    Parameter {
+        name: String, // Flag or run file key
+        message: String,
+    },
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    Content {
+        path: String,    // File, empty while unknown (filled by `in_file`)
+        pointer: String, // JSON pointer of the value, or "line N" of a text file
+        message: String,
+    },
+    Api {
+        url: String,
+        message: String,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn parameter(name: &str, message: impl Into<String>) -> Error {
+        Error::Parameter {
+            name: name.to_string(),
+            message: message.into(),
+        }
+    }
+
+    pub fn io(path: &str, source: std::io::Error) -> Error {
+        Error::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    // Invalid value at `pointer` (ex: "/binary_polynomial/terms/3/c") of a file known later
+    pub fn content(pointer: impl Into<String>, message: impl Into<String>) -> Error {
+        Error::Content {
+            path: String::new(),
+            pointer: pointer.into(),
+            message: message.into(),
+        }
+    }
+
+    // Syntax error of a whole file
+    pub fn syntax(path: &str, message: impl fmt::Display) -> Error {
+        Error::Content {
+            path: path.to_string(),
+            pointer: String::new(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn api(url: &str, message: impl Into<String>) -> Error {
+        Error::Api {
+            url: url.to_string(),
+            message: message.into(),
+        }
+    }
+
+    // Name the file of a content error raised while decoding a value
+    pub fn in_file(self, file_path: &str) -> Error {
+        match self {
+            Error::Content {
+                path,
+                pointer,
+                message,
+            } if path.is_empty() => Error::Content {
+                path: file_path.to_string(),
+                pointer,
+                message,
+            },
+            other => other,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Mismatch(_) => 1,
+            Error::Parameter { .. } => 2,
+            Error::Io { .. } => 3,
+            Error::Content { .. } => 4,
+            Error::Api { .. } => 5,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Mismatch(message) => write!(f, "{}", message),
+            Error::Parameter { name, message } => write!(f, "invalid {}: {}", name, message),
+            Error::Io { path, source } => write!(f, "{}: {}", path, source),
+            Error::Content {
+                path,
+                pointer,
+                message,
+            } => match (path.is_empty(), pointer.is_empty()) {
+                (true, _) => write!(f, "{}: {}", pointer, message),
+                (false, true) => write!(f, "{}: {}", path, message),
+                (false, false) => write!(f, "{} ({}): {}", path, pointer, message),
+            },
+            Error::Api { url, message } => write!(f, "{}: {}", url, message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,160 @@
+// Purpose: Exact ground states of small problems by Gray-code enumeration of all 2^N configurations
+use crate::polynomial::Polynomial;
+use crate::solution::{qubo_solution, Solution};
+use rayon::prelude::*;
+use serde_json::{json, Value};
+use std::time::Instant;
+
+const PREFIX_BITS: usize = 8; // The highest bits fixed per parallel chunk (2^8 chunks)
+const TOLERANCE: f64 = 1e-6; // Energies closer than this (relative) are degenerate
+
+fn is_degenerate(a: f64, b: f64) -> bool {
+    (a - b).abs() <= TOLERANCE * a.abs().max(b.abs()).max(1.0)
+}
+
+fn to_bits(state: u64, n: usize) -> Vec<bool> {
+    (0..n).map(|p| (state >> p) & 1 == 1).collect()
+}
+
+/*
+ * Enumerate the chunk whose highest `n - m` bits are `prefix`.
+ * The lowest m bits follow the Gray code, so every step flips a single variable and the
+ * energy is updated with Polynomial::flip_delta.
+ */
+fn enumerate_chunk(polynomial: &Polynomial, prefix: u64, m: usize) -> (f64, Vec<u64>) {
+    let n: usize = polynomial.len();
+    let mut state: u64 = prefix << m;
+    let mut x: Vec<bool> = to_bits(state, n);
+    let mut energy: f64 = polynomial.energy(&x);
+
+    let (mut best, mut ground_states): (f64, Vec<u64>) = (energy, vec![state]);
+    for i in 1..(1_u64 << m) {
+        let bit: usize = i.trailing_zeros() as usize;
+        energy += polynomial.flip_delta(&x, bit);
+        x[bit] = !x[bit];
+        state ^= 1 << bit;
+
+        if is_degenerate(energy, best) {
+            ground_states.push(state);
+            best = best.min(energy);
+        } else if energy < best {
+            best = energy;
+            ground_states.clear();
+            ground_states.push(state);
+        }
+    }
+    (best, ground_states)
+}
+
+// Ground-state energy, degeneracy and every ground state, in the DA3 result format
+pub fn exact_ground_states(polynomial: &Polynomial, max_variables: usize) -> Value {
+    let n: usize = polynomial.len();
+    if n > max_variables || n > 63 {
+        panic!(
+            "Exact enumeration refuses {} variables (limit: {}).",
+            n, max_variables
+        );
+    }
+    println!("Enumerating 2^{} configurations...", n);
+
+    let start = Instant::now();
+    let prefix_bits: usize = PREFIX_BITS.min(n);
+    let m: usize = n - prefix_bits;
+
+    let chunks: Vec<(f64, Vec<u64>)> = (0..(1_u64 << prefix_bits))
+        .into_par_iter()
+        .map(|prefix| enumerate_chunk(polynomial, prefix, m))
+        .collect();
+
+    // Recompute the candidates exactly, the incremental energies carry rounding errors
+    let lowest: f64 = chunks
+        .iter()
+        .map(|(best, _)| *best)
+        .fold(f64::INFINITY, f64::min);
+    let candidates: Vec<(f64, u64)> = chunks
+        .into_iter()
+        .filter(|(best, _)| is_degenerate(*best, lowest))
+        .flat_map(|(_, states)| states)
+        .map(|state| (polynomial.energy(&to_bits(state, n)), state))
+        .collect();
+    let ground_energy: f64 = candidates
+        .iter()
+        .map(|(energy, _)| *energy)
+        .fold(f64::INFINITY, f64::min);
+    let solutions: Vec<Solution> = candidates
+        .into_iter()
+        .filter(|(energy, _)| is_degenerate(*energy, ground_energy))
+        .map(|(energy, state)| Solution {
+            configuration: to_bits(state, n),
+            energy,
+            frequency: 1,
+        })
+        .collect();
+
+    let degeneracy: usize = solutions.len();
+    println!("ground state energy: {}", ground_energy);
+    println!("degeneracy: {}", degeneracy);
+
+    let mut result: Value = qubo_solution(
+        &polynomial.variables,
+        solutions,
+        Vec::new(),
+        start.elapsed(),
+    );
+    result["ground_state"] = json!({
+        "energy": ground_energy,
+        "degeneracy": degeneracy,
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polynomial::tests::{configurations, random_polynomial};
+
+    // Lowest energy by a plain loop over every configuration
+    fn brute_force(polynomial: &Polynomial) -> (f64, usize) {
+        let energies: Vec<f64> = configurations(polynomial.len())
+            .map(|x| polynomial.energy(&x))
+            .collect();
+        let lowest: f64 = energies.iter().copied().fold(f64::INFINITY, f64::min);
+        let degeneracy: usize = energies
+            .iter()
+            .filter(|e| is_degenerate(**e, lowest))
+            .count();
+        (lowest, degeneracy)
+    }
+
+    #[test]
+    fn matches_brute_force_with_and_without_chunks() {
+        // 5 variables: fewer than PREFIX_BITS, 12 variables: 2^8 chunks of 2^4
+        for (n, seed) in [(5, 1), (12, 2), (12, 3)] {
+            let polynomial: Polynomial = random_polynomial(n, seed);
+            let result: Value = exact_ground_states(&polynomial, 20);
+            let (lowest, degeneracy) = brute_force(&polynomial);
+            let energy: f64 = result["ground_state"]["energy"].as_f64().unwrap();
+            assert!((energy - lowest).abs() < 1e-9);
+            assert_eq!(result["ground_state"]["degeneracy"], json!(degeneracy));
+        }
+    }
+
+    #[test]
+    fn finds_every_ground_state_of_a_frustrated_triangle() {
+        // J (s_0 s_1 + s_1 s_2 + s_0 s_2) with s = 2x - 1, J = 1: 6 states of energy -1
+        let mut raw_terms: Vec<(f64, Vec<i32>)> = vec![(3.0, Vec::new())];
+        for (a, b) in [(0, 1), (1, 2), (0, 2)] {
+            raw_terms.push((4.0, vec![a, b]));
+            raw_terms.push((-2.0, vec![a]));
+            raw_terms.push((-2.0, vec![b]));
+        }
+        let result: Value = exact_ground_states(&Polynomial::new(raw_terms), 20);
+        assert_eq!(
+            result["ground_state"],
+            json!({"energy": -1.0, "degeneracy": 6})
+        );
+        let solutions = result["qubo_solution"]["solutions"].as_array().unwrap();
+        assert_eq!(solutions.len(), 6);
+        assert!(solutions.iter().all(|s| s["energy"] == json!(-1.0)));
+    }
+}
@@ -0,0 +1,188 @@
+// Purpose: Export the binary polynomial of a request to standard QUBO file formats
+use crate::error::{Error, Result};
+use crate::polynomial::Polynomial;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/*
+ * Every format stores E(x) = \sum_{i <= j} Q_{i, j} x_i x_j + offset with the variable indices of
+ * the request (the diagonal holds the linear terms, x_i x_i = x_i):
+ *   qubo   - qbsolv: "p qubo 0 maxNodes nNodes nCouplers", then "i i Q_ii" and "i j Q_ij" lines
+ *   sparse - plain "i j value" lines
+ *   mtx    - Matrix Market coordinate real general, 1-based, upper triangle
+ *   bqm    - D-Wave style JSON {"linear", "quadratic", "offset", "vartype": "BINARY"}
+ * The offset goes to a comment line in the text formats.
+ */
+pub const FORMATS: [(&str, &str); 4] = [
+    ("qubo", "qubo"),
+    ("sparse", "txt"),
+    ("mtx", "mtx"),
+    ("bqm", "bqm.json"),
+];
+
+type Matrix = BTreeMap<(i32, i32), f64>; // Q_{i, j} by variable indices, i <= j
+
+// Upper-triangular Q (by variable index) and the offset
+pub fn get_qubo_matrix(polynomial: &Polynomial) -> Result<(Matrix, f64)> {
+    let mut matrix: Matrix = BTreeMap::new();
+    for (c, positions) in &polynomial.terms {
+        let key: (i32, i32) = match positions.as_slice() {
+            [a] => (polynomial.variables[*a], polynomial.variables[*a]),
+            [a, b] => (polynomial.variables[*a], polynomial.variables[*b]),
+            _ => {
+                return Err(Error::content(
+                    "/binary_polynomial",
+                    format!(
+                        "only quadratic polynomials can be exported (term of order {})",
+                        positions.len()
+                    ),
+                ))
+            }
+        };
+        *matrix.entry(key).or_insert(0.0) += c;
+    }
+    Ok((matrix, polynomial.constant))
+}
+
+fn to_qbsolv(matrix: &BTreeMap<(i32, i32), f64>, offset: f64, variables: &[i32]) -> String {
+    let max_nodes: i32 = variables.last().map_or(0, |v| v + 1);
+    let nodes: Vec<(&(i32, i32), &f64)> = matrix.iter().filter(|((i, j), _)| i == j).collect();
+    let couplers: Vec<(&(i32, i32), &f64)> = matrix.iter().filter(|((i, j), _)| i != j).collect();
+
+    let mut lines: Vec<String> = vec![
+        "c Exported from the binary_polynomial of a Fujitsu DA3 request".to_string(),
+        format!("c offset {}", offset),
+        format!("p qubo 0 {} {} {}", max_nodes, nodes.len(), couplers.len()),
+    ];
+    for ((i, j), value) in nodes.into_iter().chain(couplers) {
+        lines.push(format!("{} {} {}", i, j, value));
+    }
+    lines.join("\n") + "\n"
+}
+
+fn to_sparse(matrix: &BTreeMap<(i32, i32), f64>, offset: f64) -> String {
+    let mut lines: Vec<String> = vec![format!("# offset {}", offset)];
+    for ((i, j), value) in matrix {
+        lines.push(format!("{} {} {}", i, j, value));
+    }
+    lines.join("\n") + "\n"
+}
+
+fn to_matrix_market(matrix: &BTreeMap<(i32, i32), f64>, offset: f64, variables: &[i32]) -> String {
+    let size: i32 = variables.last().map_or(0, |v| v + 1);
+    let mut lines: Vec<String> = vec![
+        "%%MatrixMarket matrix coordinate real general".to_string(),
+        format!("% offset {}", offset),
+        format!("{} {} {}", size, size, matrix.len()),
+    ];
+    for ((i, j), value) in matrix {
+        lines.push(format!("{} {} {}", i + 1, j + 1, value));
+    }
+    lines.join("\n") + "\n"
+}
+
+fn to_bqm(matrix: &BTreeMap<(i32, i32), f64>, offset: f64) -> Value {
+    let mut linear = Map::new();
+    let mut quadratic: Vec<Value> = Vec::new();
+    for ((i, j), value) in matrix {
+        if i == j {
+            linear.insert(i.to_string(), json!(value));
+        } else {
+            quadratic.push(json!([i, j, value]));
+        }
+    }
+    json!({
+        "linear": linear,
+        "quadratic": quadratic,
+        "offset": offset,
+        "vartype": "BINARY",
+    })
+}
+
+// Write the problem in `format`, ex: ./workspace/input.json -> ./workspace/input.qubo
+pub fn export(polynomial: &Polynomial, format: &str, input_path: &str) -> Result<String> {
+    let extension: &str = match FORMATS.iter().find(|(name, _)| *name == format) {
+        Some((_, extension)) => extension,
+        None => panic!("Unknown export format: {} (qubo, sparse, mtx, bqm)", format), // Checked by the flag
+    };
+    let path = Path::new(input_path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let target_file: String = path
+        .with_file_name(format!("{}.{}", stem, extension))
+        .to_string_lossy()
+        .to_string();
+
+    let (matrix, offset) = get_qubo_matrix(polynomial).map_err(|e| e.in_file(input_path))?;
+    println!("Exporting {} entries to {}...", matrix.len(), target_file);
+    match format {
+        "bqm" => crate::write_json(&target_file, &to_bqm(&matrix, offset))?,
+        _ => {
+            let text: String = match format {
+                "qubo" => to_qbsolv(&matrix, offset, &polynomial.variables),
+                "sparse" => to_sparse(&matrix, offset),
+                _ => to_matrix_market(&matrix, offset, &polynomial.variables),
+            };
+            std::fs::write(&target_file, text).map_err(|e| Error::io(&target_file, e))?;
+        }
+    }
+    Ok(target_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2 x_3 - x_3 x_7 + 0.5 x_7 + 1.5
+    fn polynomial() -> Polynomial {
+        Polynomial::new(vec![
+            (2.0, vec![3]),
+            (-1.0, vec![7, 3]),
+            (0.5, vec![7]),
+            (1.5, Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn matrix_is_upper_triangular_by_variable_index() {
+        let (matrix, offset) = get_qubo_matrix(&polynomial()).unwrap();
+        let entries: Vec<((i32, i32), f64)> = matrix.into_iter().collect();
+        assert_eq!(entries, vec![((3, 3), 2.0), ((3, 7), -1.0), ((7, 7), 0.5)]);
+        assert_eq!(offset, 1.5);
+    }
+
+    #[test]
+    fn cubic_terms_are_refused() {
+        let cubic = Polynomial::new(vec![(1.0, vec![0, 1, 2])]);
+        assert!(get_qubo_matrix(&cubic).is_err());
+    }
+
+    #[test]
+    fn text_formats() {
+        let polynomial: Polynomial = polynomial();
+        let (matrix, offset) = get_qubo_matrix(&polynomial).unwrap();
+        assert_eq!(
+            to_qbsolv(&matrix, offset, &polynomial.variables),
+            "c Exported from the binary_polynomial of a Fujitsu DA3 request\n\
+             c offset 1.5\np qubo 0 8 2 1\n3 3 2\n7 7 0.5\n3 7 -1\n"
+        );
+        assert_eq!(
+            to_sparse(&matrix, offset),
+            "# offset 1.5\n3 3 2\n3 7 -1\n7 7 0.5\n"
+        );
+        assert_eq!(
+            to_matrix_market(&matrix, offset, &polynomial.variables),
+            "%%MatrixMarket matrix coordinate real general\n% offset 1.5\n8 8 3\n\
+             4 4 2\n4 8 -1\n8 8 0.5\n"
+        );
+        assert_eq!(
+            to_bqm(&matrix, offset),
+            json!({
+                "linear": {"3": 2.0, "7": 0.5},
+                "quadratic": [[3, 7, -1.0]],
+                "offset": 1.5,
+                "vartype": "BINARY",
+            })
+        );
+    }
+}
@@ -0,0 +1,345 @@
+// Purpose: Fixed spins of the lattice (--fix), sent as fixed_config or substituted out of the problem
+use crate::args::GenerateArgs;
+use crate::error::{Error, Result};
+use crate::ising::Ising;
+use crate::lattice::Lattice;
+use crate::request::Request;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/*
+ * --fix red=up,row0=down,40=up: sites = spin, on every layer but a single index
+ *   red, green, blue   a sub-lattice
+ *   row<i>, col<j>     a row / column of the L x L plane (ex: the boundary rows row0 and row<L-1>)
+ *   <index>            one site, by its 3D index
+ * Modes:
+ *   fixed-config  - fixed_config of fujitsuDA3, honoured by the DA API and the da emulator
+ *   substitute    - s_i = +-1 put into H_eff by the builder, the fixed spins leave the problem:
+ *                     h_i s_i -> offset, J_{i, j} s_i s_j -> h_j (or offset when both are fixed)
+ *                   so the energy of a reduced configuration is the one of the full configuration.
+ *                   Its results get the fixed spins back (generate, submit, analyze).
+ */
+pub struct Fixing {
+    pub mode: String,               // fixed-config or substitute
+    pub spins: BTreeMap<i32, bool>, // Value of each fixed index (up: x = 1)
+}
+
+const SUB_LATTICES: [&str; 3] = ["red", "green", "blue"]; // Order of Lattice::site_table
+
+// Indices of one `sites` of --fix
+fn sites(lattice: &Lattice, name: &str) -> Result<Vec<i32>> {
+    let site_table: Vec<(usize, usize)> = lattice.site_table();
+    let size: i32 = site_table.len() as i32;
+    let parse = |number: &str, limit: i32| -> Result<i32> {
+        match number.parse::<i32>() {
+            Ok(number) if (0..limit).contains(&number) => Ok(number),
+            _ => Err(Error::parameter(
+                "--fix",
+                format!("{:?} is not a site name or an index below {}", name, limit),
+            )),
+        }
+    };
+    if let Some(sub_lattice) = SUB_LATTICES.iter().position(|s| *s == name) {
+        return Ok((0..size)
+            .filter(|index| site_table[*index as usize].1 == sub_lattice)
+            .collect());
+    }
+    let indices: Vec<i32> = if let Some(row) = name.strip_prefix("row") {
+        let row: i32 = parse(row, lattice.l)?;
+        (0..size)
+            .filter(|index| lattice.get_hij(*index).1 == row)
+            .collect()
+    } else if let Some(column) = name.strip_prefix("col") {
+        let column: i32 = parse(column, lattice.l)?;
+        (0..size)
+            .filter(|index| lattice.get_hij(*index).2 == column)
+            .collect()
+    } else {
+        vec![parse(name, size)?]
+    };
+    Ok(indices)
+}
+
+impl Fixing {
+    // Fixed spins of `--fix`, None without it
+    pub fn from_options(options: &GenerateArgs, lattice: &Lattice) -> Result<Option<Fixing>> {
+        let text: &str = match &options.fix {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+        let mut spins: BTreeMap<i32, bool> = BTreeMap::new();
+        for item in text.split(',') {
+            let (name, spin): (&str, bool) = match item.trim().split_once('=') {
+                Some((name, "up")) => (name, true),
+                Some((name, "down")) => (name, false),
+                _ => {
+                    return Err(Error::parameter(
+                        "--fix",
+                        format!("{:?} is not <sites>=up or <sites>=down", item),
+                    ))
+                }
+            };
+            for index in sites(lattice, name)? {
+                if spins.insert(index, spin) == Some(!spin) {
+                    return Err(Error::parameter(
+                        "--fix",
+                        format!("site {} is fixed both up and down", index),
+                    ));
+                }
+            }
+        }
+
+        let mode: String = options.fix_mode.clone(); // One of the possible values of the flag
+        println!("Fixed spins: {}, mode: {}", spins.len(), mode);
+        Ok(Some(Fixing { mode, spins }))
+    }
+
+    pub fn substitutes(&self) -> bool {
+        self.mode == "substitute"
+    }
+
+    // Mode and fixed spins, for the metadata (read back by `from_metadata`)
+    pub fn to_value(&self) -> Value {
+        json!({"mode": self.mode, "config": self.config_value()})
+    }
+
+    // The "Fixed" object of a metadata.json, if any
+    pub fn from_metadata(meta: &Value) -> Option<Fixing> {
+        let fixed = meta.get("Fixed")?;
+        let spins: BTreeMap<i32, bool> = fixed["config"]
+            .as_object()?
+            .iter()
+            .filter_map(|(key, value)| Some((key.parse().ok()?, value.as_bool()?)))
+            .collect();
+        Some(Fixing {
+            mode: fixed["mode"].as_str()?.to_string(),
+            spins,
+        })
+    }
+
+    // `{"index": bool, ...}` of the fixed spins
+    fn config_value(&self) -> Value {
+        let config: Map<String, Value> = self
+            .spins
+            .iter()
+            .map(|(index, spin)| (index.to_string(), json!(spin)))
+            .collect();
+        Value::Object(config)
+    }
+
+    // Substitute the fixed spins into the spin form of H_eff (substitute mode only)
+    pub fn add_to_ising(&self, ising: &mut Ising) {
+        if !self.substitutes() {
+            return;
+        }
+        // New position of each kept spin, the value of each fixed one
+        let fixed: Vec<Option<f64>> = ising
+            .variables
+            .iter()
+            .map(|index| self.spins.get(index).map(|up| if *up { 1.0 } else { -1.0 }))
+            .collect();
+        let mut positions: Vec<usize> = vec![0; fixed.len()];
+        let mut variables: Vec<i32> = Vec::new();
+        let mut h: Vec<f64> = Vec::new();
+        let mut offset: f64 = ising.offset;
+        for (a, index) in ising.variables.iter().enumerate() {
+            match fixed[a] {
+                Some(s) => offset += ising.h[a] * s,
+                None => {
+                    positions[a] = variables.len();
+                    variables.push(*index);
+                    h.push(ising.h[a]);
+                }
+            }
+        }
+        let mut j: Vec<(usize, usize, f64)> = Vec::new();
+        for (a, b, value) in &ising.j {
+            match (fixed[*a], fixed[*b]) {
+                (Some(sa), Some(sb)) => offset += value * sa * sb,
+                (Some(sa), None) => h[positions[*b]] += value * sa,
+                (None, Some(sb)) => h[positions[*a]] += value * sb,
+                (None, None) => j.push((positions[*a], positions[*b], *value)),
+            }
+        }
+        *ising = Ising {
+            variables,
+            h,
+            j,
+            offset,
+        };
+    }
+
+    // Write the fixed spins to fixed_config (fixed-config mode only)
+    pub fn add_to_request(&self, request: &mut Request) {
+        if !self.substitutes() {
+            request
+                .da3
+                .insert("fixed_config".to_string(), self.config_value());
+        }
+    }
+
+    // Put the fixed spins back into a configuration indexed by the variable index (substitute mode only)
+    pub fn fill(&self, configuration: &mut Vec<Option<bool>>) {
+        if !self.substitutes() {
+            return;
+        }
+        if let Some((last, _)) = self.spins.last_key_value() {
+            if configuration.len() <= *last as usize {
+                configuration.resize(*last as usize + 1, None);
+            }
+        }
+        for (index, spin) in &self.spins {
+            configuration[*index as usize] = Some(*spin);
+        }
+    }
+
+    // Drop the fixed spins from a `{"index": bool, ...}` of the full lattice (substitute mode only)
+    pub fn reduce(&self, config: &mut Value) {
+        if let (true, Some(config)) = (self.substitutes(), config.as_object_mut()) {
+            config.retain(|key, _| {
+                key.parse::<i32>()
+                    .map_or(true, |index| !self.spins.contains_key(&index))
+            });
+        }
+    }
+
+    // Put the fixed spins back into every solution of a result (substitute mode only)
+    pub fn expand_result(&self, result: &mut Value) {
+        if !self.substitutes() {
+            return;
+        }
+        let solutions = match result["qubo_solution"]["solutions"].as_array_mut() {
+            Some(solutions) => solutions,
+            None => return,
+        };
+        for solution in solutions {
+            if let Some(configuration) = solution["configuration"].as_object_mut() {
+                for (index, spin) in &self.spins {
+                    configuration.insert(index.to_string(), json!(spin));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hamiltonian::ising_eff;
+    use crate::polynomial::tests::configurations;
+    use crate::sqa::layer_strength;
+    use crate::{create_vector, Jxx};
+    use structopt::StructOpt;
+
+    // 3 x 3 x 2 lattice, 18 spins
+    fn lattice() -> (Jxx, Lattice) {
+        let jxx = Jxx {
+            j: 1.0,
+            jl: layer_strength(0.5),
+            l: 3,
+            h: 2,
+            gamma: 0.5,
+        };
+        let lattice: Lattice = create_vector(&jxx);
+        (jxx, lattice)
+    }
+
+    fn fixing(fix: &str, mode: &str) -> Result<Option<Fixing>> {
+        let options = GenerateArgs::from_iter(["generate", "--fix", fix, "--fix-mode", mode]);
+        Fixing::from_options(&options, &lattice().1)
+    }
+
+    #[test]
+    fn site_names() {
+        let red: Fixing = fixing("red=up", "substitute").unwrap().unwrap();
+        assert_eq!(red.spins.len(), 6); // 3 sites a layer
+        let row: Fixing = fixing("row0=down,col2=down", "substitute")
+            .unwrap()
+            .unwrap();
+        assert_eq!(row.spins.len(), 10); // 6 + 6 - the 2 corners
+        assert!(row.spins.values().all(|spin| !spin));
+        assert!(row.spins.contains_key(&2) && row.spins.contains_key(&17)); // (0, 2) and (1, 2, 2)
+        let one: Fixing = fixing("17=up", "substitute").unwrap().unwrap();
+        assert_eq!(one.spins.keys().collect::<Vec<_>>(), vec![&17]);
+        for fix in ["row3=up", "18=up", "red=left", "purple=up", "red=up,0=down"] {
+            assert!(
+                matches!(fixing(fix, "substitute"), Err(Error::Parameter { .. })),
+                "{}",
+                fix
+            );
+        }
+    }
+
+    #[test]
+    fn substitution_keeps_the_energy() {
+        let (jxx, lattice) = lattice();
+        let full: Ising = ising_eff(&jxx, &lattice, false);
+        let fixing: Fixing = fixing("red=up,17=down", "substitute").unwrap().unwrap();
+        let mut reduced: Ising = ising_eff(&jxx, &lattice, false);
+        fixing.add_to_ising(&mut reduced);
+        assert_eq!(reduced.variables.len(), 18 - fixing.spins.len());
+        assert!(reduced
+            .variables
+            .iter()
+            .all(|i| !fixing.spins.contains_key(i)));
+
+        for x in configurations(reduced.variables.len()) {
+            let s: Vec<f64> = x.iter().map(|v| if *v { 1.0 } else { -1.0 }).collect();
+            let mut configuration: Vec<Option<bool>> = vec![None; 18];
+            for (index, value) in reduced.variables.iter().zip(&x) {
+                configuration[*index as usize] = Some(*value);
+            }
+            fixing.fill(&mut configuration);
+            let full_s: Vec<f64> = configuration
+                .iter()
+                .map(|v| if v.unwrap() { 1.0 } else { -1.0 })
+                .collect();
+            assert!((reduced.energy(&s) - full.energy(&full_s)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn results_get_the_fixed_spins_back() {
+        let fixing: Fixing = fixing("blue=down", "substitute").unwrap().unwrap();
+        let full: Value = Value::Object(
+            (0..18)
+                .map(|index| (index.to_string(), json!(index % 2 == 0)))
+                .collect(),
+        );
+        let mut reduced: Value = full.clone();
+        fixing.reduce(&mut reduced);
+        assert_eq!(reduced.as_object().unwrap().len(), 12);
+
+        let mut result: Value =
+            json!({"qubo_solution": {"solutions": [{"configuration": reduced, "energy": 0.0}]}});
+        fixing.expand_result(&mut result);
+        let expanded = &result["qubo_solution"]["solutions"][0]["configuration"];
+        for (index, spin) in &fixing.spins {
+            assert_eq!(expanded[index.to_string()], json!(spin));
+        }
+        assert_eq!(expanded.as_object().unwrap().len(), 18);
+    }
+
+    #[test]
+    fn fixed_config_mode_leaves_the_problem() {
+        let (jxx, lattice) = lattice();
+        let fixing: Fixing = fixing("green=up", "fixed-config").unwrap().unwrap();
+        let mut ising: Ising = ising_eff(&jxx, &lattice, false);
+        fixing.add_to_ising(&mut ising);
+        assert_eq!(ising.variables.len(), 18);
+
+        let mut request = Request::default();
+        fixing.add_to_request(&mut request);
+        assert_eq!(request.da3["fixed_config"], fixing.config_value());
+        let mut configuration: Vec<Option<bool>> = vec![None; 3];
+        fixing.fill(&mut configuration);
+        assert_eq!(configuration, vec![None; 3]);
+
+        let meta: Value = json!({"Fixed": fixing.to_value()});
+        let read: Fixing = Fixing::from_metadata(&meta).unwrap();
+        assert_eq!(
+            (read.mode, read.spins),
+            (fixing.mode.clone(), fixing.spins.clone())
+        );
+    }
+}
@@ -1,11 +1,16 @@
+use crate::args::WorkspaceArgs;
+use crate::error::{Error, Result};
+use crate::fixing::Fixing;
+use crate::import::import;
+use crate::ising::Ising;
+use crate::lattice::Lattice;
+use crate::polynomial::Polynomial;
+use crate::result_reader::{for_each_solution, result_metadata, SolutionRecord};
 use num::complex::Complex;
+use rayon::prelude::*;
 use serde_json::Value;
-use std::error::Error;
 use std::f64::consts::{E, PI};
 use std::fmt;
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
 
 /*
 * Config file structure
@@ -71,57 +76,188 @@ impl ComplexColorP {
     }
 }
 
-pub fn analysis(file_path: String) {
-    let (gamma, strength, length, height) = get_data(file_path.clone()); // Get data from file path
-    let num_length: i32 = length.parse().unwrap(); // Convert length to i32
-    let num_height: i32 = height.parse().unwrap(); // Convert height to i32
-
-    println!("Loading...");
-    let lattice: Value = read_payload_from_file(file_path.clone()).unwrap(); // Get json file data
-    println!("Calculating...");
-
-    let configs = lattice["qubo_solution"]["solutions"].as_array().unwrap();
-
-    println!("configs length: {}", configs.len());
-
-    let mut analysis_data: Vec<String> = Vec::new();
-    let mut skip_count: usize = 0;
-
-    for (config_index, config) in configs.iter().enumerate() {
-        let detail = config.as_object().unwrap();
-        let energy = detail["energy"].as_f64().unwrap();
+// Energy of solution `k` in the QUBO (x) and Ising (s = 2x - 1) conventions
+fn energy_both(
+    polynomial: &Polynomial,
+    ising: &Ising,
+    configuration: &[Option<bool>],
+    k: usize,
+) -> Result<(f64, f64)> {
+    let mut x: Vec<bool> = Vec::with_capacity(polynomial.len());
+    for index in &polynomial.variables {
+        match configuration.get(*index as usize).copied().flatten() {
+            Some(value) => x.push(value),
+            None => {
+                return Err(Error::content(
+                    format!("/qubo_solution/solutions/{}/configuration", k),
+                    format!("variable {} of the problem is missing", index),
+                ))
+            }
+        }
+    }
+    let s: Vec<f64> = x.iter().map(|v| if *v { 1.0 } else { -1.0 }).collect();
+    Ok((polynomial.energy(&x), ising.energy(&s)))
+}
 
-        // list_c6_orderp: Vec<(f64, f64, i32)> = (c6, order_p, layer)
-        let list_c6_orderp: Vec<(f64, f64, i32)> =
-            calc_c6_order_p(&detail["configuration"], num_length, num_height);
+const CHUNK_SIZE: usize = 256; // Solutions kept in memory and analysed in parallel at a time
 
-        // println!("list_c6_orderp length: {}", list_c6_orderp.len());
+// Lines of the analysis file, in the order of the solutions
+struct Rows {
+    data: Vec<String>,
+    skip_count: usize,
+    max_difference: f64, // max |energy - qubo energy| when the problem is given
+    scale: f64,          // Scale of the request (--scale-bits), the energies are divided by it
+}
 
-        for (c6, order_p, layer) in list_c6_orderp {
-            if (c6, order_p) == (0.0, 0.0) {
-                skip_count += 1;
-                continue;
-            } else {
-                analysis_data.push(format!(
-                    "{}\t{}\t{}\t{}\t{}",
-                    c6, order_p, config_index, layer, energy
-                ));
-            }
+impl Rows {
+    fn analyse(
+        &mut self,
+        chunk: &[(usize, SolutionRecord)],
+        site_table: &[(usize, usize)],
+        height: i32,
+        problem: &Option<(Polynomial, Ising)>,
+    ) -> Result<()> {
+        // (lines, skipped layers, |energy - qubo energy|) of each solution
+        let results: Vec<(Vec<String>, usize, f64)> = chunk
+            .par_iter()
+            .map(|(config_index, detail)| {
+                // The problem is the request that was solved, scaled as the energy of the result
+                let energy: f64 = detail.energy / self.scale;
+                let (energies, difference): (String, f64) = match problem {
+                    Some((polynomial, ising)) => {
+                        let (qubo_energy, ising_energy) =
+                            energy_both(polynomial, ising, &detail.configuration, *config_index)?;
+                        let qubo_energy: f64 = qubo_energy / self.scale;
+                        (
+                            format!("\t{}\t{}", qubo_energy, ising_energy / self.scale),
+                            (qubo_energy - energy).abs(),
+                        )
+                    }
+                    None => (String::new(), 0.0),
+                };
+
+                // list_c6_orderp: Vec<(f64, f64, i32)> = (c6, order_p, layer)
+                let list_c6_orderp: Vec<(f64, f64, i32)> =
+                    calc_c6_order_p(&detail.configuration, site_table, height);
+
+                let mut lines: Vec<String> = Vec::new();
+                let mut skip_count: usize = 0;
+                for (c6, order_p, layer) in list_c6_orderp {
+                    if (c6, order_p) == (0.0, 0.0) {
+                        skip_count += 1;
+                        continue;
+                    } else {
+                        lines.push(format!(
+                            "{}\t{}\t{}\t{}\t{}{}",
+                            c6, order_p, config_index, layer, energy, energies
+                        ));
+                    }
+                }
+                Ok((lines, skip_count, difference))
+            })
+            .collect::<Result<_>>()?;
+
+        for (lines, skip_count, difference) in results {
+            self.data.extend(lines);
+            self.skip_count += skip_count;
+            self.max_difference = self.max_difference.max(difference);
         }
+        Ok(())
+    }
+}
 
-        // break; // tmp break in first config
+pub fn analysis(
+    file_path: String,
+    problem_path: Option<String>,
+    workspace: &WorkspaceArgs,
+) -> Result<()> {
+    let (gamma, strength, length, height) = get_data(file_path.clone())?; // Get data from file path
+    let num_length: i32 = length.parse().map_err(|_| name_error(&file_path))?; // Convert length to i32
+    let num_height: i32 = height.parse().map_err(|_| name_error(&file_path))?; // Convert height to i32
+    let num_gamma: f64 = gamma.parse().map_err(|_| name_error(&file_path))?; // Convert gamma to f64
+    let num_strength: f64 = strength.parse().map_err(|_| name_error(&file_path))?; // Convert strength to f64
+
+    // With the problem, the energies are recomputed in both conventions
+    let problem: Option<(Polynomial, Ising)> = match problem_path {
+        Some(problem_path) => {
+            let polynomial: Polynomial = Polynomial::from_value(&import(&problem_path)?.0)
+                .map_err(|e| e.in_file(&problem_path))?;
+            let ising: Ising =
+                Ising::from_polynomial(&polynomial).map_err(|e| e.in_file(&problem_path))?;
+            Some((polynomial, ising))
+        }
+        None => None,
+    };
+
+    // Spins substituted out of the problem of the run of the result, put back before the analysis,
+    // and the scale of its coefficients, the energies are given in the units of the unscaled problem
+    let run: Option<Value> = result_run_metadata(
+        &file_path,
+        workspace,
+        (num_gamma, num_strength, num_length, num_height),
+    )?;
+    let fixing: Option<Fixing> = run
+        .as_ref()
+        .and_then(Fixing::from_metadata)
+        .filter(|fixing| fixing.substitutes());
+    let scale: f64 = run.as_ref().map_or(1.0, scaling);
+    if scale != 1.0 {
+        println!("Energies divided by the scale of the request: {}", scale);
     }
 
+    // (layer, sub-lattice) of each index, instead of parsing the keys of every configuration
+    let site_table: Vec<(usize, usize)> = Lattice::new(num_length, num_height).site_table();
+    let mut rows = Rows {
+        data: Vec::new(),
+        skip_count: 0,
+        max_difference: 0.0,
+        scale,
+    };
+
+    // The solutions are read one at a time from the file and analysed in parallel by chunks
+    println!("Calculating...");
+    let mut chunk: Vec<(usize, SolutionRecord)> = Vec::with_capacity(CHUNK_SIZE);
+    let mut failure: Result<()> = Ok(()); // First error of a chunk, the rest of the file is skipped
+    let configs_len: usize =
+        for_each_solution(&file_path, site_table.len(), |config_index, mut detail| {
+            if failure.is_err() {
+                return;
+            }
+            if let Some(fixing) = &fixing {
+                fixing.fill(&mut detail.configuration);
+            }
+            chunk.push((config_index, detail));
+            if chunk.len() == CHUNK_SIZE {
+                failure = rows.analyse(&chunk, &site_table, num_height, &problem);
+                chunk.clear();
+            }
+        })
+        .map_err(|e| match e.downcast::<Error>() {
+            Ok(e) => e.in_file(&file_path), // Packed file, index or energy of a solution
+            Err(e) => match e.downcast::<std::io::Error>() {
+                Ok(e) => Error::io(&file_path, *e),
+                Err(e) => Error::syntax(&file_path, e),
+            },
+        })?;
+    failure
+        .and_then(|_| rows.analyse(&chunk, &site_table, num_height, &problem))
+        .map_err(|e| e.in_file(&file_path))?;
+    let (analysis_data, skip_count, max_difference) =
+        (rows.data, rows.skip_count, rows.max_difference);
+
+    println!("configs length: {}", configs_len);
     println!("data length: {}", analysis_data.len());
     println!("skip count: {} ( c6 or order_p is 0.0, skip )", skip_count);
+    if problem.is_some() {
+        println!("max |energy - qubo energy|: {}", max_difference);
+    }
 
     assert_eq!(
         analysis_data.len() + skip_count,
-        configs.len() as usize * num_height as usize
+        configs_len * num_height as usize
     );
 
-    let target_dir = format!("./target/Gamma{}", gamma);
-    std::fs::create_dir_all(&target_dir).unwrap(); // Create directory if not exists
+    let target_dir: String = workspace.gamma_dir(&gamma)?;
 
     let target_file = format!(
         "{}/{}_{}_{}_{}.txt",
@@ -130,44 +266,60 @@ pub fn analysis(file_path: String) {
 
     println!("Saving to {}...", target_file);
 
-    std::fs::File::create(target_file.clone()).unwrap();
-    std::fs::write(target_file, analysis_data.join("\n")).unwrap();
+    std::fs::write(&target_file, analysis_data.join("\n")).map_err(|e| Error::io(&target_file, e))
 }
 
-fn calc_c6_order_p(config: &Value, length: i32, height: i32) -> Vec<(f64, f64, i32)> {
-    let detail = config.as_object().unwrap();
+/*
+ * Metadata of the run of a result: the copy saved in it by generate --solver and submit, otherwise
+ * (ex: api.sh --get) the metadata.json of the workspace when the result has its (Gamma, Strength, L, H)
+ */
+fn result_run_metadata(
+    file_path: &str,
+    workspace: &WorkspaceArgs,
+    name: (f64, f64, i32, i32),
+) -> Result<Option<Value>> {
+    let saved: Option<Value> =
+        result_metadata(file_path).map_err(|e| match e.downcast::<Error>() {
+            Ok(e) => e.in_file(file_path), // Packed file
+            Err(e) => match e.downcast::<std::io::Error>() {
+                Ok(e) => Error::io(file_path, *e),
+                Err(e) => Error::syntax(file_path, e),
+            },
+        })?;
+    if saved.is_some() {
+        return Ok(saved);
+    }
+    crate::solution::run_metadata(workspace, name)
+}
+
+// Scale of the coefficients of the request (--scale-bits), 1 when they were not scaled
+fn scaling(meta: &Value) -> f64 {
+    meta["Scaling"]["scale"].as_f64().unwrap_or(1.0)
+}
 
+// Sites missing from `config` (None) are left out of the counts of their sublattice.
+// `config` is not longer than the lattice: for_each_solution refuses the indices past it.
+pub fn calc_c6_order_p(
+    config: &[Option<bool>],
+    site_table: &[(usize, usize)],
+    height: i32,
+) -> Vec<(f64, f64, i32)> {
     // BLUE, BLACK, RED
     let mut m_color_params: Vec<Vec<i32>> = vec![vec![0, 0, 0]; height as usize];
     let mut m_each_count: Vec<Vec<i32>> = vec![vec![0, 0, 0]; height as usize];
 
-    for (key, value) in detail {
-        let mut index = key.parse::<i32>().unwrap();
-        let layer = index / (length * length);
+    for (value, (layer, remainder)) in config.iter().zip(site_table) {
+        let (value, layer, remainder): (bool, usize, usize) = match value {
+            Some(value) => (*value, *layer, *remainder),
+            None => continue,
+        };
 
-        // layer should be less than height
-        assert!(
-            layer < height,
-            "layer is over height: {} > {}",
-            layer,
-            height
-        );
-
-        index = index % (length * length);
-        let remainder: i32 = ((index / length) + index) % 3;
-
-        // if layer != 0 {
-        //     println!("layer: {}", layer);
-        // }
-
-        m_each_count[layer as usize][remainder as usize] += 1;
-        if value.as_bool().unwrap() {
-            m_color_params[layer as usize][remainder as usize] += 1;
+        m_each_count[layer][remainder] += 1;
+        if value {
+            m_color_params[layer][remainder] += 1;
         } else {
-            m_color_params[layer as usize][remainder as usize] -= 1;
+            m_color_params[layer][remainder] -= 1;
         }
-
-        // println!("m_color_params: {:?}", m_color_params);
     }
 
     let mut list_c6_orderp: Vec<(f64, f64, i32)> = Vec::new();
@@ -188,30 +340,29 @@ fn calc_c6_order_p(config: &Value, length: i32, height: i32) -> Vec<(f64, f64, i
         }
         let c6: f64 = order_p_6.re / order_p_6.norm();
 
-        list_c6_orderp.push((c6, order_p, i as i32));
+        list_c6_orderp.push((c6, order_p, i));
     }
 
     list_c6_orderp
 }
 
-fn read_payload_from_file<P: AsRef<Path>>(path: P) -> Result<Value, Box<dyn Error>> {
-    // Open file in RO mode with buffer
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-
-    // Read the JSON contents of the file
-    let u = serde_json::from_reader(reader)?;
-
-    Ok(u)
+pub fn name_error(file_path: &str) -> Error {
+    Error::parameter(
+        file_path,
+        "not named like .../Gamma<G>/Strength<J>_Lattice<L>_<L>_<H>_<solver>.json",
+    )
 }
 
 // Get data from file path (gamma, strength, length, height)
-fn get_data(file_path: String) -> (String, String, String, String) {
-    // file path ex: ./target/Gamma0.0/Strength1.0_Lattice12_12_1_Time10.json
+pub fn get_data(file_path: String) -> Result<(String, String, String, String)> {
+    // file path ex: ./workspace/Gamma0.0/Strength1.0_Lattice12_12_1_Time10.json
     let v: Vec<&str> = file_path.split("Lattice").collect();
-    // v: ["./target/Gamma0.0/Strength1.0_", "12_12_1_Time10.json"]
+    // v: ["./workspace/Gamma0.0/Strength1.0_", "12_12_1_Time10.json"]
     let one: Vec<&str> = v[0].split("Gamma").collect();
-    // one: ["./target/", "0.0/Strength1.0_"]
+    // one: ["./workspace/", "0.0/Strength1.0_"]
+    if v.len() < 2 || one.len() < 2 || !one[1].contains("Strength") || v[1].split('_').count() < 3 {
+        return Err(name_error(&file_path));
+    }
     let gamma: String = one[1].split("/").collect::<Vec<&str>>()[0].to_string();
     let strength: String = one[1].split("Strength").collect::<Vec<&str>>()[1]
         .split("_")
@@ -223,5 +374,263 @@ fn get_data(file_path: String) -> (String, String, String, String) {
         "gamma: {}, strength: {}, length: {}, height: {}",
         gamma, strength, length, height
     );
-    (gamma, strength, length, height)
+    Ok((gamma, strength, length, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polynomial::tests::configurations;
+
+    // -x0 + 2 x0 x1 - 3 x1 x4 + x2 x3 + 0.5, the indices 0 to 4
+    fn quadratic() -> Polynomial {
+        Polynomial::new(vec![
+            (-1.0, vec![0]),
+            (2.0, vec![0, 1]),
+            (-3.0, vec![1, 4]),
+            (1.0, vec![2, 3]),
+            (0.5, vec![]),
+        ])
+    }
+
+    #[test]
+    fn energy_both_agrees() {
+        let polynomial: Polynomial = quadratic();
+        let ising: Ising = Ising::from_polynomial(&polynomial).unwrap();
+        for configuration in configurations(5) {
+            let configuration: Vec<Option<bool>> = configuration.into_iter().map(Some).collect();
+            let (qubo, spin) = energy_both(&polynomial, &ising, &configuration, 0).unwrap();
+            assert!((qubo - spin).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn energy_both_missing_variable() {
+        let polynomial: Polynomial = quadratic();
+        let ising: Ising = Ising::from_polynomial(&polynomial).unwrap();
+        match energy_both(
+            &polynomial,
+            &ising,
+            &[Some(true), None, Some(true), Some(true), Some(false)],
+            7,
+        ) {
+            Err(e @ Error::Content { .. }) => {
+                assert_eq!(e.exit_code(), 4);
+                assert!(e
+                    .to_string()
+                    .contains("/qubo_solution/solutions/7/configuration"));
+            }
+            _ => panic!("a missing variable must be a content error"),
+        }
+    }
+
+    #[test]
+    fn scaled_energies_are_divided() {
+        assert_eq!(
+            scaling(&serde_json::json!({"Scaling": {"scale": 2.5}})),
+            2.5
+        );
+        assert_eq!(scaling(&serde_json::json!({"Side_length": 3})), 1.0);
+
+        // One layer of 3 x 3, the first sub-lattice up: the energies of the scaled request are 2.5 x E
+        let site_table: Vec<(usize, usize)> = Lattice::new(3, 1).site_table();
+        let configuration: Vec<Option<bool>> = site_table
+            .iter()
+            .map(|(_, remainder)| Some(*remainder == 0))
+            .collect();
+        let polynomial: Polynomial = quadratic();
+        let scaled: Polynomial = Polynomial::new(
+            polynomial
+                .raw_terms()
+                .into_iter()
+                .map(|(c, p)| (2.5 * c, p))
+                .collect(),
+        );
+        let x: Vec<bool> = configuration.iter().map(|value| value.unwrap()).collect();
+        let energy: f64 = polynomial.energy(&x);
+        let record = SolutionRecord {
+            configuration,
+            energy: 2.5 * energy,
+            frequency: 1,
+        };
+        let mut rows = Rows {
+            data: Vec::new(),
+            skip_count: 0,
+            max_difference: 0.0,
+            scale: 2.5,
+        };
+        let scaled_ising: Ising = Ising::from_polynomial(&scaled).unwrap();
+        let problem: Option<(Polynomial, Ising)> = Some((scaled, scaled_ising));
+        rows.analyse(&[(0, record)], &site_table, 1, &problem)
+            .unwrap();
+        let fields: Vec<f64> = rows.data[0]
+            .split('\t')
+            .map(|field| field.parse().unwrap())
+            .collect();
+        for field in &fields[4..] {
+            assert!((field - energy).abs() < 1e-9);
+        }
+        assert!(rows.max_difference < 1e-9);
+    }
+
+    // Empty workspace of its own for a test
+    fn workspace(name: &str) -> WorkspaceArgs {
+        let directory =
+            std::env::temp_dir().join(format!("fujitsu-analysis-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(directory.join("Gamma0.5")).unwrap();
+        WorkspaceArgs {
+            out_dir: directory.to_str().unwrap().to_string(),
+        }
+    }
+
+    // Result `name` of Gamma 0.5 in the workspace, with these solutions
+    fn result_file(workspace: &WorkspaceArgs, name: &str, solutions: Value) -> String {
+        let path: String = format!("{}/Gamma0.5/{}", workspace.out_dir, name);
+        crate::write_json(
+            &path,
+            &serde_json::json!({"qubo_solution": {"solutions": solutions}}),
+        )
+        .unwrap();
+        path
+    }
+
+    #[test]
+    fn index_outside_the_lattice_is_a_content_error() {
+        let workspace: WorkspaceArgs = workspace("outside");
+        let configuration: Value = (0..10)
+            .map(|i| (i.to_string(), Value::Bool(i % 3 == 0)))
+            .collect();
+        let path: String = result_file(
+            &workspace,
+            "Strength1.0_Lattice3_3_1_TABU.json",
+            serde_json::json!([{"configuration": configuration, "energy": -1.0}]),
+        );
+        match analysis(path.clone(), None, &workspace) {
+            Err(e @ Error::Content { .. }) => {
+                assert_eq!(e.exit_code(), 4);
+                assert!(e.to_string().contains(&path), "{}", e);
+                assert!(e.to_string().contains("variable 9"), "{}", e);
+            }
+            _ => panic!("an index outside the lattice must be a content error"),
+        }
+        std::fs::remove_dir_all(&workspace.out_dir).unwrap();
+    }
+
+    // First sub-lattice up on 3 x 3 x 1
+    fn first_up() -> Vec<Option<bool>> {
+        Lattice::new(3, 1)
+            .site_table()
+            .iter()
+            .map(|(_, remainder)| Some(*remainder == 0))
+            .collect()
+    }
+
+    // Result of Gamma 0.5, Strength 1.0 on 3 x 3 x 1 with one solution of energy -4 (with `metadata`),
+    // and the fields of its analysis
+    fn analysed_row(
+        workspace: &WorkspaceArgs,
+        configuration: &[Option<bool>],
+        metadata: Option<Value>,
+    ) -> Vec<f64> {
+        let configuration: Value = configuration
+            .iter()
+            .enumerate()
+            .filter_map(|(i, spin)| Some((i.to_string(), Value::Bool((*spin)?))))
+            .collect();
+        let path: String = result_file(
+            workspace,
+            "Strength1.0_Lattice3_3_1_TABU.json",
+            serde_json::json!([{"configuration": configuration, "energy": -4.0}]),
+        );
+        if let Some(metadata) = metadata {
+            let mut result: Value = crate::read_json(&path).unwrap();
+            result["metadata"] = metadata;
+            crate::write_json(&path, &result).unwrap();
+        }
+        analysis(path, None, workspace).unwrap();
+        let rows: String =
+            std::fs::read_to_string(format!("{}/Gamma0.5/1.0_3_3_1.txt", workspace.out_dir))
+                .unwrap();
+        rows.split('\t')
+            .map(|field| field.parse().unwrap())
+            .collect()
+    }
+
+    fn analysed_energy(workspace: &WorkspaceArgs, metadata: Option<Value>) -> f64 {
+        analysed_row(workspace, &first_up(), metadata)[4]
+    }
+
+    // metadata.json of a run of 3 x 3 x 1 at `gamma`, scaled by `scale` if any
+    fn metadata(gamma: f64, scale: Option<f64>) -> Value {
+        let mut meta: Value = serde_json::json!({
+            "Gamma": gamma,
+            "Strength": 1.0,
+            "Side_length": 3,
+            "Height": 1,
+        });
+        if let Some(scale) = scale {
+            meta["Scaling"] = serde_json::json!({ "scale": scale });
+        }
+        meta
+    }
+
+    #[test]
+    fn scale_is_the_one_of_the_run_of_the_result() {
+        let workspace: WorkspaceArgs = workspace("scale");
+        let meta_path: String = workspace.metadata_path();
+
+        // A scaled workspace of the same L and H but of another Gamma
+        crate::write_json(&meta_path, &metadata(0.2, Some(2.0))).unwrap();
+        assert_eq!(analysed_energy(&workspace, None), -4.0);
+
+        // The workspace of the run of the result
+        crate::write_json(&meta_path, &metadata(0.5, Some(2.0))).unwrap();
+        assert_eq!(analysed_energy(&workspace, None), -2.0);
+
+        // The metadata saved with the result comes first
+        assert_eq!(analysed_energy(&workspace, Some(metadata(0.5, None))), -4.0);
+        assert_eq!(
+            analysed_energy(&workspace, Some(metadata(0.5, Some(4.0)))),
+            -1.0
+        );
+        std::fs::remove_dir_all(&workspace.out_dir).unwrap();
+    }
+
+    #[test]
+    fn fixed_spins_are_the_ones_of_the_run_of_the_result() {
+        let workspace: WorkspaceArgs = workspace("fixed");
+        let site_table: Vec<(usize, usize)> = Lattice::new(3, 1).site_table();
+        let mut filled: Vec<Option<bool>> = first_up();
+        filled[0] = filled[0].map(|spin| !spin);
+        let mut missing: Vec<Option<bool>> = first_up();
+        missing[0] = None;
+        let c6_order_p = |configuration: &[Option<bool>]| -> Vec<f64> {
+            let (c6, order_p, _) = calc_c6_order_p(configuration, &site_table, 1)[0];
+            vec![c6, order_p]
+        };
+        assert_ne!(c6_order_p(&filled), c6_order_p(&missing));
+
+        // Site 0 substituted out of the problem, with the value of `filled`
+        let mut meta: Value = metadata(0.2, None);
+        meta["Fixed"] = serde_json::json!({
+            "mode": "substitute",
+            "config": { "0": filled[0] },
+        });
+
+        // The workspace is of another run: its fixed spins are not put into the result
+        crate::write_json(&workspace.metadata_path(), &meta).unwrap();
+        assert_eq!(
+            analysed_row(&workspace, &missing, None)[..2],
+            c6_order_p(&missing)[..]
+        );
+
+        // The metadata saved with the result fills them
+        meta["Gamma"] = serde_json::json!(0.5);
+        assert_eq!(
+            analysed_row(&workspace, &missing, Some(meta))[..2],
+            c6_order_p(&filled)[..]
+        );
+        std::fs::remove_dir_all(&workspace.out_dir).unwrap();
+    }
 }
@@ -1,27 +1,59 @@
-use serde_json::Value;
-use std::error::Error;
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
-
-pub fn use_guidance(guidance_path: String) {
-    let (gamma, strength, length, height) = get_data(guidance_path.clone()); // Get data from file path
-
-    let gamma: f64 = gamma.parse().unwrap(); // Convert gamma to f64
-    let strength: f64 = strength.parse().unwrap(); // Convert strength to f64
-    let length: i32 = length.parse().unwrap(); // Convert length to i32
-    let height: i32 = height.parse().unwrap(); // Convert height to i32
-    let get_meta = read_payload_from_file("./target/metadata.json").unwrap(); // Get meta file data
-    if (gamma, strength, length, height)
-        != (
-            get_meta["Gamma"].as_f64().unwrap(),
-            get_meta["Strength"].as_f64().unwrap(),
-            get_meta["Side_length"].as_i64().unwrap() as i32,
-            get_meta["Height"].as_i64().unwrap() as i32,
-        )
-    {
-        println!("Meta data is not matched");
-        return;
+use crate::args::{GuidanceArgs, WorkspaceArgs};
+use crate::error::{Error, Result};
+use crate::fixing::Fixing;
+use crate::gamma_analysis::name_error;
+use crate::guidance_select::{select, Selection};
+use crate::initial_state::{set_state, state_value};
+use crate::lattice::Lattice;
+use crate::request::Request;
+use crate::solution_set::{read_solution_set, SolutionSet};
+use crate::Jxx;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeSet;
+use std::fs;
+
+pub fn use_guidance(options: &GuidanceArgs) -> Result<()> {
+    let guidance_path: String = match (&options.guidance_path, &options.state) {
+        (_, Some(state)) => return use_state(options, state),
+        (Some(guidance_path), None) => guidance_path.clone(),
+        (None, None) => {
+            return Err(Error::parameter(
+                "<guidance-path>",
+                "a result file or --state is required",
+            ))
+        }
+    };
+    let workspace: &WorkspaceArgs = &options.workspace;
+    let (gamma, strength, length, height) = get_data(guidance_path.clone())?; // Get data from file path
+
+    let gamma: f64 = gamma.parse().map_err(|_| name_error(&guidance_path))?; // Convert gamma to f64
+    let strength: f64 = strength.parse().map_err(|_| name_error(&guidance_path))?; // Convert strength to f64
+    let length: i32 = length.parse().map_err(|_| name_error(&guidance_path))?; // Convert length to i32
+    let height: i32 = height.parse().map_err(|_| name_error(&guidance_path))?; // Convert height to i32
+    let meta_path: &str = &workspace.existing(workspace.metadata_path())?;
+    let mut get_meta: Value = crate::read_json(meta_path)?; // Get meta file data
+    let meta_number = |key: &str| -> Result<f64> { meta_number(&get_meta, meta_path, key) };
+    let meta: (f64, f64, i32, i32) = (
+        meta_number("Gamma")?,
+        meta_number("Strength")?,
+        meta_number("Side_length")? as i32,
+        meta_number("Height")? as i32,
+    );
+    let from: (f64, f64, i32, i32) = (gamma, strength, length, height);
+    if options.transfer {
+        check_transfer(from, meta, &guidance_path, meta_path)?;
+        println!(
+            "Transfer: Gamma {}, L {}, H {} -> Gamma {}, L {}, H {}",
+            gamma, length, height, meta.0, meta.2, meta.3
+        );
+    } else if from != meta {
+        return Err(Error::parameter(
+            &guidance_path,
+            format!(
+                "Gamma {}, Strength {}, L {}, H {} do not match {} (Gamma {}, Strength {}, L {}, H {}), see --transfer",
+                gamma, strength, length, height, meta_path, meta.0, meta.1, meta.2, meta.3
+            ),
+        ));
     } else {
         println!("Meta data is matched");
         println!(
@@ -31,56 +63,186 @@ pub fn use_guidance(guidance_path: String) {
     }
 
     println!("Loading...");
-    let lattice: Value = read_payload_from_file(guidance_path.clone()).unwrap(); // Get json file data
-    let mut input: Value = read_payload_from_file("./target/input.json").unwrap(); // Get input file data
-
-    let configs = lattice["qubo_solution"]["solutions"].as_array().unwrap();
-    let random_config_index: usize = rand::random::<usize>() % configs.len() as usize;
-    println!("random_config_index: {}", random_config_index);
+    // Packed (.sols) or DA JSON result
+    let configs: SolutionSet = read_solution_set(&guidance_path)?;
+    if configs.len() == 0 {
+        return Err(
+            Error::content("/qubo_solution/solutions", "no solution").in_file(&guidance_path)
+        );
+    }
+    let input_path: &str = &workspace.existing(workspace.request_path())?;
 
-    let da3 = input["fujitsuDA3"].as_object_mut().unwrap();
+    let selection: Selection = select(&configs, options, length, height)?;
+    let config_index: usize = selection.index;
+    println!("config_index: {} ({})", config_index, selection.reason);
+    let guidance: Value = if (length, height) == (meta.2, meta.3) {
+        configs.configuration_value(config_index)
+    } else {
+        transfer(&configs, config_index, (length, height), (meta.2, meta.3))
+    };
+    write_guidance(input_path, &guidance_path, guidance, &get_meta)?;
 
-    match da3.get_mut("guidance_config") {
-        None => {
-            println!("guidance_config is None\nInserting...");
-            da3.insert(
-                "guidance_config".to_string(),
-                configs[random_config_index]["configuration"].clone(),
-            );
-            crate::write_json("./target/input.json", &input);
-        }
-        Some(d) => {
-            println!("guidance_config is not None\nUpdating...");
-            let update_guidance = d.as_object_mut().unwrap();
-            update_guidance.remove_entry("guidance_config");
-            update_guidance.insert(
-                "guidance_config".to_string(),
-                configs[random_config_index]["configuration"].clone(),
-            );
-            crate::write_json("./target/input.json", &input);
-            return;
-        }
+    // Record the choice with the run
+    get_meta["Guidance"] = json!({
+        "source": guidance_path,
+        "strategy": options.select,
+        "index": config_index,
+        "reason": selection.reason,
+        "energy": configs.energies[config_index],
+        "frequency": configs.frequencies[config_index],
+    });
+    if options.transfer {
+        get_meta["Guidance"]["from"] = json!({
+            "Gamma": gamma,
+            "Strength": strength,
+            "Side_length": length,
+            "Height": height,
+        });
     }
+    crate::write_json(meta_path, &get_meta)
+}
+
+fn meta_number(meta: &Value, meta_path: &str, key: &str) -> Result<f64> {
+    meta[key]
+        .as_f64()
+        .ok_or_else(|| Error::content(format!("/{}", key), "not a number").in_file(meta_path))
+}
+
+// Write a synthetic state of the lattice of the request (--state) as its guidance_config
+fn use_state(options: &GuidanceArgs, state: &str) -> Result<()> {
+    let workspace: &WorkspaceArgs = &options.workspace;
+    let meta_path: &str = &workspace.existing(workspace.metadata_path())?;
+    let mut get_meta: Value = crate::read_json(meta_path)?; // Get meta file data
+    let jxx = Jxx {
+        j: meta_number(&get_meta, meta_path, "Strength")?,
+        jl: meta_number(&get_meta, meta_path, "Layer_strength")?,
+        l: meta_number(&get_meta, meta_path, "Side_length")? as i32,
+        h: meta_number(&get_meta, meta_path, "Height")? as i32,
+        gamma: meta_number(&get_meta, meta_path, "Gamma")?,
+    };
+    let input_path: &str = &workspace.existing(workspace.request_path())?;
+    println!("state: {} (L {}, H {})", state, jxx.l, jxx.h);
+
+    let mut lattice: Lattice = crate::create_vector(&jxx);
+    set_state(&mut lattice, state, options.seed);
+    write_guidance(input_path, "--state", state_value(&lattice), &get_meta)?;
+
+    get_meta["Guidance"] = json!({ "state": state, "seed": options.seed });
+    crate::write_json(meta_path, &get_meta)
 }
 
-fn read_payload_from_file<P: AsRef<Path>>(path: P) -> Result<Value, Box<dyn Error>> {
-    // Open file in RO mode with buffer
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+// Replace the guidance_config of the request, every index of it being a variable of the problem
+// (but the spins substituted out of it by the fixing of the metadata, dropped)
+fn write_guidance(
+    input_path: &str,
+    guidance_path: &str,
+    mut guidance: Value,
+    meta: &Value,
+) -> Result<()> {
+    if let Some(fixing) = Fixing::from_metadata(meta) {
+        fixing.reduce(&mut guidance);
+    }
+    let mut input: Value = crate::read_json(input_path)?; // Get input file data
+    let variables: BTreeSet<i32> = Request::from_value(&input)
+        .and_then(|request| request.variables())
+        .map_err(|e| e.in_file(input_path))?;
+    let config = guidance.as_object().unwrap(); // Built from the solution, {"index": bool, ...}
+    let missing: Vec<&String> = config
+        .keys()
+        .filter(|key| {
+            key.parse::<i32>()
+                .map_or(true, |index| !variables.contains(&index))
+        })
+        .collect();
+    if let Some(first) = missing.first() {
+        return Err(Error::parameter(
+            guidance_path,
+            format!(
+                "{} of the {} guidance indices (ex: {}) are not variables of the problem of {}",
+                missing.len(),
+                config.len(),
+                first,
+                input_path
+            ),
+        ));
+    }
+
+    let da3 = input["fujitsuDA3"]
+        .as_object_mut()
+        .ok_or_else(|| Error::content("/fujitsuDA3", "not an object").in_file(input_path))?;
+    let variable_count: usize = config.len();
+    match da3.insert("guidance_config".to_string(), guidance) {
+        None => println!("guidance_config inserted ({} variables)", variable_count),
+        Some(_) => println!("guidance_config replaced ({} variables)", variable_count),
+    }
+
+    // Written beside and renamed, so the request is never left half written
+    let temporary_path: String = format!("{}.tmp", input_path);
+    crate::write_json(&temporary_path, &input)?;
+    fs::rename(&temporary_path, input_path).map_err(|e| Error::io(input_path, e))
+}
 
-    // Read the JSON contents of the file
-    let u = serde_json::from_reader(reader)?;
+/*
+ * --transfer maps a solution of another run of the same Strength onto the lattice of the request:
+ *   Gamma   any, the solution is a warm start for a neighbouring Gamma
+ *   L       a divisor of the request L, the L x L plane is tiled (i mod L, j mod L)
+ *   H       1, the layer is replicated on every Trotter layer (ex: a Gamma = 0 ground state), or the request H
+ * When the lattice differs only the spins are mapped, the auxiliary variables are left to the solver.
+ */
+fn check_transfer(
+    from: (f64, f64, i32, i32),
+    to: (f64, f64, i32, i32),
+    guidance_path: &str,
+    meta_path: &str,
+) -> Result<()> {
+    let reason: Option<String> = if from.2 <= 0 || from.3 <= 0 {
+        Some(format!("L {} and H {} must be positive", from.2, from.3))
+    } else if from.1 != to.1 {
+        Some(format!("Strength {} is not {}", from.1, to.1))
+    } else if to.2 % from.2 != 0 {
+        Some(format!("L {} does not divide L {}", from.2, to.2))
+    } else if from.3 != 1 && from.3 != to.3 {
+        Some(format!("H {} is neither 1 nor H {}", from.3, to.3))
+    } else {
+        None
+    };
+    match reason {
+        Some(reason) => Err(Error::parameter(
+            guidance_path,
+            format!("cannot be transferred to {}: {}", meta_path, reason),
+        )),
+        None => Ok(()),
+    }
+}
 
-    Ok(u)
+// `{"index": bool, ...}` of the spins of an L x L x H lattice from solution `k` of another lattice (checked by check_transfer)
+fn transfer(configs: &SolutionSet, k: usize, from: (i32, i32), to: (i32, i32)) -> Value {
+    let (from_length, from_height): (i32, i32) = from;
+    let source: Vec<Option<bool>> = configs.configuration_by_index(k);
+    let lattice: Lattice = Lattice::new(to.0, to.1);
+    let mut map: Map<String, Value> = Map::new();
+    for index in 0..to.0 * to.0 * to.1 {
+        let (h, i, j) = lattice.get_hij(index);
+        let layer: i32 = if from_height == 1 { 0 } else { h };
+        let source_index: i32 =
+            layer * from_length * from_length + (i % from_length) * from_length + (j % from_length);
+        if let Some(Some(value)) = source.get(source_index as usize) {
+            map.insert(index.to_string(), Value::from(*value));
+        }
+    }
+    Value::Object(map)
 }
 
 // Get data from file path (gamma, strength, length, height)
-fn get_data(file_path: String) -> (String, String, String, String) {
-    // file path ex: ./target/Gamma0.0/Strength1.0_Lattice12_12_1_Time10.json
+fn get_data(file_path: String) -> Result<(String, String, String, String)> {
+    // file path ex: ./workspace/Gamma0.0/Strength1.0_Lattice12_12_1_Time10.json
     let v: Vec<&str> = file_path.split("Lattice").collect();
-    // v: ["./target/Gamma0.0/Strength1.0_", "12_12_1_Time10.json"]
+    // v: ["./workspace/Gamma0.0/Strength1.0_", "12_12_1_Time10.json"]
     let one: Vec<&str> = v[0].split("Gamma").collect();
-    // one: ["./target/", "0.0/Strength1.0_"]
+    // one: ["./workspace/", "0.0/Strength1.0_"]
+    if v.len() < 2 || one.len() < 2 || !one[1].contains("Strength") || v[1].split('_').count() < 3 {
+        return Err(name_error(&file_path));
+    }
     let gamma: String = one[1].split("/").collect::<Vec<&str>>()[0].to_string();
     let strength: String = one[1].split("Strength").collect::<Vec<&str>>()[1]
         .split("_")
@@ -88,5 +250,161 @@ fn get_data(file_path: String) -> (String, String, String, String) {
         .to_string();
     let length: String = v[1].split("_").collect::<Vec<&str>>()[0].to_string();
     let height: String = v[1].split("_").collect::<Vec<&str>>()[2].to_string();
-    (gamma, strength, length, height)
+    Ok((gamma, strength, length, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // Every spin of an L x L x H lattice, different from one site to the next
+    fn source(length: i32, height: i32) -> SolutionSet {
+        let n: i32 = length * length * height;
+        let mut configs = SolutionSet::new((0..n).collect(), json!({}));
+        let configuration: Vec<bool> = (0..n).map(|index| index % 3 != 1).collect();
+        configs.push(&configuration, -1.0, 1);
+        configs
+    }
+
+    // Source index of each (h, i, j) of the source lattice
+    fn source_index(length: i32, height: i32, hij: (i32, i32, i32)) -> i32 {
+        let lattice: Lattice = Lattice::new(length, height);
+        (0..length * length * height)
+            .find(|index| lattice.get_hij(*index) == hij)
+            .unwrap()
+    }
+
+    fn check_mapping(from: (i32, i32), to: (i32, i32)) {
+        let configs: SolutionSet = source(from.0, from.1);
+        let map: Value = transfer(&configs, 0, from, to);
+        let target: Lattice = Lattice::new(to.0, to.1);
+        assert_eq!(
+            map.as_object().unwrap().len(),
+            (to.0 * to.0 * to.1) as usize
+        );
+        for index in 0..to.0 * to.0 * to.1 {
+            let (h, i, j) = target.get_hij(index);
+            let layer: i32 = if from.1 == 1 { 0 } else { h };
+            let source: i32 = source_index(from.0, from.1, (layer, i % from.0, j % from.0));
+            assert_eq!(
+                map[index.to_string()],
+                json!(configs.bit(0, source as usize))
+            );
+        }
+    }
+
+    #[test]
+    fn single_layer_is_copied_onto_every_layer() {
+        check_mapping((3, 1), (3, 4));
+    }
+
+    #[test]
+    fn plane_is_tiled_onto_a_multiple_of_l() {
+        check_mapping((3, 1), (9, 2));
+        check_mapping((3, 2), (6, 2));
+    }
+
+    #[test]
+    fn missing_spins_are_not_transferred() {
+        let mut configs = SolutionSet::new(vec![0, 2], json!({}));
+        configs.push(&[true, false], -1.0, 1);
+        let map: Value = transfer(&configs, 0, (3, 1), (3, 1));
+        assert_eq!(map, json!({"0": true, "2": false}));
+    }
+
+    #[test]
+    fn transfer_is_refused_between_incompatible_lattices() {
+        let check = |from: (f64, f64, i32, i32)| {
+            check_transfer(from, (0.5, 1.0, 6, 4), "guidance.json", "metadata.json")
+        };
+        assert!(check((0.2, 1.0, 3, 1)).is_ok());
+        assert!(check((0.2, 1.0, 6, 4)).is_ok());
+        for from in [
+            (0.2, 1.0, 0, 1),  // Lattice0_...
+            (0.2, 1.0, -3, 1), // Negative L
+            (0.2, 1.0, 3, 0),  // H = 0
+            (0.2, 2.0, 3, 1),  // Other Strength
+            (0.2, 1.0, 4, 1),  // 4 does not divide 6
+            (0.2, 1.0, 3, 2),  // H neither 1 nor 4
+        ] {
+            assert!(
+                matches!(check(from), Err(Error::Parameter { .. })),
+                "{:?}",
+                from
+            );
+        }
+    }
+
+    // Request of the variables 0 to 2 with a guidance_config already, in a directory of its own
+    fn input(name: &str) -> String {
+        let directory =
+            std::env::temp_dir().join(format!("fujitsu-guidance-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let path: String = directory.join("input.json").to_str().unwrap().to_string();
+        let request: Value = json!({
+            "fujitsuDA3": {"time_limit_sec": 1, "guidance_config": {"0": true, "1": true}},
+            "binary_polynomial": {"terms": [{"c": 1.0, "p": [0, 1]}, {"c": -1.0, "p": [2]}]},
+        });
+        crate::write_json(&path, &request).unwrap();
+        path
+    }
+
+    #[test]
+    fn guidance_config_is_replaced_as_a_whole() {
+        let path: String = input("replace");
+        let guidance: Value = json!({"1": false, "2": true});
+        write_guidance(&path, "guidance.json", guidance.clone(), &json!({})).unwrap();
+        let request: Value = crate::read_json(&path).unwrap();
+        assert_eq!(request["fujitsuDA3"]["guidance_config"], guidance);
+        assert_eq!(request["fujitsuDA3"]["time_limit_sec"], json!(1));
+        assert!(request["fujitsuDA3"]["guidance_config"]
+            .get("guidance_config")
+            .is_none());
+        assert!(request.get("guidance_config").is_none());
+        // Written beside then renamed, nothing is left beside it
+        assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+    }
+
+    #[test]
+    fn indices_outside_the_problem_are_refused() {
+        let path: String = input("outside");
+        let before: String = fs::read_to_string(&path).unwrap();
+        for guidance in [json!({"0": true, "7": false}), json!({"x": true})] {
+            match write_guidance(&path, "guidance.json", guidance, &json!({})) {
+                Err(e @ Error::Parameter { .. }) => {
+                    assert!(e.to_string().contains("guidance.json"))
+                }
+                _ => panic!("an index outside the problem must be refused"),
+            }
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), before);
+        assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+    }
+
+    #[test]
+    fn request_is_written_beside_and_renamed() {
+        let path: String = input("rename");
+        let temporary_path: String = format!("{}.tmp", path);
+        fs::write(&temporary_path, "left by an interrupted run").unwrap();
+        write_guidance(&path, "--state", json!({"0": false}), &json!({})).unwrap();
+        assert!(!std::path::Path::new(&temporary_path).exists());
+        let request: Value = crate::read_json(&path).unwrap();
+        assert_eq!(
+            request["fujitsuDA3"]["guidance_config"],
+            json!({"0": false})
+        );
+
+        // A request without fujitsuDA3 is left as it was, nothing is written beside it
+        let mut request: Value = request;
+        request.as_object_mut().unwrap().remove("fujitsuDA3");
+        crate::write_json(&path, &request).unwrap();
+        assert!(matches!(
+            write_guidance(&path, "--state", json!({"0": true}), &json!({})),
+            Err(Error::Content { .. })
+        ));
+        assert_eq!(crate::read_json(&path).unwrap(), request);
+        assert!(!std::path::Path::new(&temporary_path).exists());
+    }
 }
@@ -0,0 +1,394 @@
+// Purpose: Choice of the solution of a result file used as the guidance_config (guidance --select)
+use crate::args::GuidanceArgs;
+use crate::error::{Error, Result};
+use crate::gamma_analysis::calc_c6_order_p;
+use crate::lattice::Lattice;
+use crate::random::get_rng;
+use crate::solution_set::{is_solution_set, read_solution_set, SolutionSet};
+use rand::Rng;
+use serde_json::Value;
+
+/*
+ * --select:
+ *   random             uniform pick, reproducible with --seed
+ *   lowest-energy      lowest energy, the first one on a tie
+ *   highest-frequency  highest frequency, the lowest energy on a tie
+ *   order              highest |psi|^2, averaged over the layers
+ *   c6                 c6 closest to --c6-target, averaged over the layers with psi != 0
+ *   nearest            lowest Hamming distance to --reference, the lowest energy on a tie
+ */
+pub struct Selection {
+    pub index: usize,   // Solution of the result file
+    pub reason: String, // Why it was chosen, printed and written to the metadata
+}
+
+// Index of the best solution by `key`, the lowest energy (then the first one) on a tie
+fn best_by(configs: &SolutionSet, key: impl Fn(usize) -> f64) -> usize {
+    let (mut best, mut best_value): (usize, f64) = (0, key(0));
+    for k in 1..configs.len() {
+        let value: f64 = key(k);
+        if value < best_value
+            || (value == best_value && configs.energies[k] < configs.energies[best])
+        {
+            (best, best_value) = (k, value);
+        }
+    }
+    best
+}
+
+// (mean |psi|^2 over the layers, mean c6 over the layers with psi != 0) of solution `k`
+fn order_parameters(
+    configs: &SolutionSet,
+    k: usize,
+    site_table: &[(usize, usize)],
+    height: i32,
+) -> (f64, Option<f64>) {
+    let mut configuration: Vec<Option<bool>> = configs.configuration_by_index(k);
+    configuration.truncate(site_table.len()); // Auxiliary variables of the constraints are not spins
+    let list_c6_orderp: Vec<(f64, f64, i32)> = calc_c6_order_p(&configuration, site_table, height);
+    let order_p: f64 = list_c6_orderp
+        .iter()
+        .map(|(_, order_p, _)| order_p)
+        .sum::<f64>()
+        / height as f64;
+    let c6s: Vec<f64> = list_c6_orderp
+        .iter()
+        .filter(|(c6, order_p, _)| (*c6, *order_p) != (0.0, 0.0))
+        .map(|(c6, _, _)| *c6)
+        .collect();
+    let c6: Option<f64> = match c6s.len() {
+        0 => None,
+        n => Some(c6s.iter().sum::<f64>() / n as f64),
+    };
+    (order_p, c6)
+}
+
+// Variable `index` of the reference, refused past the `variables` of the solutions before allocating it
+fn check_reference_index(index: usize, variables: usize, pointer: &str, path: &str) -> Result<()> {
+    if index >= variables {
+        return Err(Error::content(
+            pointer,
+            format!(
+                "variable {} is outside the {} variables of the solutions",
+                index, variables
+            ),
+        )
+        .in_file(path));
+    }
+    Ok(())
+}
+
+// Reference state of --select nearest, indexed by the variable index (missing = false),
+// of the `variables` variables of the solutions (indices 0 to variables - 1)
+fn read_reference(path: &str, variables: usize) -> Result<Vec<bool>> {
+    let value: Option<Value> = match is_solution_set(path) {
+        true => None,
+        false => Some(crate::read_json(path)?),
+    };
+    let config = match &value {
+        Some(value) if value.get("qubo_solution").is_none() => value
+            .as_object()
+            .ok_or_else(|| Error::syntax(path, "not a result nor a map of index to bool"))?,
+        _ => {
+            let reference: SolutionSet = read_solution_set(path)?;
+            if reference.len() == 0 {
+                return Err(Error::content("/qubo_solution/solutions", "no solution").in_file(path));
+            }
+            if let Some(last) = reference.variables.last() {
+                check_reference_index(*last as usize, variables, "/qubo_solution/solutions", path)?;
+            }
+            let lowest: usize = best_by(&reference, |k| reference.energies[k]);
+            return Ok(reference
+                .configuration_by_index(lowest)
+                .into_iter()
+                .map(|bit| bit.unwrap_or(false))
+                .collect());
+        }
+    };
+    let mut reference: Vec<bool> = Vec::new();
+    for (key, bit) in config {
+        let (index, bit): (usize, bool) = match (key.parse::<usize>(), bit.as_bool()) {
+            (Ok(index), Some(bit)) => (index, bit),
+            _ => {
+                return Err(Error::content(
+                    format!("/{}", key),
+                    format!("{} is not an index to a bool", bit),
+                )
+                .in_file(path))
+            }
+        };
+        check_reference_index(index, variables, &format!("/{}", key), path)?;
+        if index >= reference.len() {
+            reference.resize(index + 1, false);
+        }
+        reference[index] = bit;
+    }
+    Ok(reference)
+}
+
+fn hamming_distance(configs: &SolutionSet, k: usize, reference: &[bool]) -> usize {
+    configs
+        .variables
+        .iter()
+        .enumerate()
+        .filter(|(i, index)| {
+            configs.bit(k, *i) != reference.get(**index as usize).copied().unwrap_or(false)
+        })
+        .count()
+}
+
+// Choose a solution of `configs` (not empty) of an L x L x H lattice
+pub fn select(
+    configs: &SolutionSet,
+    options: &GuidanceArgs,
+    length: i32,
+    height: i32,
+) -> Result<Selection> {
+    let site_table: Vec<(usize, usize)> = Lattice::new(length, height).site_table();
+    let (index, reason): (usize, String) = match options.select.as_str() {
+        "lowest-energy" => {
+            let index: usize = best_by(configs, |k| configs.energies[k]);
+            (index, format!("lowest energy {}", configs.energies[index]))
+        }
+        "highest-frequency" => {
+            let index: usize = best_by(configs, |k| -configs.frequencies[k] as f64);
+            (
+                index,
+                format!("highest frequency {}", configs.frequencies[index]),
+            )
+        }
+        "order" => {
+            let index: usize = best_by(configs, |k| {
+                -order_parameters(configs, k, &site_table, height).0
+            });
+            let (order_p, _) = order_parameters(configs, index, &site_table, height);
+            (index, format!("highest |psi|^2 {}", order_p))
+        }
+        "c6" => {
+            let target: f64 = options
+                .c6_target
+                .ok_or_else(|| Error::parameter("--c6-target", "required by --select c6"))?;
+            // No c6 when psi = 0 on every layer, never chosen before a solution with one
+            let distance = |k: usize| -> f64 {
+                match order_parameters(configs, k, &site_table, height).1 {
+                    Some(c6) => (c6 - target).abs(),
+                    None => f64::INFINITY,
+                }
+            };
+            let index: usize = best_by(configs, distance);
+            match order_parameters(configs, index, &site_table, height).1 {
+                Some(c6) => (index, format!("c6 {} closest to {}", c6, target)),
+                None => {
+                    return Err(Error::parameter(
+                        "--select c6",
+                        "psi is 0 on every layer of every solution",
+                    ))
+                }
+            }
+        }
+        "nearest" => {
+            let reference_path: &str = options
+                .reference_path
+                .as_deref()
+                .ok_or_else(|| Error::parameter("--reference", "required by --select nearest"))?;
+            let variables: usize = configs
+                .variables
+                .last()
+                .map_or(0, |last| *last as usize + 1);
+            let reference: Vec<bool> = read_reference(reference_path, variables)?;
+            let index: usize =
+                best_by(configs, |k| hamming_distance(configs, k, &reference) as f64);
+            (
+                index,
+                format!(
+                    "Hamming distance {} to {}",
+                    hamming_distance(configs, index, &reference),
+                    reference_path
+                ),
+            )
+        }
+        _ => {
+            let index: usize = get_rng(options.seed).gen_range(0..configs.len());
+            match options.seed {
+                Some(seed) => (index, format!("random with seed {}", seed)),
+                None => (index, "random".to_string()),
+            }
+        }
+    };
+    Ok(Selection { index, reason })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use structopt::StructOpt;
+
+    // One 6 x 6 layer with the first `up[r]` of the 12 sites of each sub-lattice r up
+    fn state(up: [usize; 3]) -> Vec<bool> {
+        let mut seen: [usize; 3] = [0; 3];
+        Lattice::new(6, 1)
+            .site_table()
+            .iter()
+            .map(|(_, remainder)| {
+                seen[*remainder] += 1;
+                seen[*remainder] <= up[*remainder]
+            })
+            .collect()
+    }
+
+    fn set(states: &[([usize; 3], f64, i32)]) -> SolutionSet {
+        let mut configs = SolutionSet::new((0..36).collect(), json!({}));
+        for (up, energy, frequency) in states {
+            configs.push(&state(*up), *energy, *frequency);
+        }
+        configs
+    }
+
+    // 0: ferro, 1: uud, 2: up-down-zero, 3: up, down and 1/3, 4: uud again
+    fn fixture() -> SolutionSet {
+        set(&[
+            ([12, 12, 12], 0.0, 1),
+            ([12, 12, 0], -3.0, 2),
+            ([12, 0, 6], -3.0, 5),
+            ([12, 0, 8], -2.0, 5),
+            ([12, 12, 0], -1.0, 1),
+        ])
+    }
+
+    fn select_with(configs: &SolutionSet, args: &[&str]) -> Result<Selection> {
+        let options = GuidanceArgs::from_iter(["guidance", "result.json"].iter().chain(args));
+        select(configs, &options, 6, 1)
+    }
+
+    fn selected(args: &[&str]) -> usize {
+        select_with(&fixture(), args).unwrap().index
+    }
+
+    // File `name` of the test directory holding `value`
+    fn file(name: &str, value: &Value) -> String {
+        let directory = std::env::temp_dir().join(format!("fujitsu-select-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path: String = directory.join(name).to_str().unwrap().to_string();
+        crate::write_json(&path, value).unwrap();
+        path
+    }
+
+    #[test]
+    fn lowest_energy_takes_the_first_on_a_tie() {
+        assert_eq!(selected(&["--select", "lowest-energy"]), 1);
+    }
+
+    #[test]
+    fn highest_frequency_takes_the_lowest_energy_on_a_tie() {
+        assert_eq!(selected(&["--select", "highest-frequency"]), 2);
+    }
+
+    #[test]
+    fn order_takes_a_full_three_sublattice_state() {
+        // |psi|^2 is 4/3 for uud, 1 for up-down-zero, the lowest energy of both uud is taken
+        assert_eq!(selected(&["--select", "order"]), 1);
+    }
+
+    #[test]
+    fn c6_takes_the_closest() {
+        let site_table: Vec<(usize, usize)> = Lattice::new(6, 1).site_table();
+        let configs: SolutionSet = fixture();
+        let c6 = |k: usize| order_parameters(&configs, k, &site_table, 1).1.unwrap();
+        assert!((c6(1) - 1.0).abs() < 1e-9 && (c6(2) + 1.0).abs() < 1e-9);
+        assert!(c6(3).abs() < 1.0 - 1e-9);
+        for k in [1, 2, 3] {
+            let target: String = format!("{}", c6(k));
+            assert_eq!(selected(&["--select", "c6", "--c6-target", &target]), k);
+        }
+        assert!(matches!(
+            select_with(&configs, &["--select", "c6"]),
+            Err(Error::Parameter { .. })
+        ));
+    }
+
+    #[test]
+    fn c6_fails_without_psi() {
+        // Half of every sub-lattice up: no magnetisation, psi = 0
+        let configs: SolutionSet = set(&[([6, 6, 6], -1.0, 1), ([6, 6, 6], -2.0, 1)]);
+        assert!(matches!(
+            select_with(&configs, &["--select", "c6", "--c6-target", "0.5"]),
+            Err(Error::Parameter { .. })
+        ));
+    }
+
+    #[test]
+    fn nearest_uses_the_hamming_distance() {
+        // The solution 3 with one more spin down
+        let mut reference: Vec<bool> = fixture().configuration(3);
+        let flipped: usize = reference.iter().position(|spin| *spin).unwrap();
+        reference[flipped] = false;
+        let map: Value = reference
+            .iter()
+            .enumerate()
+            .map(|(i, spin)| (i.to_string(), json!(spin)))
+            .collect::<serde_json::Map<String, Value>>()
+            .into();
+        let path: String = file("reference.json", &map);
+        let selection: Selection =
+            select_with(&fixture(), &["--select", "nearest", "--reference", &path]).unwrap();
+        assert_eq!(selection.index, 3);
+        assert!(selection.reason.starts_with("Hamming distance 1 "));
+    }
+
+    #[test]
+    fn seeded_random_is_reproducible() {
+        let first: Selection = select_with(&fixture(), &["--seed", "11"]).unwrap();
+        for _ in 0..3 {
+            assert_eq!(
+                select_with(&fixture(), &["--seed", "11"]).unwrap().index,
+                first.index
+            );
+        }
+        assert_eq!(first.reason, "random with seed 11");
+        let picks: Vec<usize> = (0..20)
+            .map(|seed| selected(&["--seed", &seed.to_string()]))
+            .collect();
+        assert!(picks.iter().any(|k| *k != picks[0]));
+    }
+
+    #[test]
+    fn reference_is_a_map_or_the_lowest_energy_of_a_result() {
+        let path: String = file("map.json", &json!({"0": true, "3": true}));
+        assert_eq!(
+            read_reference(&path, 36).unwrap(),
+            vec![true, false, false, true]
+        );
+
+        let result: Value = json!({"qubo_solution": {"solutions": [
+            {"configuration": {"0": true, "1": true}, "energy": -1.0, "frequency": 1},
+            {"configuration": {"0": false, "1": true}, "energy": -2.0, "frequency": 1},
+        ]}});
+        let path: String = file("result.json", &result);
+        assert_eq!(read_reference(&path, 36).unwrap(), vec![false, true]);
+    }
+
+    #[test]
+    fn reference_past_the_solutions_is_refused() {
+        for (name, value) in [
+            ("far.json", json!({"0": true, "4000000000": true})),
+            (
+                "far_result.json",
+                json!({"qubo_solution": {"solutions": [
+                    {"configuration": {"0": true, "4000000000": true}, "energy": -1.0, "frequency": 1},
+                ]}}),
+            ),
+        ] {
+            let path: String = file(name, &value);
+            match read_reference(&path, 36) {
+                Err(e @ Error::Content { .. }) => assert!(e.to_string().contains("4000000000")),
+                _ => panic!("an index past the solutions must be a content error"),
+            }
+        }
+        let path: String = file("not_bool.json", &json!({"0": 1}));
+        assert!(matches!(
+            read_reference(&path, 36),
+            Err(Error::Content { .. })
+        ));
+    }
+}
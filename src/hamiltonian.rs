@@ -1,7 +1,8 @@
+use crate::ising::Ising;
+use crate::lattice::Lattice;
 use crate::Jxx;
-use crate::NODES;
-use serde_json::{json, Map, Value};
-use std::collections::HashMap;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
 
 // Check direction of the nodes
@@ -11,179 +12,124 @@ enum Direction {
     BtmRight,
 }
 
-// Make new object for the fujitsu input json
-fn new_obj(cof: f64, vec: &Vec<i32>) -> Map<String, Value> {
-    let mut obj = Map::new();
-    obj.insert("c".to_string(), json!(cof));
-    if vec.len() == 1 && vec[0] == -1 {
-        // Constant term
-        return obj;
-    }
-    obj.insert("p".to_string(), json!(vec));
-    return obj;
-}
-
-// Get the front part of the Hamiltonian function
-fn get_front(i: i32, h: i32, jxx: &Jxx, without_cycle: bool) -> Vec<(f64, Vec<i32>)> {
+// Get the front part of the Hamiltonian function, as (k, i, j) bonds of k s_i s_j
+fn get_front(
+    i: i32,
+    h: i32,
+    jxx: &Jxx,
+    lattice: &Lattice,
+    without_cycle: bool,
+) -> Vec<(f64, i32, i32)> {
     #![allow(non_snake_case)]
     let L2: i32 = jxx.l * jxx.l; // L^2
     let idx: usize = usize::try_from((h * L2) + i).unwrap();
-    let mut outer_vec: Vec<(f64, Vec<i32>)> = Vec::new();
-
-    unsafe {
-        let (right_idx, right_j): (usize, f64) = (NODES[idx].right as usize, NODES[idx].j_right);
-
-        let (bottom_idx, bottom_j): (usize, f64) =
-            (NODES[idx].bottom as usize, NODES[idx].j_bottom);
-
-        let (btm_right_idx, btm_right_j): (usize, f64) =
-            (NODES[idx].btm_right as usize, NODES[idx].j_btm_right);
-
-        let iter_vec: Vec<(usize, f64, Direction)> = vec![
-            (right_idx, right_j, Direction::Right),
-            (bottom_idx, bottom_j, Direction::Bottom),
-            (btm_right_idx, btm_right_j, Direction::BtmRight),
-        ];
-
-        for iter in iter_vec {
-            let (iter_idx, iter_j, direction) = iter;
-
-            // k: strength between two nodes (iter_j)
-            // polynomial: 4k s_{i, n} s_{j, n} - 2k s_{i, n} - 2k s_{j, n} + k
-            if without_cycle && is_cycle(idx, iter_idx, jxx.l, direction) {
-                // check cycle
-                continue;
-            } else {
-                outer_vec.push((4.0 * iter_j, vec![idx as i32, iter_idx as i32])); // 4k s_{i, n} s_{j, n}
-                outer_vec.push((-2.0 * iter_j, vec![idx as i32])); // -2k s_{i, n}
-                outer_vec.push((-2.0 * iter_j, vec![iter_idx as i32])); // -2k s_{j, n}
-                outer_vec.push((iter_j, vec![-1])); // k (Constant term)
-            }
+    let mut outer_vec: Vec<(f64, i32, i32)> = Vec::new();
+
+    let node = &lattice.nodes[idx];
+    let (right_idx, right_j): (usize, f64) = (node.right as usize, node.j_right);
+
+    let (bottom_idx, bottom_j): (usize, f64) = (node.bottom as usize, node.j_bottom);
+
+    let (btm_right_idx, btm_right_j): (usize, f64) = (node.btm_right as usize, node.j_btm_right);
+
+    let iter_vec: Vec<(usize, f64, Direction)> = vec![
+        (right_idx, right_j, Direction::Right),
+        (bottom_idx, bottom_j, Direction::Bottom),
+        (btm_right_idx, btm_right_j, Direction::BtmRight),
+    ];
+
+    for iter in iter_vec {
+        let (iter_idx, iter_j, direction) = iter;
+
+        // k: strength between two nodes (iter_j), k s_{i, n} s_{j, n}
+        if without_cycle && is_cycle(idx, iter_idx, jxx.l, direction) {
+            // check cycle
+            continue;
+        } else {
+            outer_vec.push((iter_j, idx as i32, iter_idx as i32));
         }
     }
 
     outer_vec
 }
 
-// Get the back part of the Hamiltonian function
-fn get_back(idx: i32, without_cycle: bool) -> Vec<(f64, Vec<i32>)> {
-    let mut outer_vec: Vec<(f64, Vec<i32>)> = Vec::new();
-
-    unsafe {
-        let (mut cur_idx, mut next_idx) = (idx, NODES[idx as usize].layer_up);
-        loop {
-            let j_layer_up = NODES[cur_idx as usize].j_layer_up; // Get the strength of the bond
-
-            // polynomial: 4k s_{i, n} s_{i, n+1} - 2k s_{i, n} - 2k s_{i, n+1} + k
-            let cof4k: f64 = 4.0 * j_layer_up;
-            let cof2k: f64 = -2.0 * j_layer_up;
-            let cof_constant: f64 = j_layer_up;
-
-            // Add negative sign to the coefficient (0.0 - cof)
-            outer_vec.push((0.0 - cof4k, vec![cur_idx, next_idx]));
-            outer_vec.push((0.0 - cof2k, vec![cur_idx]));
-            outer_vec.push((0.0 - cof2k, vec![next_idx]));
-            outer_vec.push((0.0 - cof_constant, vec![-1]));
-
-            // Check if the next loop will reach the cycle
-            if without_cycle && next_idx - 1 == idx {
-                // When next_idx - 1 == idx, it means that next loop will reach the cycle
-                break;
-            }
+// Get the back part of the Hamiltonian function, as (-k, i, j) bonds of -k s_{i, n} s_{i, n+1}
+fn get_back(idx: i32, lattice: &Lattice, without_cycle: bool) -> Vec<(f64, i32, i32)> {
+    let mut outer_vec: Vec<(f64, i32, i32)> = Vec::new();
 
-            if next_idx == idx {
-                // When next_idx == idx, it means that we have reached a cycle
-                break;
-            }
+    let (mut cur_idx, mut next_idx) = (idx, lattice.nodes[idx as usize].layer_up);
+    loop {
+        let j_layer_up = lattice.nodes[cur_idx as usize].j_layer_up; // Get the strength of the bond
+
+        // Add negative sign to the coefficient (0.0 - k)
+        outer_vec.push((0.0 - j_layer_up, cur_idx, next_idx));
+
+        // Check if the next loop will reach the cycle
+        if without_cycle && next_idx - 1 == idx {
+            // When next_idx - 1 == idx, it means that next loop will reach the cycle
+            break;
+        }
 
-            cur_idx = next_idx;
-            next_idx = NODES[cur_idx as usize].layer_up;
+        if next_idx == idx {
+            // When next_idx == idx, it means that we have reached a cycle
+            break;
         }
+
+        cur_idx = next_idx;
+        next_idx = lattice.nodes[cur_idx as usize].layer_up;
     }
 
     outer_vec
 }
 
-pub fn hamiltonian_eff(jxx: &Jxx, without_cycle: bool) -> Value {
+// Spin form of H_eff: J of every bond, no field and no offset
+pub fn ising_eff(jxx: &Jxx, lattice: &Lattice, without_cycle: bool) -> Ising {
     // H_{eff} = \sum{K s_{i, n} s_{j, n}} - \sum{K' s_{i, n} s_{i, n+1}}
     // sum1 -> i, j is a pair and n is the idx of layer; sum2 -> i is the idx of layer.
     #![allow(non_snake_case)]
     let L2: i32 = jxx.l * jxx.l; // L^2
     let height: i32 = jxx.h; // Height of the triangular lattice
 
-    let mut fujitsu = json!({
-        "fujitsuDA3": {},
-        "binary_polynomial": {
-            "terms": []
-        }
-    });
-
-    let term_list = fujitsu["binary_polynomial"]["terms"]
-        .as_array_mut()
-        .unwrap();
-
-    // Build map for term consolidation
-    let mut term_map: HashMap<Vec<i32>, f64> = HashMap::new();
-    let mut constant_term: f64 = 0.0;
-
+    let mut bonds: Vec<(f64, i32, i32)> = Vec::new();
     for h in 0..height {
         for i in 0..L2 {
-            let iter: Vec<(f64, Vec<i32>)> = get_front(i, h, jxx, without_cycle);
-            for mut it in iter {
-                it.1.sort();
-                // it.0: f64 = coefficient, it.1: Vec<i32> = polynomial
-                if it.1.len() == 1 && it.1[0] == -1 {
-                    // constant term
-                    constant_term += it.0;
-                } else {
-                    // term_map.insert(it.1, it.0);
-                    if let Some((_k, v)) = term_map.get_key_value(&it.1) {
-                        term_map.insert(it.1, v + it.0);
-                    } else {
-                        term_map.insert(it.1, it.0);
-                    }
-                }
-            }
+            bonds.extend(get_front(i, h, jxx, lattice, without_cycle));
         }
     }
-
-    if height == 1 {
-        for (k, v) in term_map.iter() {
-            term_list.push(Value::Object(new_obj(*v, k)));
-        }
-        if constant_term != 0.0 {
-            term_list.push(Value::Object(new_obj(constant_term, &vec![-1])));
+    if height > 1 {
+        for i in 0..L2 {
+            bonds.extend(get_back(i, lattice, without_cycle));
         }
-        return fujitsu;
     }
 
-    for i in 0..L2 {
-        let iter: Vec<(f64, Vec<i32>)> = get_back(i, without_cycle);
-        for mut it in iter {
-            it.1.sort();
-            // it.0: f64 = coefficient, it.1: Vec<i32> = polynomial
-            if it.1.len() == 1 && it.1[0] == -1 {
-                // constant term
-                constant_term += it.0;
-            } else {
-                // term_map.insert(it.1, it.0);
-                if let Some((_k, v)) = term_map.get_key_value(&it.1) {
-                    term_map.insert(it.1, v + it.0);
-                } else {
-                    term_map.insert(it.1, it.0);
-                }
-            }
+    // Consolidate the bonds of the same pair
+    let mut bond_map: BTreeMap<(usize, usize), f64> = BTreeMap::new();
+    let mut offset: f64 = 0.0;
+    for (k, a, b) in bonds {
+        let (a, b): (usize, usize) = (a.min(b) as usize, a.max(b) as usize);
+        if a == b {
+            offset += k; // s_i s_i = 1
+        } else {
+            *bond_map.entry((a, b)).or_insert(0.0) += k;
         }
     }
 
-    for (k, v) in term_map.iter() {
-        term_list.push(Value::Object(new_obj(*v, k)));
-    }
-    if constant_term != 0.0 {
-        term_list.push(Value::Object(new_obj(constant_term, &vec![-1])));
+    let n: i32 = L2 * height;
+    Ising {
+        variables: (0..n).collect(),
+        h: vec![0.0; n as usize],
+        j: bond_map.into_iter().map(|((a, b), k)| (a, b, k)).collect(),
+        offset,
     }
+}
 
-    fujitsu
+// Request of H_eff (from `ising_eff`) in the QUBO (`binary_polynomial`) or Ising (`ising`) form
+pub fn hamiltonian_eff(ising: &Ising, form: &str) -> Value {
+    match form {
+        "qubo" => ising.to_polynomial().to_value(),
+        "ising" => json!({"fujitsuDA3": {}, "ising": ising.to_value()}),
+        _ => panic!("Unknown form: {} (qubo, ising)", form),
+    }
 }
 
 // Check if the 2 nodes reach the cycle (without_cycle = true) (for the front part of the Hamiltonian function)
@@ -205,5 +151,5 @@ fn is_cycle(idx: usize, iter_idx: usize, side_length: i32, direction: Direction)
             }
         }
     }
-    return false;
+    false
 }
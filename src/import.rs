@@ -0,0 +1,402 @@
+// Purpose: Import external QUBO/Ising instances as the binary_polynomial of a request
+use crate::error::{Error, Result};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+
+/*
+ * Supported inputs (detected from the file name, then the content):
+ *   *.json with binary_polynomial - a request, used as is (or with ising, the Ising form)
+ *   *.json with linear/quadratic  - BQM, vartype BINARY or SPIN (s = 2x - 1)
+ *   *.qubo                        - qbsolv, "c" comments and "p qubo ..." header skipped
+ *   *.mtx                         - Matrix Market coordinate, 1-based indices, general or symmetric
+ *                                   (the entries of a symmetric matrix stand for both Q_{i, j} and Q_{j, i})
+ *   first line "n m"              - Gset Max-Cut edge list "i j w", 1-based indices, after the "#" comments
+ *   otherwise                     - sparse "i j value" lines
+ * The offset written by `export` ("c offset", "# offset", "% offset") is read back as a constant term.
+ * Errors name the line (text files) or the JSON pointer (BQM) of the invalid value.
+ * Max-Cut maximises \sum_{(i, j)} w_{i, j} (x_i + x_j - 2 x_i x_j), so the energy is minus the cut.
+ */
+type RawTerms = Vec<(f64, Vec<i32>)>;
+
+// `token` of line `number` (1-based) of a text file
+fn parse_number<T: std::str::FromStr>(token: &str, number: usize) -> Result<T> {
+    token.parse().map_err(|_| {
+        Error::content(
+            format!("line {}", number),
+            format!("invalid number {:?}", token),
+        )
+    })
+}
+
+// Variable index `token` of line `number`, shifted by `base` (1 for the 1-based formats)
+fn parse_index(token: &str, base: i32, number: usize) -> Result<i32> {
+    let index: i32 = parse_number::<i32>(token, number)? - base;
+    if index < 0 {
+        return Err(Error::content(
+            format!("line {}", number),
+            format!("index {} is below the first one, {}", token, base),
+        ));
+    }
+    Ok(index)
+}
+
+// Upper-triangular `i j value` entries (diagonal = linear term), shifted by `base`
+fn matrix_terms(text: &str, comments: &[&str], skip_header: bool, base: i32) -> Result<RawTerms> {
+    let mut raw_terms: RawTerms = Vec::new();
+    let mut header_skipped: bool = !skip_header;
+    for (k, line) in text.lines().enumerate() {
+        let (number, line): (usize, &str) = (k + 1, line.trim());
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(prefix) = comments.iter().find(|prefix| line.starts_with(**prefix)) {
+            let comment: Vec<&str> = line[prefix.len()..].split_whitespace().collect();
+            if let ["offset", value] = comment.as_slice() {
+                raw_terms.push((parse_number(value, number)?, Vec::new()));
+            }
+            continue;
+        }
+        if line.starts_with("p ") || !header_skipped {
+            header_skipped = true; // "p qubo ..." or the Matrix Market size line
+            continue;
+        }
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.len() != 3 {
+            return Err(Error::content(
+                format!("line {}", number),
+                format!("expected \"i j value\", got {:?}", line),
+            ));
+        }
+        let i: i32 = parse_index(tokens[0], base, number)?;
+        let j: i32 = parse_index(tokens[1], base, number)?;
+        let value: f64 = parse_number(tokens[2], number)?;
+        if i == j {
+            raw_terms.push((value, vec![i]));
+        } else {
+            raw_terms.push((value, vec![i, j]));
+        }
+    }
+    Ok(raw_terms)
+}
+
+// Lines of a Gset or sparse file which are neither empty nor "#" comments, as (line number, line)
+fn content_lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .map(|(k, line)| (k + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+}
+
+// Whether the "%%MatrixMarket" banner declares a symmetric matrix
+fn is_symmetric(text: &str) -> Result<bool> {
+    let banner: Vec<String> = match text.lines().next() {
+        Some(line) if line.starts_with("%%MatrixMarket") => {
+            line.split_whitespace().map(str::to_lowercase).collect()
+        }
+        _ => return Ok(false), // No banner: general
+    };
+    match banner.get(4).map(String::as_str) {
+        None | Some("general") => Ok(false),
+        Some("symmetric") => Ok(true),
+        Some(symmetry) => Err(Error::content(
+            "line 1",
+            format!("unsupported symmetry {:?} (general, symmetric)", symmetry),
+        )),
+    }
+}
+
+// Count the off-diagonal entries of a symmetric matrix for both triangles
+fn mirror(raw_terms: RawTerms) -> RawTerms {
+    raw_terms
+        .into_iter()
+        .map(|(c, p)| if p.len() == 2 { (2.0 * c, p) } else { (c, p) })
+        .collect()
+}
+
+fn gset_terms(text: &str) -> Result<RawTerms> {
+    let mut raw_terms: RawTerms = Vec::new();
+    for (number, line) in content_lines(text).skip(1) {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            [i, j, w] => {
+                let i: i32 = parse_index(i, 1, number)?;
+                let j: i32 = parse_index(j, 1, number)?;
+                let w: f64 = parse_number(w, number)?;
+                raw_terms.push((-w, vec![i]));
+                raw_terms.push((-w, vec![j]));
+                raw_terms.push((2.0 * w, vec![i, j]));
+            }
+            _ => {
+                return Err(Error::content(
+                    format!("line {}", number),
+                    format!("expected \"i j w\", got {:?}", line),
+                ))
+            }
+        }
+    }
+    Ok(raw_terms)
+}
+
+fn bqm_terms(bqm: &Value) -> Result<RawTerms> {
+    let spin: bool = match bqm["vartype"].as_str().unwrap_or("BINARY") {
+        "BINARY" => false,
+        "SPIN" => true,
+        vartype => {
+            return Err(Error::content(
+                "/vartype",
+                format!("unknown vartype {:?} (BINARY, SPIN)", vartype),
+            ))
+        }
+    };
+    let index = |key: &str, pointer: &str| -> Result<i32> {
+        key.trim()
+            .parse()
+            .map_err(|_| Error::content(pointer, format!("{:?} is not a variable index", key)))
+    };
+    let mut raw_terms: RawTerms = vec![(bqm["offset"].as_f64().unwrap_or(0.0), Vec::new())];
+
+    if let Some(linear) = bqm["linear"].as_object() {
+        for (key, value) in linear {
+            let pointer: String = format!("/linear/{}", key);
+            let i: i32 = index(key, &pointer)?;
+            let h: f64 = value
+                .as_f64()
+                .ok_or_else(|| Error::content(pointer.clone(), "not a number"))?;
+            if spin {
+                // h s_i -> 2h x_i - h
+                raw_terms.push((2.0 * h, vec![i]));
+                raw_terms.push((-h, Vec::new()));
+            } else {
+                raw_terms.push((h, vec![i]));
+            }
+        }
+    }
+
+    // [[i, j, value], ...] or {"i,j": value, ...}
+    let mut quadratic: Vec<(i32, i32, f64)> = Vec::new();
+    match &bqm["quadratic"] {
+        Value::Array(list) => {
+            for (k, entry) in list.iter().enumerate() {
+                match (entry[0].as_i64(), entry[1].as_i64(), entry[2].as_f64()) {
+                    (Some(i), Some(j), Some(value)) => quadratic.push((i as i32, j as i32, value)),
+                    _ => {
+                        return Err(Error::content(
+                            format!("/quadratic/{}", k),
+                            "not [i, j, value]",
+                        ))
+                    }
+                }
+            }
+        }
+        Value::Object(map) => {
+            for (key, value) in map {
+                let pointer: String = format!("/quadratic/{}", key);
+                let (i, j): (&str, &str) = key
+                    .split_once(',')
+                    .ok_or_else(|| Error::content(pointer.clone(), "key is not \"i,j\""))?;
+                let value: f64 = value
+                    .as_f64()
+                    .ok_or_else(|| Error::content(pointer.clone(), "not a number"))?;
+                quadratic.push((index(i, &pointer)?, index(j, &pointer)?, value));
+            }
+        }
+        Value::Null => (),
+        _ => return Err(Error::content("/quadratic", "neither a list nor a map")),
+    }
+    for (i, j, coupling) in quadratic {
+        if spin {
+            // J s_i s_j -> 4J x_i x_j - 2J x_i - 2J x_j + J
+            raw_terms.push((4.0 * coupling, vec![i, j]));
+            raw_terms.push((-2.0 * coupling, vec![i]));
+            raw_terms.push((-2.0 * coupling, vec![j]));
+            raw_terms.push((coupling, Vec::new()));
+        } else {
+            raw_terms.push((coupling, vec![i, j]));
+        }
+    }
+    Ok(raw_terms)
+}
+
+// `{"binary_polynomial": {"terms": [...]}}` with an empty `fujitsuDA3`, like `hamiltonian_eff`
+fn request_value(raw_terms: RawTerms) -> Value {
+    let term_list: Vec<Value> = raw_terms
+        .into_iter()
+        .filter(|(c, _)| *c != 0.0)
+        .map(|(c, p)| {
+            if p.is_empty() {
+                json!({"c": c})
+            } else {
+                json!({"c": c, "p": p})
+            }
+        })
+        .collect();
+    json!({
+        "fujitsuDA3": {},
+        "binary_polynomial": {
+            "terms": term_list
+        }
+    })
+}
+
+// Read any supported file, returns the request and whether it had to be converted
+pub fn import(input_path: &str) -> Result<(Value, bool)> {
+    let text: String = fs::read_to_string(input_path).map_err(|e| Error::io(input_path, e))?;
+    let extension: String = Path::new(input_path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    let raw_terms: Result<RawTerms> = match extension.as_str() {
+        "json" => {
+            let value: Value =
+                serde_json::from_str(&text).map_err(|e| Error::syntax(input_path, e))?;
+            if value.get("binary_polynomial").is_some() || value.get("ising").is_some() {
+                return Ok((value, false));
+            }
+            if value.get("linear").is_none() && value.get("quadratic").is_none() {
+                return Err(Error::syntax(input_path, "neither a request nor a BQM"));
+            }
+            println!("Importing BQM {}...", input_path);
+            bqm_terms(&value)
+        }
+        "qubo" => {
+            println!("Importing qbsolv {}...", input_path);
+            matrix_terms(&text, &["c"], false, 0)
+        }
+        "mtx" => {
+            println!("Importing Matrix Market {}...", input_path);
+            match is_symmetric(&text) {
+                Ok(true) => matrix_terms(&text, &["%"], true, 1).map(mirror),
+                Ok(false) => matrix_terms(&text, &["%"], true, 1),
+                Err(e) => Err(e),
+            }
+        }
+        _ => {
+            let first: Option<(usize, &str)> = content_lines(&text).next();
+            match first.map(|(_, line)| line.split_whitespace().count()) {
+                Some(2) => {
+                    println!("Importing Gset Max-Cut {} (energy = -cut)...", input_path);
+                    gset_terms(&text)
+                }
+                _ => {
+                    println!("Importing sparse {}...", input_path);
+                    matrix_terms(&text, &["#"], false, 0)
+                }
+            }
+        }
+    };
+    let raw_terms: RawTerms = raw_terms.map_err(|e| e.in_file(input_path))?;
+    Ok((request_value(raw_terms), true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{export, FORMATS};
+    use crate::polynomial::tests::configurations;
+    use crate::polynomial::Polynomial;
+    use crate::random::get_rng;
+    use rand::Rng;
+
+    fn energies(polynomial: &Polynomial, n: usize) -> Vec<f64> {
+        // Every variable of 0..n, missing ones of the polynomial do not change the energy
+        configurations(n)
+            .map(|x| {
+                let x: Vec<bool> = polynomial
+                    .variables
+                    .iter()
+                    .map(|i| x[*i as usize])
+                    .collect();
+                polynomial.energy(&x)
+            })
+            .collect()
+    }
+
+    fn import_text(name: &str, text: &str) -> Polynomial {
+        let directory = std::env::temp_dir().join(format!("fujitsu-import-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(name);
+        fs::write(&path, text).unwrap();
+        let (request, converted) = import(path.to_str().unwrap()).unwrap();
+        assert!(converted);
+        Polynomial::from_value(&request).unwrap()
+    }
+
+    #[test]
+    fn every_export_format_reads_back() {
+        let mut rng = get_rng(Some(4));
+        let mut raw_terms: RawTerms = vec![(rng.gen_range(-2.0..2.0), Vec::new())];
+        for i in 0..6 {
+            raw_terms.push((rng.gen_range(-2.0..2.0), vec![i]));
+            for j in i + 1..6 {
+                raw_terms.push((rng.gen_range(-2.0..2.0), vec![i, j]));
+            }
+        }
+        let polynomial: Polynomial = Polynomial::new(raw_terms);
+
+        let directory = std::env::temp_dir().join(format!("fujitsu-export-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let input_path: String = directory.join("input.json").to_string_lossy().to_string();
+        for (format, _) in FORMATS {
+            let path: String = export(&polynomial, format, &input_path).unwrap();
+            let (request, _) = import(&path).unwrap();
+            let read: Polynomial = Polynomial::from_value(&request).unwrap();
+            let (expected, got) = (energies(&polynomial, 6), energies(&read, 6));
+            for (a, b) in expected.iter().zip(&got) {
+                assert!((a - b).abs() < 1e-9, "{}: {} != {}", format, a, b);
+            }
+        }
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn symmetric_matrix_market_counts_both_triangles() {
+        let entries: &str = "3 3 3\n1 1 1.0\n2 1 -3\n3 3 0.5\n";
+        let general: Polynomial = import_text(
+            "general.mtx",
+            &format!("%%MatrixMarket matrix coordinate real general\n{}", entries),
+        );
+        let symmetric: Polynomial = import_text(
+            "symmetric.mtx",
+            &format!(
+                "%%MatrixMarket matrix coordinate real symmetric\n{}",
+                entries
+            ),
+        );
+        assert_eq!(general.energy(&[true, true, false]), 1.0 - 3.0);
+        assert_eq!(symmetric.energy(&[true, true, false]), 1.0 - 6.0);
+        assert_eq!(symmetric.energy(&[false, false, true]), 0.5);
+        assert!(is_symmetric("%%MatrixMarket matrix coordinate real skew-symmetric\n").is_err());
+    }
+
+    #[test]
+    fn gset_skips_leading_comments() {
+        // Path 1 - 2 - 3: x = (1, 0, 1) cuts both edges
+        for text in [
+            "3 2\n1 2 1\n2 3 1\n",
+            "# G-set\n\n# two edges\n3 2\n1 2 1\n2 3 1\n",
+        ] {
+            let polynomial: Polynomial = import_text("path.txt", text);
+            assert_eq!(polynomial.energy(&[true, false, true]), -2.0);
+            assert_eq!(polynomial.energy(&[true, true, true]), 0.0);
+        }
+    }
+
+    #[test]
+    fn index_below_the_base_names_its_line() {
+        let mtx: &str = "%%MatrixMarket matrix coordinate real general\n3 3 2\n1 1 1.0\n0 2 -3\n";
+        let gset: &str = "3 2\n1 2 1\n2 0 1\n";
+        let sparse: &str = "0 1 1.0\n-1 1 2.0\n";
+        for (result, line) in [
+            (matrix_terms(mtx, &["%"], true, 1), "line 4"),
+            (gset_terms(gset), "line 3"),
+            (matrix_terms(sparse, &[], false, 0), "line 2"),
+        ] {
+            match result {
+                Err(e @ Error::Content { .. }) => assert!(e.to_string().contains(line), "{}", e),
+                _ => panic!("a negative index must be a content error"),
+            }
+        }
+    }
+}
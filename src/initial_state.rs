@@ -0,0 +1,130 @@
+// Purpose: Synthetic spin states of the lattice, written as guidance_config or used as the start of the local solvers
+use crate::lattice::Lattice;
+use crate::random::get_rng;
+use crate::solution::configuration_value;
+use crate::SubLattice;
+use rand::Rng;
+use serde_json::Value;
+
+pub const STATES: [&str; 5] = ["uud", "udz", "stripe", "ferro", "random"];
+
+/*
+ * Spin of every node (up: x = 1), the same on every Trotter layer but the random ones:
+ *   uud     sqrt3 x sqrt3 up-up-down, RED and GREEN up, BLUE down
+ *   udz     sqrt3 x sqrt3 up-down-zero, RED up, GREEN down, BLUE random (zero on average)
+ *   stripe  rows i alternately up and down (periodic for an even L)
+ *   ferro   every spin up
+ *   random  every spin random
+ * The random spins are reproducible with `seed`.
+ */
+pub fn set_state(lattice: &mut Lattice, state: &str, seed: Option<u64>) {
+    let mut rng = get_rng(seed);
+    let length: i32 = lattice.l;
+    for node in lattice.nodes.iter_mut() {
+        let row: i32 = (node.index % (length * length)) / length;
+        node.spin = match (state, &node.sub_lattice) {
+            ("uud", SubLattice::BLUE) => false,
+            ("uud", _) => true,
+            ("udz", SubLattice::RED) => true,
+            ("udz", SubLattice::GREEN) => false,
+            ("stripe", _) => row % 2 == 0,
+            ("ferro", _) => true,
+            ("udz", SubLattice::BLUE) | ("random", _) => rng.gen(),
+            _ => panic!("Unknown state: {}", state), // Checked by the flag
+        };
+    }
+}
+
+// `{"index": bool, ...}` of the spins of the lattice
+pub fn state_value(lattice: &Lattice) -> Value {
+    let indices: Vec<i32> = lattice.nodes.iter().map(|node| node.index).collect();
+    let spins: Vec<bool> = lattice.nodes.iter().map(|node| node.spin).collect();
+    configuration_value(&indices, &spins)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_vector, Jxx};
+
+    // 6 x 6 x 3 lattice, 108 spins
+    fn lattice_in(state: &str, seed: Option<u64>) -> Lattice {
+        let mut lattice: Lattice = create_vector(&Jxx {
+            j: 1.0,
+            jl: 0.5,
+            l: 6,
+            h: 3,
+            gamma: 0.5,
+        });
+        set_state(&mut lattice, state, seed);
+        lattice
+    }
+
+    fn spins(lattice: &Lattice) -> Vec<bool> {
+        lattice.nodes.iter().map(|node| node.spin).collect()
+    }
+
+    #[test]
+    fn uud_is_down_on_blue_only() {
+        let lattice: Lattice = lattice_in("uud", None);
+        for node in &lattice.nodes {
+            let up: bool = !matches!(node.sub_lattice, SubLattice::BLUE);
+            assert_eq!(node.spin, up, "{}", node.index);
+        }
+    }
+
+    #[test]
+    fn udz_is_up_on_red_and_down_on_green() {
+        let lattice: Lattice = lattice_in("udz", Some(2));
+        for node in &lattice.nodes {
+            match node.sub_lattice {
+                SubLattice::RED => assert!(node.spin),
+                SubLattice::GREEN => assert!(!node.spin),
+                SubLattice::BLUE => (),
+            }
+        }
+        let blue: Vec<bool> = lattice
+            .nodes
+            .iter()
+            .filter(|node| matches!(node.sub_lattice, SubLattice::BLUE))
+            .map(|node| node.spin)
+            .collect();
+        assert!(blue.contains(&true) && blue.contains(&false));
+    }
+
+    #[test]
+    fn stripe_alternates_the_rows() {
+        let lattice: Lattice = lattice_in("stripe", None);
+        for node in &lattice.nodes {
+            let (_, i, _) = lattice.get_hij(node.index);
+            assert_eq!(node.spin, i % 2 == 0, "{}", node.index);
+        }
+    }
+
+    #[test]
+    fn ferro_is_up_everywhere() {
+        assert!(spins(&lattice_in("ferro", None)).iter().all(|spin| *spin));
+    }
+
+    #[test]
+    fn seeded_random_is_reproducible() {
+        let first: Vec<bool> = spins(&lattice_in("random", Some(5)));
+        assert_eq!(spins(&lattice_in("random", Some(5))), first);
+        assert_ne!(spins(&lattice_in("random", Some(6))), first);
+        assert!(first.contains(&true) && first.contains(&false));
+        assert_eq!(
+            spins(&lattice_in("udz", Some(5))),
+            spins(&lattice_in("udz", Some(5)))
+        );
+    }
+
+    #[test]
+    fn state_value_maps_every_index() {
+        let lattice: Lattice = lattice_in("uud", None);
+        let value: Value = state_value(&lattice);
+        assert_eq!(value.as_object().unwrap().len(), lattice.nodes.len());
+        for node in &lattice.nodes {
+            assert_eq!(value[node.index.to_string()], Value::Bool(node.spin));
+        }
+    }
+}
@@ -0,0 +1,289 @@
+// Purpose: Contains the Ising struct (spin form s = 2x - 1 of a quadratic binary polynomial)
+use crate::error::{Error, Result};
+use crate::polynomial::Polynomial;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+/*
+ * E(s) = offset + \sum_i h_i s_i + \sum_{i < j} J_{i, j} s_i s_j,  s_i \in {+1, -1}
+ * From x = (s + 1) / 2 (QUBO -> Ising):
+ *   c x_i x_j -> c/4 s_i s_j + c/4 s_i + c/4 s_j + c/4
+ *   c x_i     -> c/2 s_i + c/2
+ * From s = 2x - 1 (Ising -> QUBO):
+ *   J s_i s_j -> 4J x_i x_j - 2J x_i - 2J x_j + J
+ *   h s_i     -> 2h x_i - h
+ * Both keep the energy of every configuration, constant included.
+ *
+ * Request form: {"fujitsuDA3": {...}, "ising": {"h": {"0": 0.0, ...}, "J": [[0, 1, 1.0], ...], "offset": 0.0}}
+ */
+pub struct Ising {
+    pub variables: Vec<i32>,         // Sorted variable indices, position -> index
+    pub h: Vec<f64>,                 // Linear coefficient of each spin
+    pub j: Vec<(usize, usize, f64)>, // Couplings (i < j) between the spin positions
+    pub offset: f64,                 // Constant term
+}
+
+impl Ising {
+    pub fn from_polynomial(polynomial: &Polynomial) -> Result<Ising> {
+        let n: usize = polynomial.len();
+        let mut h: Vec<f64> = vec![0.0; n];
+        let mut j: Vec<(usize, usize, f64)> = Vec::new();
+        let mut offset: f64 = polynomial.constant;
+
+        for (c, positions) in &polynomial.terms {
+            match positions.as_slice() {
+                [a] => {
+                    h[*a] += c / 2.0;
+                    offset += c / 2.0;
+                }
+                [a, b] => {
+                    j.push((*a, *b, c / 4.0));
+                    h[*a] += c / 4.0;
+                    h[*b] += c / 4.0;
+                    offset += c / 4.0;
+                }
+                _ => {
+                    return Err(Error::content(
+                        "/binary_polynomial",
+                        format!(
+                            "only quadratic polynomials have an Ising form (term of order {})",
+                            positions.len()
+                        ),
+                    ))
+                }
+            }
+        }
+
+        Ok(Ising {
+            variables: polynomial.variables.clone(),
+            h,
+            j,
+            offset,
+        })
+    }
+
+    // Ising -> QUBO, equal terms merged in (sorted) polynomial order
+    pub fn to_polynomial(&self) -> Polynomial {
+        let mut term_map: BTreeMap<Vec<i32>, f64> = BTreeMap::new();
+        let mut constant: f64 = self.offset;
+        for (a, h) in self.h.iter().enumerate() {
+            *term_map.entry(vec![self.variables[a]]).or_insert(0.0) += 2.0 * h;
+            constant -= h;
+        }
+        for (a, b, j) in &self.j {
+            let (va, vb): (i32, i32) = (self.variables[*a], self.variables[*b]);
+            *term_map.entry(vec![va.min(vb), va.max(vb)]).or_insert(0.0) += 4.0 * j;
+            *term_map.entry(vec![va]).or_insert(0.0) -= 2.0 * j;
+            *term_map.entry(vec![vb]).or_insert(0.0) -= 2.0 * j;
+            constant += j;
+        }
+
+        let mut raw_terms: Vec<(f64, Vec<i32>)> =
+            term_map.into_iter().map(|(p, c)| (c, p)).collect();
+        if constant != 0.0 {
+            raw_terms.push((constant, Vec::new()));
+        }
+        Polynomial::new(raw_terms)
+    }
+
+    // Read the `ising` object of a request
+    pub fn from_value(ising: &Value) -> Result<Ising> {
+        let mut h_map: BTreeMap<i32, f64> = BTreeMap::new();
+        if let Some(h) = ising["h"].as_object() {
+            for (key, value) in h {
+                match (key.parse(), value.as_f64()) {
+                    (Ok(index), Some(value)) => {
+                        h_map.insert(index, value);
+                    }
+                    _ => {
+                        return Err(Error::content(
+                            format!("/ising/h/{}", key),
+                            "not a number of a variable index",
+                        ))
+                    }
+                }
+            }
+        }
+        let mut couplings: Vec<(i32, i32, f64)> = Vec::new();
+        if let Some(list) = ising["J"].as_array() {
+            for (k, entry) in list.iter().enumerate() {
+                match (entry[0].as_i64(), entry[1].as_i64(), entry[2].as_f64()) {
+                    (Some(a), Some(b), Some(value)) => couplings.push((a as i32, b as i32, value)),
+                    _ => {
+                        return Err(Error::content(
+                            format!("/ising/J/{}", k),
+                            "not [i, j, value]",
+                        ))
+                    }
+                }
+            }
+        }
+
+        let variables: Vec<i32> = h_map
+            .keys()
+            .copied()
+            .chain(couplings.iter().flat_map(|(a, b, _)| [*a, *b]))
+            .collect::<BTreeSet<i32>>()
+            .into_iter()
+            .collect();
+        let position = |index: &i32| -> usize { variables.binary_search(index).unwrap() };
+
+        let mut h: Vec<f64> = vec![0.0; variables.len()];
+        for (index, value) in &h_map {
+            h[position(index)] += value;
+        }
+        let mut offset: f64 = ising["offset"].as_f64().unwrap_or(0.0);
+        let mut j: Vec<(usize, usize, f64)> = Vec::new();
+        for (a, b, value) in &couplings {
+            let (a, b): (usize, usize) = (position(a), position(b));
+            match a.cmp(&b) {
+                std::cmp::Ordering::Equal => offset += value, // s_i s_i = 1
+                std::cmp::Ordering::Less => j.push((a, b, *value)),
+                std::cmp::Ordering::Greater => j.push((b, a, *value)),
+            }
+        }
+
+        Ok(Ising {
+            variables,
+            h,
+            j,
+            offset,
+        })
+    }
+
+    pub fn to_value(&self) -> Value {
+        let mut h = Map::new();
+        for (index, value) in self.variables.iter().zip(&self.h) {
+            h.insert(index.to_string(), json!(value));
+        }
+        let couplings: Vec<Value> = self
+            .j
+            .iter()
+            .map(|(a, b, j)| json!([self.variables[*a], self.variables[*b], j]))
+            .collect();
+        json!({"h": h, "J": couplings, "offset": self.offset})
+    }
+
+    pub fn len(&self) -> usize {
+        self.h.len()
+    }
+
+    pub fn energy(&self, s: &[f64]) -> f64 {
+        let mut sum: f64 = self.offset;
+        for (i, h) in self.h.iter().enumerate() {
+            sum += h * s[i];
+        }
+        for (a, b, j) in &self.j {
+            sum += j * s[*a] * s[*b];
+        }
+        sum
+    }
+
+    // Couplings of each spin as (other spin, J), both directions
+    pub fn adjacency(&self) -> Vec<Vec<(usize, f64)>> {
+        let mut adjacency: Vec<Vec<(usize, f64)>> = vec![Vec::new(); self.len()];
+        for (a, b, j) in &self.j {
+            adjacency[*a].push((*b, *j));
+            adjacency[*b].push((*a, *j));
+        }
+        adjacency
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polynomial::tests::configurations;
+    use crate::random::get_rng;
+    use rand::Rng;
+
+    // Random linear and quadratic terms of n variables (each pair once) and a constant
+    fn random_quadratic(n: i32, seed: u64) -> Polynomial {
+        let mut rng = get_rng(Some(seed));
+        let mut raw_terms: Vec<(f64, Vec<i32>)> = vec![(rng.gen_range(-2.0..2.0), Vec::new())];
+        for i in 0..n {
+            raw_terms.push((rng.gen_range(-2.0..2.0), vec![i]));
+            for j in i + 1..n {
+                if rng.gen::<bool>() {
+                    raw_terms.push((rng.gen_range(-2.0..2.0), vec![j, i]));
+                }
+            }
+        }
+        Polynomial::new(raw_terms)
+    }
+
+    fn spins(x: &[bool]) -> Vec<f64> {
+        x.iter().map(|x| if *x { 1.0 } else { -1.0 }).collect()
+    }
+
+    #[test]
+    fn ising_form_has_the_energy_of_every_configuration() {
+        for seed in 1..=3 {
+            let polynomial: Polynomial = random_quadratic(7, seed);
+            let ising: Ising = Ising::from_polynomial(&polynomial).unwrap();
+            assert_eq!(ising.variables, polynomial.variables);
+            for x in configurations(polynomial.len()) {
+                assert!((ising.energy(&spins(&x)) - polynomial.energy(&x)).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn there_and_back_returns_the_same_terms() {
+        let polynomial: Polynomial = random_quadratic(6, 4);
+        let back: Polynomial = Ising::from_polynomial(&polynomial).unwrap().to_polynomial();
+        let expected: BTreeMap<Vec<i32>, f64> = polynomial
+            .raw_terms()
+            .into_iter()
+            .map(|(c, p)| (p, c))
+            .collect();
+        let got: BTreeMap<Vec<i32>, f64> =
+            back.raw_terms().into_iter().map(|(c, p)| (p, c)).collect();
+        assert_eq!(
+            expected.keys().collect::<Vec<_>>(),
+            got.keys().collect::<Vec<_>>()
+        );
+        for (p, c) in &expected {
+            assert!((got[p] - c).abs() < 1e-12, "{:?}: {} != {}", p, got[p], c);
+        }
+    }
+
+    #[test]
+    fn offset_and_constant() {
+        // h s_0 + J s_0 s_1 + offset -> 2h x_0 - h + 4J x_0 x_1 - 2J x_0 - 2J x_1 + J + offset
+        let ising = Ising {
+            variables: vec![0, 1],
+            h: vec![0.5, 0.0],
+            j: vec![(0, 1, 2.0)],
+            offset: 3.0,
+        };
+        let polynomial: Polynomial = ising.to_polynomial();
+        assert_eq!(polynomial.constant, -0.5 + 2.0 + 3.0);
+        for x in configurations(2) {
+            assert_eq!(polynomial.energy(&x), ising.energy(&spins(&x)));
+        }
+        let constant_only = Polynomial::new(vec![(1.5, Vec::new())]);
+        let ising: Ising = Ising::from_polynomial(&constant_only).unwrap();
+        assert_eq!((ising.len(), ising.offset), (0, 1.5));
+        assert_eq!(ising.to_polynomial().constant, 1.5);
+    }
+
+    #[test]
+    fn cubic_terms_have_no_ising_form() {
+        let cubic = Polynomial::new(vec![(1.0, vec![0, 1, 2])]);
+        assert!(Ising::from_polynomial(&cubic).is_err());
+    }
+
+    #[test]
+    fn value_round_trip() {
+        let ising: Ising = Ising::from_polynomial(&random_quadratic(5, 5)).unwrap();
+        let read: Ising = Ising::from_value(&ising.to_value()).unwrap();
+        assert_eq!(read.variables, ising.variables);
+        assert_eq!(read.h, ising.h);
+        assert_eq!(read.j, ising.j);
+        assert_eq!(read.offset, ising.offset);
+        // A self coupling s_i s_i = 1 goes to the offset
+        let read: Ising = Ising::from_value(&json!({"J": [[3, 3, 2.0], [4, 3, 1.0]]})).unwrap();
+        assert_eq!((read.j, read.offset), (vec![(0, 1, 1.0)], 2.0));
+    }
+}
@@ -0,0 +1,137 @@
+// Purpose: Contains the Lattice struct (every Trotter layer of the triangular lattice)
+use crate::node::Node;
+
+/* 3D Triangular Lattice
+ * (h: height, i: 2D i, j: 2D j)
+ * current index: (h * L^2) + (i * L) + (j)
+ */
+
+pub struct Lattice {
+    pub nodes: Vec<Node>, // Nodes of every layer, indexed by the 3D index
+    pub l: i32,           // Side length of the triangular lattice
+    pub h: i32,           // Height of the triangular lattice (number of Trotter layers)
+}
+
+impl Lattice {
+    pub fn new(l: i32, h: i32) -> Lattice {
+        Lattice {
+            nodes: Vec::with_capacity((l * l * h) as usize),
+            l,
+            h,
+        }
+    }
+
+    // Index helpers for the neighbours which are not stored in the node itself
+    pub fn get_left(&self, index: i32) -> i32 {
+        let (h, i, j) = self.get_hij(index);
+        let _j: i32 = (j - 1 + self.l) % self.l;
+        (h * self.l * self.l) + (i * self.l) + (_j)
+    }
+    pub fn get_up(&self, index: i32) -> i32 {
+        let (h, i, j) = self.get_hij(index);
+        let _i: i32 = (i - 1 + self.l) % self.l;
+        (h * self.l * self.l) + (_i * self.l) + (j)
+    }
+    pub fn get_up_left(&self, index: i32) -> i32 {
+        let (h, i, j) = self.get_hij(index);
+        let _i: i32 = (i - 1 + self.l) % self.l;
+        let _j: i32 = (j - 1 + self.l) % self.l;
+        (h * self.l * self.l) + (_i * self.l) + (_j)
+    }
+    pub fn get_layer_down(&self, index: i32) -> i32 {
+        let (h, i, j) = self.get_hij(index);
+        let _h: i32 = (h - 1 + self.h) % self.h;
+        (_h * self.l * self.l) + (i * self.l) + (j)
+    }
+
+    // Get (h, i, j) from the 3D index
+    pub fn get_hij(&self, index: i32) -> (i32, i32, i32) {
+        let l2: i32 = self.l * self.l;
+        (index / l2, (index % l2) / self.l, index % self.l)
+    }
+
+    // (layer, sub-lattice) of every index, the sub-lattice of the in-plane index i * L + j as in the analysis
+    pub fn site_table(&self) -> Vec<(usize, usize)> {
+        (0..self.l * self.l * self.h)
+            .map(|index| {
+                let (h, i, j) = self.get_hij(index);
+                let plane: i32 = i * self.l + j;
+                (h as usize, (((plane / self.l) + plane) % 3) as usize)
+            })
+            .collect()
+    }
+
+    // In-plane neighbours of `index` with the strength of the bond (6 neighbours)
+    pub fn in_plane_neighbors(&self, index: i32) -> [(usize, f64); 6] {
+        let node: &Node = &self.nodes[index as usize];
+        let (left, up, up_left) = (
+            &self.nodes[self.get_left(index) as usize],
+            &self.nodes[self.get_up(index) as usize],
+            &self.nodes[self.get_up_left(index) as usize],
+        );
+        [
+            (node.right as usize, node.j_right),
+            (node.bottom as usize, node.j_bottom),
+            (node.btm_right as usize, node.j_btm_right),
+            (left.index as usize, left.j_right),
+            (up.index as usize, up.j_bottom),
+            (up_left.index as usize, up_left.j_btm_right),
+        ]
+    }
+
+    // Sum of K s_j over the in-plane bonds touching `index`
+    pub fn in_plane_field(&self, spins: &[f64], index: i32) -> f64 {
+        self.in_plane_neighbors(index)
+            .iter()
+            .map(|(j, k)| k * spins[*j])
+            .sum()
+    }
+
+    // Sum of s_j over the imaginary-time neighbours of `index` (layer up and layer down)
+    pub fn layer_field(&self, spins: &[f64], index: i32) -> f64 {
+        if self.h == 1 {
+            return 0.0;
+        }
+        let node: &Node = &self.nodes[index as usize];
+        spins[node.layer_up as usize] + spins[self.get_layer_down(index) as usize]
+    }
+
+    // In-plane energy of a single layer: \sum{K s_{i, n} s_{j, n}}
+    pub fn layer_energy(&self, spins: &[f64], layer: i32) -> f64 {
+        let l2: usize = (self.l * self.l) as usize;
+        let start: usize = layer as usize * l2;
+        let mut sum: f64 = 0.0;
+        for node in &self.nodes[start..start + l2] {
+            let s: f64 = spins[node.index as usize];
+            sum += node.j_right * s * spins[node.right as usize];
+            sum += node.j_bottom * s * spins[node.bottom as usize];
+            sum += node.j_btm_right * s * spins[node.btm_right as usize];
+        }
+        sum
+    }
+
+    // H_{eff} = \sum{K s_{i, n} s_{j, n}} - \sum{K' s_{i, n} s_{i, n+1}}
+    pub fn hamiltonian_energy(&self, spins: &[f64]) -> f64 {
+        let mut sum: f64 = (0..self.h).map(|n| self.layer_energy(spins, n)).sum();
+        if self.h > 1 {
+            for node in &self.nodes {
+                sum -= node.j_layer_up * spins[node.index as usize] * spins[node.layer_up as usize];
+            }
+        }
+        sum
+    }
+
+    // Store +1.0 / -1.0 spins back into the nodes
+    pub fn set_spins(&mut self, spins: &[f64]) {
+        for (node, s) in self.nodes.iter_mut().zip(spins) {
+            node.spin = *s > 0.0;
+        }
+    }
+
+    pub fn print_info(&self) {
+        println!("========== NODES info ==========");
+        for node in &self.nodes {
+            node.print_info();
+        }
+    }
+}
@@ -0,0 +1,328 @@
+// Purpose: Fixed magnetisation constraints (total or per sub-lattice) of each layer of the lattice model
+use crate::args::GenerateArgs;
+use crate::error::{Error, Result};
+use crate::ising::Ising;
+use crate::lattice::Lattice;
+use crate::request::Request;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/*
+ * Each layer n (Trotter slice) gets its own constraint, on all its sites or on each sub-lattice:
+ *   M = \sum_{i \in group} s_i = 2 \sum_{i \in group} x_i - N_group, so \sum x_i = (N_group + M) / 2
+ * Modes:
+ *   objective    - w / 4 (\sum s_i - M)^2 added to H_eff by the builder (both request forms)
+ *                = w / 2 \sum_{i < j} s_i s_j - w M / 2 \sum_i s_i + w (N_group + M^2) / 4
+ *   penalty      - w (\sum x_i - (N_group + M) / 2)^2 in penalty_binary_polynomial (DA penalty_coef)
+ *   inequalities - \sum x_i <= t and -\sum x_i <= -t, with lambda = w
+ * Both penalties cost w for each spin away from the target (w per unit of (\sum x - t)^2).
+ * A single spin flip changes H_eff by at most 2 \sum_j |J_{i, j}| <= 2 z max|J| (z: couplings per spin),
+ * so the default weight 2 z max|J| keeps a violating configuration above the constrained minimum.
+ */
+pub struct Magnetization {
+    pub groups: Vec<(String, Vec<usize>, i32)>, // (name, sites, target \sum s) of each constraint
+    pub mode: String,                           // objective, penalty or inequalities
+    pub weight: f64,                            // Penalty weight w (lambda of the inequalities)
+}
+
+impl Magnetization {
+    // Constraints of `--magnetization` / `--sub-magnetization`, None without either
+    pub fn from_options(
+        options: &GenerateArgs,
+        lattice: &Lattice,
+        ising: &Ising,
+    ) -> Result<Option<Magnetization>> {
+        let site_table: Vec<(usize, usize)> = lattice.site_table();
+        let height: usize = lattice.h as usize;
+
+        let mut groups: Vec<(String, Vec<usize>, i32)> = Vec::new();
+        if let Some(total) = options.magnetization {
+            for layer in 0..height {
+                let sites: Vec<usize> = (0..site_table.len())
+                    .filter(|index| site_table[*index].0 == layer)
+                    .collect();
+                groups.push((format!("layer {}", layer), sites, total));
+            }
+        }
+        if let Some(text) = &options.sub_magnetization {
+            let targets: Vec<i32> = text
+                .split(',')
+                .map(|token| {
+                    token.trim().parse().map_err(|_| {
+                        Error::parameter(
+                            "--sub-magnetization",
+                            format!("{:?} is not an integer", token),
+                        )
+                    })
+                })
+                .collect::<Result<Vec<i32>>>()?;
+            if targets.len() != 3 {
+                return Err(Error::parameter(
+                    "--sub-magnetization",
+                    format!("needs 3 values (RED,GREEN,BLUE), got {:?}", text),
+                ));
+            }
+            for layer in 0..height {
+                for (sub_lattice, target) in targets.iter().enumerate() {
+                    let sites: Vec<usize> = (0..site_table.len())
+                        .filter(|index| site_table[*index] == (layer, sub_lattice))
+                        .collect();
+                    groups.push((
+                        format!("layer {} sub-lattice {}", layer, sub_lattice),
+                        sites,
+                        *target,
+                    ));
+                }
+            }
+        }
+        if groups.is_empty() {
+            return Ok(None);
+        }
+
+        for (name, sites, target) in &groups {
+            let n: i32 = sites.len() as i32;
+            if target.abs() > n || (n + target) % 2 != 0 {
+                return Err(Error::parameter(
+                    if name.contains("sub-lattice") {
+                        "--sub-magnetization"
+                    } else {
+                        "--magnetization"
+                    },
+                    format!(
+                        "{} is impossible for {} ({} spins), it must be in [-{}, {}] with the parity of {}",
+                        target, name, n, n, n, n
+                    ),
+                ));
+            }
+        }
+
+        let mode: String = options.magnetization_mode.clone(); // One of the possible values of the flag
+
+        let max_j: f64 = ising
+            .j
+            .iter()
+            .fold(0.0, |max, (_, _, j)| f64::max(max, j.abs()));
+        let coordination: usize = ising.adjacency().iter().map(Vec::len).max().unwrap_or(0);
+        let scale: f64 = options.penalty_scale.unwrap_or(2.0 * coordination as f64);
+        let weight: f64 = options.penalty_weight.unwrap_or(scale * max_j);
+        println!(
+            "Magnetisation constraints: {}, mode: {}, weight: {} (max|J|: {}, coordination: {})",
+            groups.len(),
+            mode,
+            weight,
+            max_j,
+            coordination
+        );
+
+        Ok(Some(Magnetization {
+            groups,
+            mode,
+            weight,
+        }))
+    }
+
+    // Mode, weight and target of each group, for the metadata
+    pub fn to_value(&self) -> Value {
+        let targets: Map<String, Value> = self
+            .groups
+            .iter()
+            .map(|(name, _, target)| (name.clone(), json!(target)))
+            .collect();
+        json!({"mode": self.mode, "weight": self.weight, "targets": targets})
+    }
+
+    // Add the objective penalties to the spin form of H_eff (objective mode only)
+    pub fn add_to_ising(&self, ising: &mut Ising) {
+        if self.mode != "objective" {
+            return;
+        }
+        let mut bond_map: BTreeMap<(usize, usize), f64> =
+            ising.j.iter().map(|(a, b, j)| ((*a, *b), *j)).collect();
+        for (_, sites, target) in &self.groups {
+            let target: f64 = *target as f64;
+            for (k, a) in sites.iter().enumerate() {
+                ising.h[*a] -= self.weight * target / 2.0;
+                for b in &sites[k + 1..] {
+                    *bond_map.entry((*a.min(b), *a.max(b))).or_insert(0.0) += self.weight / 2.0;
+                }
+            }
+            ising.offset += self.weight * (sites.len() as f64 + target * target) / 4.0;
+        }
+        ising.j = bond_map.into_iter().map(|((a, b), j)| (a, b, j)).collect();
+    }
+
+    // Add the DA constraint features to the request (penalty and inequalities modes)
+    pub fn add_to_request(&self, request: &mut Request) {
+        for (_, sites, target) in &self.groups {
+            let terms: Vec<(f64, i32)> = sites.iter().map(|i| (1.0, *i as i32)).collect();
+            let ups: f64 = (sites.len() as i32 + target) as f64 / 2.0; // Target \sum x_i
+            match self.mode.as_str() {
+                "penalty" => request.add_equality(&terms, ups, self.weight),
+                "inequalities" => request.add_equality_inequalities(&terms, ups, self.weight),
+                _ => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hamiltonian::ising_eff;
+    use crate::polynomial::tests::configurations;
+    use crate::polynomial::Polynomial;
+    use crate::sqa::layer_strength;
+    use crate::{create_vector, Jxx};
+    use structopt::StructOpt;
+
+    // (H_eff, H_eff with the objective penalties) of the 3 x 3 x `h` lattice and the constraints of `flags`
+    fn constrained(h: i32, flags: &[&str]) -> (Ising, Ising, Magnetization) {
+        let gamma: f64 = if h == 1 { 0.0 } else { 0.5 };
+        let jxx = Jxx {
+            j: 1.0,
+            jl: if h == 1 { 0.0 } else { layer_strength(gamma) },
+            l: 3,
+            h,
+            gamma,
+        };
+        let lattice: Lattice = create_vector(&jxx);
+        let ising: Ising = ising_eff(&jxx, &lattice, false);
+        let options = GenerateArgs::from_iter(["generate"].iter().chain(flags));
+        let magnetization: Magnetization = Magnetization::from_options(&options, &lattice, &ising)
+            .unwrap()
+            .unwrap();
+        let mut penalised: Ising = ising_eff(&jxx, &lattice, false);
+        magnetization.add_to_ising(&mut penalised);
+        (ising, penalised, magnetization)
+    }
+
+    fn spins(x: &[bool]) -> Vec<f64> {
+        x.iter().map(|v| if *v { 1.0 } else { -1.0 }).collect()
+    }
+
+    // \sum s of each group of the constraints
+    fn sums(magnetization: &Magnetization, s: &[f64]) -> Vec<f64> {
+        magnetization
+            .groups
+            .iter()
+            .map(|(_, sites, _)| sites.iter().map(|i| s[*i]).sum())
+            .collect()
+    }
+
+    #[test]
+    fn objective_adds_the_squared_distance() {
+        let (ising, penalised, magnetization) = constrained(2, &["--sub-magnetization", "1,1,-1"]);
+        assert_eq!(magnetization.groups.len(), 6); // 3 sub-lattices x 2 layers
+        for x in configurations(18).step_by(97) {
+            let s: Vec<f64> = spins(&x);
+            let expected: f64 = magnetization
+                .groups
+                .iter()
+                .zip(sums(&magnetization, &s))
+                .map(|((_, _, target), sum)| {
+                    magnetization.weight / 4.0 * (sum - *target as f64).powi(2)
+                })
+                .sum();
+            let added: f64 = penalised.energy(&s) - ising.energy(&s);
+            assert!((added - expected).abs() < 1e-9, "{} != {}", added, expected);
+        }
+    }
+
+    #[test]
+    fn default_weight_keeps_the_ground_state_on_target() {
+        let (ising, penalised, magnetization) = constrained(1, &["--magnetization", "3"]);
+        let ground: Vec<f64> = configurations(9)
+            .map(|x| spins(&x))
+            .min_by(|a, b| penalised.energy(a).total_cmp(&penalised.energy(b)))
+            .unwrap();
+        assert_eq!(sums(&magnetization, &ground), vec![3.0]);
+        // uud has M = 3 and is a ground state of the unconstrained lattice
+        assert!((penalised.energy(&ground) - ising.energy(&ground)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn penalty_mode_is_the_squared_distance_of_the_ups() {
+        let (ising, unchanged, magnetization) = constrained(
+            1,
+            &[
+                "--magnetization",
+                "-1",
+                "--magnetization-mode",
+                "penalty",
+                "--penalty-weight",
+                "2.5",
+            ],
+        );
+        let mut request = Request::default();
+        magnetization.add_to_request(&mut request);
+        let penalty: Polynomial = Polynomial::new(
+            request
+                .penalty_binary_polynomial
+                .iter()
+                .map(|term| (term.c, term.p.clone()))
+                .collect(),
+        );
+        for x in configurations(9) {
+            let ups: f64 = x.iter().filter(|v| **v).count() as f64;
+            assert!((penalty.energy(&x) - 2.5 * (ups - 4.0).powi(2)).abs() < 1e-9);
+            assert_eq!(unchanged.energy(&spins(&x)), ising.energy(&spins(&x)));
+        }
+    }
+
+    #[test]
+    fn inequalities_mode_bounds_the_ups_both_ways() {
+        let (_, _, magnetization) = constrained(
+            2,
+            &[
+                "--magnetization",
+                "1",
+                "--magnetization-mode",
+                "inequalities",
+            ],
+        );
+        let mut request = Request::default();
+        magnetization.add_to_request(&mut request);
+        assert_eq!(request.inequalities.len(), 4); // <= and >= for each layer
+        assert!(request.penalty_binary_polynomial.is_empty());
+        let constant = |k: usize| request.inequalities[k].terms.last().unwrap().c;
+        assert_eq!((constant(0), constant(1)), (-5.0, 5.0)); // 5 of 9 spins up
+    }
+
+    #[test]
+    fn impossible_targets_are_refused() {
+        let lattice: Lattice = create_vector(&Jxx {
+            j: 1.0,
+            jl: 0.0,
+            l: 3,
+            h: 1,
+            gamma: 0.0,
+        });
+        let ising: Ising = ising_eff(
+            &Jxx {
+                j: 1.0,
+                jl: 0.0,
+                l: 3,
+                h: 1,
+                gamma: 0.0,
+            },
+            &lattice,
+            false,
+        );
+        for flags in [
+            ["--magnetization", "2"],  // Parity of 9
+            ["--magnetization", "11"], // More than 9 spins
+            ["--sub-magnetization", "1,1"],
+            ["--sub-magnetization", "3,3,5"],
+        ] {
+            let options = GenerateArgs::from_iter(["generate"].iter().chain(&flags));
+            assert!(matches!(
+                Magnetization::from_options(&options, &lattice, &ising),
+                Err(Error::Parameter { .. })
+            ));
+        }
+        let options = GenerateArgs::from_iter(["generate"]);
+        assert!(Magnetization::from_options(&options, &lattice, &ising)
+            .unwrap()
+            .is_none());
+    }
+}
@@ -1,7 +1,6 @@
-use serde_json;
 use serde_json::{json, Value};
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufReader, Write};
 
 #[derive(Debug)]
 pub struct Jxx {
@@ -22,11 +21,20 @@ pub enum SubLattice {
 mod node; // Contains the Node struct and it's implementation
 use node::Node; // Use the Node struct
 
+mod lattice; // Contains the Lattice struct (all the nodes of every layer)
+use lattice::Lattice; // Use the Lattice struct
+
 mod random; // Get the random number
 use random::random_strength; // Use the random_strength function
 
 mod hamiltonian; // Contains the hamiltonian_eff function
-use hamiltonian::hamiltonian_eff; // Use the hamiltonian_eff function
+use hamiltonian::{hamiltonian_eff, ising_eff}; // Use the hamiltonian_eff functions
+
+mod da3_params; // Contains the fujitsuDA3 parameters of the request
+use da3_params::Da3Params; // Use the Da3Params struct
+
+mod magnetization; // Contains the fixed magnetisation constraints
+use magnetization::Magnetization; // Use the Magnetization struct
 
 mod gamma_analysis; // Contains the analysis function
 use gamma_analysis::analysis; // Use the analysis function
@@ -34,40 +42,190 @@ use gamma_analysis::analysis; // Use the analysis function
 mod guidance_config; // Contains the use_guidance function
 use guidance_config::use_guidance; // Use the use_guidance function
 
-mod args; // Contains the Options struct
+mod guidance_select; // Contains the selection strategies of the guidance solution
+
+mod fixing; // Contains the fixed spins of --fix
+use fixing::Fixing; // Use the Fixing struct
+
+mod initial_state; // Contains the synthetic states of the lattice
+use initial_state::{set_state, state_value}; // Use the synthetic states
+
+mod quantization; // Contains the integer scaling of the coefficients
+use quantization::Quantization; // Use the Quantization struct
+
+mod solution; // Contains the Solution struct shared by the local solvers
+use solution::{verify_energies, write_result, write_result_beside}; // Use the write_result functions
+
+mod cluster; // Contains the cluster updates (Wolff and worldline)
+
+mod sqa; // Contains the simulated quantum annealing solver
+use sqa::{simulated_quantum_annealing, SqaParams}; // Use the SQA solver
+
+mod request; // Contains the typed request with the constraint features
+use request::Request; // Use the Request struct
+
+mod polynomial; // Contains the Polynomial struct read from the binary_polynomial
+use polynomial::Polynomial; // Use the Polynomial struct
+
+mod exact; // Contains the exhaustive ground state solver
+use exact::exact_ground_states; // Use the exact_ground_states function
 
-/* 3D Triangular Lattice
- * (h: height, i: 2D i, j: 2D j)
- * current index: (h * L^2) + (i * L) + (j)
- */
+mod tabu; // Contains the tabu search solver
+use tabu::{tabu_search, TabuParams}; // Use the tabu search solver
+
+mod ising; // Contains the Ising struct (h, J, offset)
+use ising::Ising; // Use the Ising struct
+
+mod bifurcation; // Contains the simulated bifurcation solver
+use bifurcation::{simulated_bifurcation, SbParams, Variant}; // Use the simulated bifurcation solver
+
+mod digital_annealer; // Contains the Digital Annealer emulator
+use digital_annealer::{digital_annealer, DaParams}; // Use the Digital Annealer emulator
+
+mod mock_server; // Contains the local mock of the DA3 async API
+use mock_server::mock_server; // Use the mock_server function
+
+mod export; // Contains the exporters to the QUBO file formats
+use export::export; // Use the export function
+
+mod import; // Contains the importers of external QUBO/Ising instances
+use import::import; // Use the import function
+
+mod result_reader; // Contains the streaming reader of the result files
+
+mod solution_set; // Contains the packed storage of the solution sets
+use solution_set::convert; // Use the convert function
+
+mod run_config; // Contains the run configuration file of --config
+use run_config::resolved_config; // Use the resolved_config function
+
+mod submit; // Contains the client of the DA3 async API
+use submit::submit; // Use the submit function
+
+mod error; // Contains the Error type and the exit codes
+use error::{Error, Result}; // Use the Error type
+
+mod args; // Contains the Options struct
+use args::{Command, GenerateArgs, SolveArgs, SolverArgs, WorkspaceArgs}; // Use the subcommands
 
-static mut NODES: Vec<Node> = Vec::new();
+mod workspace; // Contains the layout of the workspace directory
 
 // Main function
 fn main() {
-    let options = args::get_options();
-
-    // Check if arguments are for generting Gamma analysis data file
-    // args ex: ["target/debug/fujitsu", "--gamma-analysis", "target/Gamma0.0/Strength1.0_Lattice12_12_1_Time10.json"]
-    match &options.file_path {
-        Some(file_path) => {
-            analysis(file_path.clone());
-            return;
-        }
-        None => {}
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        std::process::exit(e.exit_code());
     }
+}
 
-    // Check if arguments are for generting guidance config file
-    // args ex: ["target/debug/fujitsu", "--guidance-config", "target/Gamma0.0/Strength1.0_Lattice12_12_1_Time10.json"]
-    match &options.guidance_path {
-        Some(guidance_path) => {
-            use_guidance(guidance_path.clone());
-            return;
+fn run() -> Result<()> {
+    let options: args::Options = args::get_options()?;
+    match &options.cmd {
+        // args ex: ["target/debug/fujitsu", "generate", "-L", "12", "-H", "3", "-G", "0.2"] (or without "generate")
+        Command::Generate(generate_args) => generate(generate_args),
+        // args ex: ["target/debug/fujitsu", "analyze", "workspace/Gamma0.0/Strength1.0_Lattice12_12_1_Time10.json"]
+        Command::Analyze(analyze_args) => analysis(
+            analyze_args.file_path.clone(),
+            analyze_args.problem_path.clone(),
+            &analyze_args.workspace,
+        ),
+        // args ex: ["target/debug/fujitsu", "guidance", "workspace/Gamma0.0/Strength1.0_Lattice12_12_1_Time10.json"]
+        // args ex: ["target/debug/fujitsu", "guidance", "workspace/Gamma0.0/Strength1.0_Lattice12_12_1_DA.json", "--select", "c6", "--c6-target", "1"]
+        Command::Guidance(guidance_args) => use_guidance(guidance_args),
+        // args ex: ["target/debug/fujitsu", "solve", "workspace/input.json", "--solver", "tabu"]
+        // args ex: ["target/debug/fujitsu", "solve", "G1", "--verify", "workspace/G1_DA.json"]
+        Command::Solve(solve_args) => solve(solve_args),
+        // args ex: ["target/debug/fujitsu", "submit", "workspace/input.json", "--wait"]
+        Command::Submit(submit_args) => submit(submit_args),
+        // args ex: ["target/debug/fujitsu", "convert", "workspace/Gamma0.0/Strength1.0_Lattice12_12_1_DA.json"]
+        Command::Convert { path } => convert(path).map(|_| ()),
+        // args ex: ["target/debug/fujitsu", "mock-server", "--port", "8080", "--api-key", "test"]
+        Command::MockServer { port, api_key } => mock_server(*port, api_key.clone()),
+    }
+}
+
+// Solve, convert, export or verify an existing request file or an external instance
+fn solve(options: &SolveArgs) -> Result<()> {
+    if options.solver.solver.as_deref() == Some("sqa") {
+        return Err(Error::parameter(
+            "--solver",
+            "sqa works on the lattice, use it with generate",
+        ));
+    }
+    let input_path: &str = &options.input_path;
+    let (mut fujitsu, converted): (Value, bool) = import(input_path)?;
+    if let Some(solver) = &options.solver.solver {
+        refuse_inequalities(solver, &fujitsu)?;
+    }
+    // The parameters of the request itself, the flags given replace them
+    let da3_params: Da3Params = Da3Params::for_request(&options.da3, &fujitsu["fujitsuDA3"])
+        .map_err(|e| e.in_file(input_path))?;
+    if let Some(request) = fujitsu.as_object_mut() {
+        request.insert(
+            "fujitsuDA3".to_string(),
+            Value::Object(da3_params.values.clone()),
+        );
+    }
+    if converted {
+        // Ready to be posted by `submit` or `api.sh --post`
+        let mut request: Request = Request::from_value(&fujitsu)?;
+        da3_params.apply(&mut request);
+        fujitsu = request.to_value();
+        let request_path: String = options.workspace.request_path();
+        options.workspace.create()?;
+        write_json(&request_path, &fujitsu)?;
+        println!("Saved the request to {}", request_path);
+    }
+    let polynomial: Polynomial =
+        Polynomial::from_value(&fujitsu).map_err(|e| e.in_file(input_path))?;
+    if let Some(form) = &options.form {
+        let mut request: Value = match form.as_str() {
+            "qubo" => polynomial.to_value(),
+            _ => json!({"ising": Ising::from_polynomial(&polynomial)
+                .map_err(|e| e.in_file(input_path))?
+                .to_value()}),
+        };
+        request["fujitsuDA3"] = fujitsu["fujitsuDA3"].clone();
+        write_result_beside(input_path, &form.to_uppercase(), &request, false)?;
+    }
+    if let Some(format) = &options.export {
+        export(&polynomial, format, input_path)?;
+    }
+    if let Some(solver) = &options.solver.solver {
+        let result: Value =
+            solve_polynomial(&options.solver, &fujitsu, da3_params.time_limit_sec())
+                .map_err(|e| e.in_file(input_path))?;
+        let mismatches: usize = verify_energies(&polynomial, &result)?;
+        let result_path: String = write_result_beside(
+            input_path,
+            &solver.to_uppercase(),
+            &result,
+            options.solver.packed,
+        )?;
+        // Saved anyway, to look at the solutions in question
+        if mismatches > 0 {
+            return Err(Error::Mismatch(format!(
+                "{} energies of {} do not match {}",
+                mismatches, result_path, input_path
+            )));
+        }
+    }
+    if let Some(verify_path) = &options.verify_path {
+        let mismatches: usize = verify_energies(&polynomial, &read_json(verify_path)?)
+            .map_err(|e| e.in_file(verify_path))?;
+        if mismatches > 0 {
+            return Err(Error::Mismatch(format!(
+                "{} energies of {} do not match {}",
+                mismatches, verify_path, input_path
+            )));
         }
-        None => {}
     }
+    Ok(())
+}
 
-    let time_limit_sec = options.T;
+// Generate the request of the lattice model, and solve it with a local solver
+fn generate(options: &GenerateArgs) -> Result<()> {
+    let da3_params: Da3Params = Da3Params::from_options(&options.da3)?;
     let (use_random, debug_output, without_cycle): (bool, bool, bool) = (
         options.use_random,
         options.debug_output,
@@ -82,7 +240,7 @@ fn main() {
         gamma: 0.2, // Gamma of the Hamiltonian
     };
 
-    args::get_jxx(&mut jxx, options);
+    args::get_jxx(&mut jxx, options)?;
 
     if jxx.gamma == 0.0 || jxx.h == 1 {
         // If Gamma equals 0, height should be 1 (If height is 1, Gamma should be 0)
@@ -92,44 +250,266 @@ fn main() {
     }
     println!("{:#?}", jxx);
 
-    create_vector(&jxx);
+    let mut lattice: Lattice = create_vector(&jxx);
     if use_random {
-        random_strength(&jxx);
+        random_strength(&jxx, &mut lattice, options.disorder_seed);
+    }
+    if let Some(state) = &options.initial_state {
+        set_state(&mut lattice, state, options.solver.seed);
     }
 
-    let mut fujitsu: Value = hamiltonian_eff(&jxx, without_cycle);
-    write_request_format(&mut fujitsu, time_limit_sec);
-    write_json("./target/input.json", &fujitsu);
-    metadata("./target/metadata.json", &jxx, jxx.gamma, time_limit_sec);
+    let form: &str = options.form.as_deref().unwrap_or("qubo");
+    let mut ising: Ising = ising_eff(&jxx, &lattice, without_cycle);
+    let magnetization: Option<Magnetization> =
+        Magnetization::from_options(options, &lattice, &ising)?;
+    if let Some(magnetization) = &magnetization {
+        magnetization.add_to_ising(&mut ising);
+    }
+    let fixing: Option<Fixing> = Fixing::from_options(options, &lattice)?;
+    if let Some(fixing) = fixing.as_ref().filter(|fixing| fixing.substitutes()) {
+        // The constraints of the request would still name the substituted spins
+        let request_constraints: bool = options.constraints_path.is_some()
+            || magnetization
+                .as_ref()
+                .is_some_and(|magnetization| magnetization.mode != "objective");
+        if request_constraints {
+            return Err(Error::parameter(
+                "--fix-mode",
+                "substitute only supports the objective magnetisation constraints, use fixed-config",
+            ));
+        }
+        fixing.add_to_ising(&mut ising);
+    }
+    let mut request: Request = Request::from_value(&hamiltonian_eff(&ising, form))?;
+    if let Some(magnetization) = &magnetization {
+        magnetization.add_to_request(&mut request);
+    }
+    if let Some(constraints_path) = &options.constraints_path {
+        request
+            .add_constraints(&read_json(constraints_path)?)
+            .map_err(|e| e.in_file(constraints_path))?;
+    }
+    da3_params.apply(&mut request);
+    if let Some(fixing) = &fixing {
+        fixing.add_to_request(&mut request);
+    }
+    if options.initial_state.is_some() {
+        request
+            .da3
+            .insert("guidance_config".to_string(), state_value(&lattice));
+    }
+    let quantization: Option<Quantization> = match options.scale_bits {
+        Some(bits) => Some(Quantization::apply(&mut request, bits)?),
+        None => None,
+    };
+    if let Some(quantization) = &quantization {
+        quantization.print();
+    }
+    let fujitsu: Value = request.to_value();
+    let workspace: &WorkspaceArgs = &options.workspace;
+    let request_path: String = workspace.request_path();
+    workspace.create()?; // Create directory if not exists
+    write_json(&request_path, &fujitsu)?;
+    write_json(
+        &workspace.config_path(),
+        &resolved_config(options, &jxx, &da3_params),
+    )?;
+    metadata(
+        &workspace.metadata_path(),
+        &jxx,
+        &da3_params,
+        magnetization.as_ref(),
+        fixing.as_ref(),
+        options.initial_state.as_deref(),
+        quantization.as_ref(),
+    )?;
+    if let Some(format) = &options.export {
+        export(&Polynomial::from_value(&fujitsu)?, format, &request_path)?;
+    }
+
+    // Run a local solver on the generated problem
+    let solver_args: &SolverArgs = &options.solver;
+    if let Some(solver) = &solver_args.solver {
+        match solver.as_str() {
+            "sqa" => {
+                if magnetization.is_some() {
+                    return Err(Error::parameter(
+                        "--solver",
+                        "sqa works on the lattice and does not support the magnetisation constraints",
+                    ));
+                }
+                if fixing.is_some() {
+                    return Err(Error::parameter(
+                        "--solver",
+                        "sqa works on the lattice and does not support the fixed spins",
+                    ));
+                }
+                if without_cycle {
+                    return Err(Error::parameter(
+                        "--solver",
+                        "sqa works on the periodic lattice and does not support --without-cycle",
+                    ));
+                }
+                let params = SqaParams {
+                    sweeps: solver_args.sweeps,
+                    gamma: (solver_args.gamma_start.unwrap_or(jxx.gamma), jxx.gamma),
+                    temperature: (solver_args.temp_start, solver_args.temp_end),
+                    wolff: solver_args.wolff,
+                    worldline: solver_args.worldline,
+                    seed: solver_args.seed,
+                    initial: options.initial_state.is_some(),
+                };
+                println!("{:#?}", params);
+                let result: Value = simulated_quantum_annealing(&mut lattice, &params)?;
+                write_result(workspace, &jxx, "SQA", &result, solver_args.packed)?;
+            }
+            _ => {
+                let mut result: Value =
+                    solve_polynomial(solver_args, &fujitsu, da3_params.time_limit_sec())?;
+                if let Some(fixing) = &fixing {
+                    fixing.expand_result(&mut result); // Full lattice for the analysis
+                }
+                write_result(
+                    workspace,
+                    &jxx,
+                    &solver.to_uppercase(),
+                    &result,
+                    solver_args.packed,
+                )?;
+            }
+        }
+    }
 
     if debug_output {
-        print_node_info();
+        lattice.print_info();
         debug_log(&fujitsu);
     }
+    Ok(())
+}
+
+// The local solvers minimise a polynomial, the inequalities of a request have none
+fn refuse_inequalities(solver: &str, fujitsu: &Value) -> Result<()> {
+    if fujitsu.get("inequalities").is_some() {
+        return Err(Error::parameter(
+            "--solver",
+            format!(
+                "{} cannot minimise the inequalities of the request, only the Digital Annealer API handles them",
+                solver
+            ),
+        ));
+    }
+    Ok(())
 }
 
-fn create_vector(jxx: &Jxx) {
+// Run one of the solvers working on the binary_polynomial of a request
+fn solve_polynomial(options: &SolverArgs, fujitsu: &Value, time_limit_sec: i64) -> Result<Value> {
+    let solver: &str = options.solver.as_deref().unwrap_or_default();
+    refuse_inequalities(solver, fujitsu)?;
+    let polynomial: &Polynomial = &Polynomial::from_value(fujitsu)?;
+    println!(
+        "variables: {}, terms: {}",
+        polynomial.len(),
+        polynomial.terms.len()
+    );
+    if polynomial.len() == 0 {
+        return Err(Error::content("/binary_polynomial", "no variable"));
+    }
+    if solver != "da" && fujitsu["fujitsuDA3"].get("fixed_config").is_some() {
+        return Err(Error::parameter(
+            "--solver",
+            format!(
+                "{} ignores the fixed_config of the request, use da (or generate --fix-mode substitute)",
+                solver
+            ),
+        ));
+    }
+    let result: Value = match solver {
+        "exact" => {
+            if polynomial.len() > options.max_variables.min(63) {
+                return Err(Error::parameter(
+                    "--max-variables",
+                    format!(
+                        "exact enumeration refuses {} variables (limit: {}, at most 63)",
+                        polynomial.len(),
+                        options.max_variables
+                    ),
+                ));
+            }
+            exact_ground_states(polynomial, options.max_variables)
+        }
+        "tabu" => {
+            // The guidance_config of the request starts the first restart
+            let guidance = DaParams::from_value(fujitsu, options.seed)?;
+            guidance.check(polynomial)?;
+            let params = TabuParams {
+                tenure: options.tenure,
+                iterations: options.iterations,
+                restarts: options.restarts,
+                time_limit_sec: time_limit_sec as i32,
+                seed: options.seed,
+                initial: guidance.initial_positions(polynomial),
+            };
+            println!("{:#?}", params);
+            tabu_search(polynomial, &params)
+        }
+        "bsb" | "dsb" => {
+            let params = SbParams {
+                variant: match solver {
+                    "bsb" => Variant::Ballistic,
+                    _ => Variant::Discrete,
+                },
+                steps: options.steps,
+                dt: options.dt,
+                pump_exponent: options.pump_exponent,
+                agents: options.agents,
+                seed: options.seed,
+            };
+            let ising: Ising = Ising::from_polynomial(polynomial)?;
+            params.check(&ising)?;
+            println!("{:#?}", params);
+            simulated_bifurcation(&ising, &params)
+        }
+        "da" => {
+            // Parameters come from the fujitsuDA3 object of the request
+            let mut params = DaParams::from_value(fujitsu, options.seed)?;
+            params.cycles = options.cycles;
+            params.check(polynomial)?;
+            println!("{:#?}", params);
+            digital_annealer(polynomial, &params)
+        }
+        _ => {
+            return Err(Error::parameter(
+                "--solver",
+                format!("{} does not solve a polynomial request", solver),
+            ))
+        }
+    };
+    Ok(result)
+}
+
+fn create_vector(jxx: &Jxx) -> Lattice {
     #![allow(non_snake_case)]
     let L: i32 = jxx.l; // Side length of the triangular lattice
     let H: i32 = jxx.h; // Height of the triangular lattice
     let L2: i32 = jxx.l * jxx.l; // L^2
+    let mut lattice: Lattice = Lattice::new(L, H);
 
     let get_right = |h: i32, i: i32, j: i32| -> i32 {
         let _j: i32 = (j + 1) % L;
-        return (h * L2) + (i * L) + (_j);
+        (h * L2) + (i * L) + (_j)
     };
     let get_bottom = |h: i32, i: i32, j: i32| -> i32 {
         let _i: i32 = (i + 1) % L;
-        return (h * L2) + (_i * L) + (j);
+        (h * L2) + (_i * L) + (j)
     };
     let get_bottom_right = |h: i32, i: i32, j: i32| -> i32 {
         let _i: i32 = (i + 1) % L;
         let _j: i32 = (j + 1) % L;
-        return (h * L2) + (_i * L) + (_j);
+        (h * L2) + (_i * L) + (_j)
     };
     let get_layer_up = |h: i32, i: i32, j: i32| -> i32 {
         let _h: i32 = (h + 1) % H;
-        return (_h * L2) + (i * L) + (j);
+        (_h * L2) + (i * L) + (j)
     };
     let get_sub_lattice = |index: i32| -> SubLattice {
         // Get the sub-lattice of the node.
@@ -138,7 +518,7 @@ fn create_vector(jxx: &Jxx) {
             0 => SubLattice::RED,
             1 => SubLattice::GREEN,
             2 => SubLattice::BLUE,
-            _ => panic!("Error: sub_lattice is not 0, 1, or 2."),
+            _ => unreachable!("a non-negative index modulo 3 is 0, 1 or 2"),
         }
     };
 
@@ -152,61 +532,60 @@ fn create_vector(jxx: &Jxx) {
                 let btm_right: i32 = get_bottom_right(h, i, j);
                 let layer_up: i32 = get_layer_up(h, i, j);
                 let sub_lattice = get_sub_lattice(index);
-                unsafe {
-                    // Set the nodes
-                    NODES.push(Node::new(
-                        index,
-                        right,
-                        bottom,
-                        btm_right,
-                        layer_up,
-                        sub_lattice,
-                        &jxx,
-                    ));
-                }
+                // Set the nodes
+                lattice.nodes.push(Node::new(
+                    index,
+                    right,
+                    bottom,
+                    btm_right,
+                    layer_up,
+                    sub_lattice,
+                    jxx,
+                ));
             }
         }
     }
+
+    lattice
 }
 
-fn write_request_format(fujitsu: &mut Value, time_limit_sec: Option<i32>) -> () {
-    let da3 = fujitsu["fujitsuDA3"].as_object_mut().unwrap();
-    if let Some(time_limit_sec) = time_limit_sec {
-        da3.insert("time_limit_sec".to_string(), Value::from(time_limit_sec));
-    }
-    da3.insert("gs_level".to_string(), Value::from(100));
-    da3.insert("gs_cutoff".to_string(), Value::from(100000));
-    da3.insert("num_output_solution".to_string(), Value::from(1024));
+pub fn create_dir(dir: &str) -> Result<()> {
+    std::fs::create_dir_all(dir).map_err(|e| Error::io(dir, e))
 }
 
-pub fn write_json(file_path: &str, fujitsu: &Value) -> () {
-    let mut file = match File::create(file_path) {
-        Ok(file) => file,
-        Err(e) => {
-            panic!("Error: {}", e);
-        }
-    };
+pub fn write_json(file_path: &str, fujitsu: &Value) -> Result<()> {
+    let mut file = File::create(file_path).map_err(|e| Error::io(file_path, e))?;
 
     let formatted_data = match serde_json::to_string_pretty(&fujitsu) {
         Ok(data) => data,
-        Err(e) => {
-            panic!("Error: {}", e);
-        }
+        Err(e) => unreachable!("a Value always serializes: {}", e),
     };
 
-    if let Err(e) = file.write_all(formatted_data.as_bytes()) {
-        panic!("Error: {}", e);
-    }
+    file.write_all(formatted_data.as_bytes())
+        .map_err(|e| Error::io(file_path, e))
 }
 
-fn metadata(file_path: &str, jxx: &Jxx, gamma: f64, time_limit_sec: Option<i32>) -> () {
-    let mut file = match File::create(file_path) {
-        Ok(file) => file,
-        Err(e) => {
-            panic!("Error: {}", e);
+pub fn read_json(file_path: &str) -> Result<Value> {
+    let file = File::open(file_path).map_err(|e| Error::io(file_path, e))?;
+
+    serde_json::from_reader(BufReader::new(file)).map_err(|e| {
+        if e.is_io() {
+            Error::io(file_path, e.into())
+        } else {
+            Error::syntax(file_path, e)
         }
-    };
+    })
+}
 
+fn metadata(
+    file_path: &str,
+    jxx: &Jxx,
+    da3_params: &Da3Params,
+    magnetization: Option<&Magnetization>,
+    fixing: Option<&Fixing>,
+    initial_state: Option<&str>,
+    quantization: Option<&Quantization>,
+) -> Result<()> {
     let mut meta = json!({});
 
     let data = meta.as_object_mut().unwrap();
@@ -214,41 +593,56 @@ fn metadata(file_path: &str, jxx: &Jxx, gamma: f64, time_limit_sec: Option<i32>)
     data.insert("Layer_strength".to_string(), Value::from(jxx.jl));
     data.insert("Side_length".to_string(), Value::from(jxx.l));
     data.insert("Height".to_string(), Value::from(jxx.h));
-    data.insert("Gamma".to_string(), Value::from(gamma));
-
-    if let Some(time_limit_sec) = time_limit_sec {
-        // If time_limit_sec is not None
-        data.insert("Time_limit_sec".to_string(), Value::from(time_limit_sec));
-    } else {
-        data.insert("Time_limit_sec".to_string(), Value::from(10));
+    data.insert("Gamma".to_string(), Value::from(jxx.gamma));
+
+    data.insert(
+        "Time_limit_sec".to_string(),
+        Value::from(da3_params.time_limit_sec()),
+    );
+    data.insert("fujitsuDA3".to_string(), da3_params.to_metadata());
+    if let Some(magnetization) = magnetization {
+        data.insert("Magnetization".to_string(), magnetization.to_value());
     }
-
-    let formatted_data = match serde_json::to_string_pretty(&meta) {
-        Ok(data) => data,
-        Err(e) => {
-            panic!("Error: {}", e);
-        }
-    };
-
-    if let Err(e) = file.write_all(formatted_data.as_bytes()) {
-        panic!("Error: {}", e);
+    if let Some(fixing) = fixing {
+        data.insert("Fixed".to_string(), fixing.to_value());
     }
-}
-
-fn print_node_info() {
-    println!("========== NODES info ==========");
-    unsafe {
-        for i in 0..NODES.len() {
-            NODES[i].print_info();
-        }
+    if let Some(state) = initial_state {
+        data.insert("Guidance".to_string(), json!({ "state": state }));
     }
+    if let Some(quantization) = quantization {
+        data.insert("Scaling".to_string(), quantization.to_value());
+    }
+
+    write_json(file_path, &meta)
 }
 
+// Terms of the request, in the QUBO or the Ising form
 fn debug_log(fujitsu: &Value) {
     println!("========== DEBUG LOG ==========");
-    let term_list = fujitsu["binary_polynomial"]["terms"].as_array().unwrap();
-    for term in term_list {
-        println!("cof: {}, poly: {}", term["c"], term["p"])
+    if let Some(term_list) = fujitsu["binary_polynomial"]["terms"].as_array() {
+        for term in term_list {
+            println!("cof: {}, poly: {}", term["c"], term["p"])
+        }
+        println!("terms: {}", term_list.len());
+    }
+    if let Some(ising) = fujitsu.get("ising") {
+        let h = ising["h"].as_object().cloned().unwrap_or_default();
+        let couplings: Vec<Value> = ising["J"].as_array().cloned().unwrap_or_default();
+        for (index, value) in &h {
+            println!("h: {}, spin: {}", value, index)
+        }
+        for coupling in &couplings {
+            println!(
+                "J: {}, spins: [{}, {}]",
+                coupling[2], coupling[0], coupling[1]
+            )
+        }
+        println!(
+            "h: {}, J: {}, offset: {}",
+            h.len(),
+            couplings.len(),
+            ising["offset"]
+        );
     }
     println!();
 }
@@ -0,0 +1,353 @@
+// Purpose: Local mock of the Fujitsu DA3 async API, solving the jobs with the Digital Annealer emulator
+use crate::digital_annealer::{digital_annealer, DaParams};
+use crate::error::{Error, Result};
+use crate::polynomial::Polynomial;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, VecDeque};
+use std::panic;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/*
+ * Endpoints (same paths as https://api.aispf.global.fujitsu.com):
+ *   POST   /da/v3/async/qubo/solve          -> {"job_id": "..."}
+ *   GET    /da/v3/async/jobs                -> {"job_status_list": [{"job_id", "job_status", "start_time"}]}
+ *   GET    /da/v3/async/jobs/result/<id>    -> {"status": "Waiting" | "Running"} or the result
+ *   DELETE /da/v3/async/jobs/result/<id>    -> {"status": "Deleted"}
+ * Every request must carry the `X-Api-Key` header.
+ */
+
+const SOLVE_PATH: &str = "/da/v3/async/qubo/solve";
+const JOBS_PATH: &str = "/da/v3/async/jobs";
+const RESULT_PATH: &str = "/da/v3/async/jobs/result/";
+
+struct Job {
+    status: String,         // Waiting, Running, Done or Error
+    start_time: String,     // Seconds since the Unix epoch when the job was posted
+    request: Option<Value>, // Request body, taken by the worker
+    result: Option<Value>,  // Response of the solver once Done
+}
+
+#[derive(Default)]
+struct State {
+    jobs: BTreeMap<String, Job>,
+    queue: VecDeque<String>,
+    next_id: u64,
+}
+
+type Shared = Arc<(Mutex<State>, Condvar)>;
+
+fn now() -> String {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        .to_string()
+}
+
+// Solve the queued jobs one at a time, like the service does for a single account
+fn worker(shared: Shared) {
+    let (lock, condvar) = &*shared;
+    loop {
+        let (job_id, request): (String, Value) = {
+            let mut state = lock.lock().unwrap();
+            loop {
+                if let Some(job_id) = state.queue.pop_front() {
+                    // The job may have been deleted while waiting
+                    if let Some(job) = state.jobs.get_mut(&job_id) {
+                        job.status = "Running".to_string();
+                        let request: Value = job.request.take().unwrap();
+                        break (job_id, request);
+                    }
+                    continue;
+                }
+                state = condvar.wait(state).unwrap();
+            }
+        };
+
+        println!("Solving {}...", job_id);
+        // A malformed request must not stop the worker
+        let outcome = panic::catch_unwind(|| -> Result<Value> {
+            let polynomial = Polynomial::from_value(&request)?;
+            let params = DaParams::from_value(&request, None)?;
+            params.check(&polynomial)?;
+            Ok(digital_annealer(&polynomial, &params))
+        });
+
+        let mut state = lock.lock().unwrap();
+        if let Some(job) = state.jobs.get_mut(&job_id) {
+            match outcome {
+                Ok(Ok(result)) => {
+                    job.status = "Done".to_string();
+                    job.result = Some(result);
+                }
+                Ok(Err(e)) => {
+                    println!("Error: {} ({})", e, job_id);
+                    job.status = "Error".to_string();
+                    job.result = Some(json!({"status": "Error", "message": e.to_string()}));
+                }
+                Err(_) => {
+                    job.status = "Error".to_string();
+                    job.result = Some(json!({"status": "Error"}));
+                }
+            }
+        }
+    }
+}
+
+fn respond(request: Request, status: u16, body: Value) {
+    let header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(header);
+    if let Err(e) = request.respond(response) {
+        println!("Error: {}", e);
+    }
+}
+
+fn handle(mut request: Request, shared: &Shared, api_key: &str) {
+    let authorized: bool = request
+        .headers()
+        .iter()
+        .any(|header| header.field.equiv("X-Api-Key") && header.value.as_str() == api_key);
+    if !authorized {
+        return respond(request, 401, json!({"error": "Invalid X-Api-Key"}));
+    }
+
+    let (lock, condvar) = &**shared;
+    let url: String = request.url().trim_end_matches('/').to_string();
+    let method: Method = request.method().clone();
+    println!("{} {}", method, url);
+
+    match (method, url.as_str()) {
+        (Method::Post, SOLVE_PATH) => {
+            let mut body = String::new();
+            if let Err(e) = request.as_reader().read_to_string(&mut body) {
+                return respond(request, 400, json!({"error": e.to_string()}));
+            }
+            let payload: Value = match serde_json::from_str(&body) {
+                Ok(payload) => payload,
+                Err(e) => return respond(request, 400, json!({"error": e.to_string()})),
+            };
+            if !payload["binary_polynomial"]["terms"].is_array() && !payload["ising"].is_object() {
+                return respond(
+                    request,
+                    400,
+                    json!({"error": "binary_polynomial.terms or ising is required"}),
+                );
+            }
+
+            let mut state = lock.lock().unwrap();
+            state.next_id += 1;
+            let job_id: String = format!("mock-{:08}", state.next_id);
+            state.jobs.insert(
+                job_id.clone(),
+                Job {
+                    status: "Waiting".to_string(),
+                    start_time: now(),
+                    request: Some(payload),
+                    result: None,
+                },
+            );
+            state.queue.push_back(job_id.clone());
+            condvar.notify_one();
+            respond(request, 200, json!({"job_id": job_id}));
+        }
+        (Method::Get, JOBS_PATH) => {
+            let state = lock.lock().unwrap();
+            let list: Vec<Value> = state
+                .jobs
+                .iter()
+                .map(|(job_id, job)| {
+                    json!({"job_id": job_id, "job_status": job.status, "start_time": job.start_time})
+                })
+                .collect();
+            drop(state);
+            respond(request, 200, json!({"job_status_list": list}));
+        }
+        (Method::Get, path) if path.starts_with(RESULT_PATH) => {
+            let job_id: &str = &path[RESULT_PATH.len()..];
+            let state = lock.lock().unwrap();
+            let body: Option<Value> = state.jobs.get(job_id).map(|job| match &job.result {
+                Some(result) => result.clone(),
+                None => json!({"status": job.status}),
+            });
+            drop(state);
+            match body {
+                Some(body) => respond(request, 200, body),
+                None => respond(request, 404, json!({"error": "Job not found"})),
+            }
+        }
+        (Method::Delete, path) if path.starts_with(RESULT_PATH) => {
+            let job_id: &str = &path[RESULT_PATH.len()..];
+            let deleted: bool = lock.lock().unwrap().jobs.remove(job_id).is_some();
+            if deleted {
+                respond(request, 200, json!({"status": "Deleted"}));
+            } else {
+                respond(request, 404, json!({"error": "Job not found"}));
+            }
+        }
+        _ => respond(request, 404, json!({"error": "Not found"})),
+    }
+}
+
+pub fn mock_server(port: u16, api_key: String) -> Result<()> {
+    let address: String = format!("127.0.0.1:{}", port);
+    let server = Server::http(&address)
+        .map_err(|e| Error::parameter("--port", format!("cannot listen on {}: {}", address, e)))?;
+    println!("Mock DA3 server listening on http://{}", address);
+    serve(server, &api_key);
+    Ok(())
+}
+
+// Answer the requests of `server` until it is closed, with one worker solving the jobs
+fn serve(server: Server, api_key: &str) {
+    let shared: Shared = Arc::new((Mutex::new(State::default()), Condvar::new()));
+    let worker_shared: Shared = Arc::clone(&shared);
+    thread::spawn(move || worker(worker_shared));
+
+    for request in server.incoming_requests() {
+        handle(request, &shared, api_key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::result_reader::for_each_solution;
+    use crate::solution_set::{read_solution_set, SolutionSet};
+    use std::time::Duration;
+
+    const API_KEY: &str = "test-key";
+
+    // Server on an ephemeral port, its base URL
+    fn start() -> String {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let base: String = format!("http://{}", server.server_addr().to_ip().unwrap());
+        thread::spawn(move || serve(server, API_KEY));
+        base
+    }
+
+    // (HTTP status, body) of a request, with `api_key` when given
+    fn call(request: ureq::Request, api_key: Option<&str>, body: Option<&Value>) -> (u16, Value) {
+        let request: ureq::Request = match api_key {
+            Some(api_key) => request.set("X-Api-Key", api_key),
+            None => request,
+        };
+        let response = match body {
+            Some(body) => request.send_json(body),
+            None => request.call(),
+        };
+        match response {
+            Ok(response) => (response.status(), response.into_json().unwrap()),
+            Err(ureq::Error::Status(code, response)) => (code, response.into_json().unwrap()),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    // -x0 - x1 + 2 x0 x1 + x2, solved in one short run
+    fn request() -> Value {
+        json!({
+            "fujitsuDA3": {"time_limit_sec": 1, "num_run": 1, "num_output_solution": 2},
+            "binary_polynomial": {"terms": [
+                {"c": -1.0, "p": [0]},
+                {"c": -1.0, "p": [1]},
+                {"c": 2.0, "p": [0, 1]},
+                {"c": 1.0, "p": [2]},
+            ]},
+        })
+    }
+
+    // Post the request and wait until the job list shows it Done
+    fn solve(base: &str) -> String {
+        let (status, body) = call(
+            ureq::post(&format!("{}{}", base, SOLVE_PATH)),
+            Some(API_KEY),
+            Some(&request()),
+        );
+        assert_eq!(status, 200);
+        let job_id: String = body["job_id"].as_str().unwrap().to_string();
+
+        for _ in 0..300 {
+            let (status, body) = call(
+                ureq::get(&format!("{}{}", base, JOBS_PATH)),
+                Some(API_KEY),
+                None,
+            );
+            assert_eq!(status, 200);
+            let job: &Value = body["job_status_list"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|job| job["job_id"] == json!(job_id))
+                .unwrap();
+            if job["job_status"] == json!("Done") {
+                return job_id;
+            }
+            assert!(["Waiting", "Running"].contains(&job["job_status"].as_str().unwrap()));
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!("job {} not done", job_id);
+    }
+
+    #[test]
+    fn wrong_or_missing_api_key_is_unauthorized() {
+        let base: String = start();
+        let url: String = format!("{}{}", base, JOBS_PATH);
+        assert_eq!(call(ureq::get(&url), None, None).0, 401);
+        assert_eq!(call(ureq::get(&url), Some("other"), None).0, 401);
+        assert_eq!(call(ureq::get(&url), Some(API_KEY), None).0, 200);
+    }
+
+    #[test]
+    fn result_is_read_like_the_api_one() {
+        let base: String = start();
+        let job_id: String = solve(&base);
+        let url: String = format!("{}{}{}", base, RESULT_PATH, job_id);
+        let (status, result) = call(ureq::get(&url), Some(API_KEY), None);
+        assert_eq!(status, 200);
+        assert_eq!(result["status"], json!("Done"));
+
+        let directory = std::env::temp_dir().join(format!("fujitsu-mock-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path: String = directory.join("result.json").to_str().unwrap().to_string();
+        crate::write_json(&path, &result).unwrap();
+        let set: SolutionSet = read_solution_set(&path).unwrap();
+        assert!(set.len() >= 1);
+        assert_eq!(set.variables, vec![0, 1, 2]);
+        assert_eq!(set.energies[0], -1.0); // One of x0, x1 and not x2
+        let mut energies: Vec<f64> = Vec::new();
+        let count: usize =
+            for_each_solution(&path, 3, |_, detail| energies.push(detail.energy)).unwrap();
+        assert_eq!((count, energies), (set.len(), set.energies.clone()));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn deleted_and_unknown_jobs_are_not_found() {
+        let base: String = start();
+        let job_id: String = solve(&base);
+        let url: String = format!("{}{}{}", base, RESULT_PATH, job_id);
+        let (status, body) = call(ureq::delete(&url), Some(API_KEY), None);
+        assert_eq!((status, body), (200, json!({"status": "Deleted"})));
+        assert_eq!(call(ureq::get(&url), Some(API_KEY), None).0, 404);
+        assert_eq!(call(ureq::delete(&url), Some(API_KEY), None).0, 404);
+
+        let unknown: String = format!("{}{}mock-99999999", base, RESULT_PATH);
+        assert_eq!(call(ureq::get(&unknown), Some(API_KEY), None).0, 404);
+    }
+
+    #[test]
+    fn request_without_a_problem_is_refused() {
+        let base: String = start();
+        let url: String = format!("{}{}", base, SOLVE_PATH);
+        let (status, _) = call(
+            ureq::post(&url),
+            Some(API_KEY),
+            Some(&json!({"fujitsuDA3": {}})),
+        );
+        assert_eq!(status, 400);
+    }
+}
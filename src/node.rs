@@ -26,7 +26,7 @@ impl Node {
         sub_lattice: SubLattice,
         jxx: &Jxx,
     ) -> Node {
-        let j_value = jxx.j as f64; // Default J_{i,j} value
+        let j_value = jxx.j; // Default J_{i,j} value
         Node {
             index,
             right,
@@ -38,7 +38,7 @@ impl Node {
             j_right: j_value,
             j_bottom: j_value,
             j_btm_right: j_value,
-            j_layer_up: jxx.jl as f64,
+            j_layer_up: jxx.jl,
         }
     }
     pub fn print_info(&self) {
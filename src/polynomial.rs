@@ -0,0 +1,202 @@
+// Purpose: Contains the Polynomial struct (the binary_polynomial of a request) used by the local solvers
+use crate::error::Result;
+use crate::request::Request;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+
+pub struct Polynomial {
+    pub variables: Vec<i32>, // Sorted variable indices, position -> index
+    pub terms: Vec<(f64, Vec<usize>)>, // Coefficient and positions of each term
+    pub constant: f64,       // Sum of the constant terms
+    pub var_terms: Vec<Vec<usize>>, // Terms which contain each variable
+}
+
+impl Polynomial {
+    // Build from `(coefficient, polynomial)` pairs, an empty polynomial is a constant term
+    pub fn new(raw_terms: Vec<(f64, Vec<i32>)>) -> Polynomial {
+        let variables: Vec<i32> = raw_terms
+            .iter()
+            .flat_map(|(_, p)| p.iter().copied())
+            .collect::<BTreeSet<i32>>()
+            .into_iter()
+            .collect();
+
+        let mut terms: Vec<(f64, Vec<usize>)> = Vec::new();
+        let mut constant: f64 = 0.0;
+        for (c, p) in raw_terms {
+            if p.is_empty() {
+                constant += c;
+                continue;
+            }
+            let mut positions: Vec<usize> = p
+                .iter()
+                .map(|index| variables.binary_search(index).unwrap())
+                .collect();
+            positions.sort();
+            positions.dedup(); // x_i * x_i = x_i
+            terms.push((c, positions));
+        }
+
+        let mut var_terms: Vec<Vec<usize>> = vec![Vec::new(); variables.len()];
+        for (t, (_, positions)) in terms.iter().enumerate() {
+            for p in positions {
+                var_terms[*p].push(t);
+            }
+        }
+
+        Polynomial {
+            variables,
+            terms,
+            constant,
+            var_terms,
+        }
+    }
+
+    // Read `{"binary_polynomial": {"terms": [{"c": 4.0, "p": [0, 1]}, {"c": 1.0}, ...]}}`
+    // The Ising form and the penalties of the constraint features are included, see `Request::to_polynomial`
+    pub fn from_value(fujitsu: &Value) -> Result<Polynomial> {
+        Request::from_value(fujitsu)?.to_polynomial()
+    }
+
+    // `(coefficient, polynomial)` pairs, as given to `new`
+    pub fn raw_terms(&self) -> Vec<(f64, Vec<i32>)> {
+        let mut raw_terms: Vec<(f64, Vec<i32>)> = self
+            .terms
+            .iter()
+            .map(|(c, positions)| (*c, positions.iter().map(|p| self.variables[*p]).collect()))
+            .collect();
+        if self.constant != 0.0 {
+            raw_terms.push((self.constant, Vec::new()));
+        }
+        raw_terms
+    }
+
+    // Request in the QUBO form, the constant term last
+    pub fn to_value(&self) -> Value {
+        let mut term_list: Vec<Value> = self
+            .terms
+            .iter()
+            .map(|(c, positions)| {
+                let p: Vec<i32> = positions.iter().map(|p| self.variables[*p]).collect();
+                json!({"c": c, "p": p})
+            })
+            .collect();
+        if self.constant != 0.0 {
+            term_list.push(json!({"c": self.constant}));
+        }
+        json!({
+            "fujitsuDA3": {},
+            "binary_polynomial": {
+                "terms": term_list
+            }
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.variables.len()
+    }
+
+    // Variables sharing a term with each variable, whose flip delta changes when it flips
+    pub fn neighbors(&self) -> Vec<Vec<usize>> {
+        (0..self.len())
+            .map(|var| {
+                self.var_terms[var]
+                    .iter()
+                    .flat_map(|t| self.terms[*t].1.iter().copied())
+                    .filter(|other| *other != var)
+                    .collect::<BTreeSet<usize>>()
+                    .into_iter()
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn energy(&self, x: &[bool]) -> f64 {
+        let mut sum: f64 = self.constant;
+        for (c, positions) in &self.terms {
+            if positions.iter().all(|p| x[*p]) {
+                sum += c;
+            }
+        }
+        sum
+    }
+
+    // Energy difference of flipping variable `var` of `x`
+    pub fn flip_delta(&self, x: &[bool], var: usize) -> f64 {
+        let mut sum: f64 = 0.0;
+        for t in &self.var_terms[var] {
+            let (c, positions) = &self.terms[*t];
+            if positions.iter().all(|p| *p == var || x[*p]) {
+                sum += c;
+            }
+        }
+        if x[var] {
+            -sum
+        } else {
+            sum
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::random::get_rng;
+    use rand::Rng;
+
+    // Random linear, quadratic and cubic terms of the variables 0..n, with a constant
+    pub fn random_polynomial(n: i32, seed: u64) -> Polynomial {
+        let mut rng = get_rng(Some(seed));
+        let mut raw_terms: Vec<(f64, Vec<i32>)> = vec![(rng.gen_range(-2.0..2.0), Vec::new())];
+        for degree in 1..=3 {
+            for _ in 0..2 * n {
+                let p: Vec<i32> = (0..degree).map(|_| rng.gen_range(0..n)).collect();
+                raw_terms.push((rng.gen_range(-2.0..2.0), p));
+            }
+        }
+        for i in 0..n {
+            raw_terms.push((rng.gen_range(-2.0..2.0), vec![i])); // Every variable appears
+        }
+        Polynomial::new(raw_terms)
+    }
+
+    // Every configuration of `n` variables, the lowest bit first
+    pub fn configurations(n: usize) -> impl Iterator<Item = Vec<bool>> {
+        (0..1_u64 << n).map(move |state| (0..n).map(|p| (state >> p) & 1 == 1).collect())
+    }
+
+    #[test]
+    fn flip_delta_is_the_energy_difference() {
+        let polynomial: Polynomial = random_polynomial(6, 1);
+        for x in configurations(polynomial.len()) {
+            for var in 0..polynomial.len() {
+                let mut flipped: Vec<bool> = x.clone();
+                flipped[var] = !flipped[var];
+                let delta: f64 = polynomial.energy(&flipped) - polynomial.energy(&x);
+                assert!((polynomial.flip_delta(&x, var) - delta).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn new_merges_repeated_variables_and_constants() {
+        let polynomial = Polynomial::new(vec![
+            (2.0, vec![5, 5]),
+            (1.0, Vec::new()),
+            (-3.0, vec![9, 5]),
+            (0.5, Vec::new()),
+        ]);
+        assert_eq!(polynomial.variables, vec![5, 9]);
+        assert_eq!(polynomial.constant, 1.5);
+        assert_eq!(polynomial.terms, vec![(2.0, vec![0]), (-3.0, vec![0, 1])]);
+        assert_eq!(polynomial.energy(&[true, true]), 0.5);
+        assert_eq!(polynomial.neighbors(), vec![vec![1], vec![0]]);
+    }
+
+    #[test]
+    fn value_round_trip_keeps_the_terms() {
+        let polynomial: Polynomial = random_polynomial(5, 2);
+        let read: Polynomial = Polynomial::from_value(&polynomial.to_value()).unwrap();
+        assert_eq!(read.raw_terms(), polynomial.raw_terms());
+    }
+}
@@ -0,0 +1,231 @@
+// Purpose: Integer coefficients of a request (--scale-bits), with the scale factor and the quantisation error
+use crate::error::{Error, Result};
+use crate::ising::Ising;
+use crate::request::{Request, Term};
+use serde_json::{json, Map, Value};
+
+/*
+ * --scale-bits b: every coefficient c of the request becomes round(scale x c), an integer, with
+ *   scale = (2^(b-1) - 1) / max|c|   (max over the non-constant terms of every polynomial)
+ * so the largest one is +-(2^(b-1) - 1), a signed b-bit integer. One scale for the objective, the
+ * penalty and the inequalities keeps their balance (penalty_coef and lambda are left as they are).
+ * The constant (and the ising offset) is rounded the same way but not bounded to b bits: it multiplies
+ * no variable, so it is not a coefficient of the solver, it only shifts every energy by the same amount.
+ * Bounding it would change the energies, not the coefficients.
+ * Energies of the scaled request are about scale x E, the analysis divides them by the scale of the
+ * metadata. This is the only place where coefficients are rounded.
+ * Error of a term: |round(scale x c) - scale x c| / scale, in the units of the unscaled request.
+ * Its sum over a polynomial bounds the energy error of every configuration (|x|, |s| <= 1).
+ * The terms keep their order: sorted by their variable indices, the constant last.
+ */
+pub struct Quantization {
+    pub bits: u32,                         // Signed integer bits of the largest coefficient
+    pub scale: f64,                        // Integer coefficient = round(scale x c)
+    pub max_coefficient: f64,              // max|c| of the unscaled request
+    pub errors: Vec<(String, TermErrors)>, // Error of each scaled polynomial
+}
+
+// Quantisation error of one polynomial, in the units of the unscaled request
+#[derive(Default)]
+pub struct TermErrors {
+    pub terms: usize,      // Scaled terms, the constant included
+    pub max_error: f64,    // Largest error of a term
+    pub energy_bound: f64, // Sum of the errors, bound of the energy error
+}
+
+impl TermErrors {
+    // Scale `c` to an integer and add its error
+    fn round(&mut self, c: &mut f64, scale: f64) {
+        let scaled: f64 = *c * scale;
+        let error: f64 = (scaled.round() - scaled).abs() / scale;
+        *c = scaled.round();
+        self.terms += 1;
+        self.max_error = self.max_error.max(error);
+        self.energy_bound += error;
+    }
+
+    fn round_terms(terms: &mut [Term], scale: f64) -> TermErrors {
+        let mut errors = TermErrors::default();
+        for term in terms {
+            errors.round(&mut term.c, scale);
+        }
+        errors
+    }
+
+    fn to_value(&self) -> Value {
+        json!({
+            "terms": self.terms,
+            "max_error": self.max_error,
+            "energy_bound": self.energy_bound,
+        })
+    }
+}
+
+// Largest |c| of the non-constant terms
+fn max_abs(terms: &[Term]) -> f64 {
+    terms
+        .iter()
+        .filter(|term| !term.p.is_empty())
+        .fold(0.0, |max: f64, term| max.max(term.c.abs()))
+}
+
+impl Quantization {
+    // Scale the coefficients of `request` to signed `bits`-bit integers, 2 to 53 (exact in a f64)
+    pub fn apply(request: &mut Request, bits: u32) -> Result<Quantization> {
+        if !(2..=53).contains(&bits) {
+            return Err(Error::parameter(
+                "--scale-bits",
+                format!("{} is not an integer in [2, 53]", bits),
+            ));
+        }
+        let ising: Option<Ising> = match request.rest.get("ising") {
+            Some(value) => Some(Ising::from_value(value)?),
+            None => None,
+        };
+
+        let mut max_coefficient: f64 = max_abs(&request.penalty_binary_polynomial);
+        if let Some(terms) = &request.binary_polynomial {
+            max_coefficient = max_coefficient.max(max_abs(terms));
+        }
+        for inequality in &request.inequalities {
+            max_coefficient = max_coefficient.max(max_abs(&inequality.terms));
+        }
+        if let Some(ising) = &ising {
+            for c in ising.h.iter().chain(ising.j.iter().map(|(_, _, j)| j)) {
+                max_coefficient = max_coefficient.max(c.abs());
+            }
+        }
+        if max_coefficient == 0.0 {
+            return Err(Error::parameter("--scale-bits", "no coefficient to scale"));
+        }
+        let scale: f64 = ((1u64 << (bits - 1)) - 1) as f64 / max_coefficient;
+
+        let mut errors: Vec<(String, TermErrors)> = Vec::new();
+        if let Some(terms) = &mut request.binary_polynomial {
+            errors.push((
+                "binary_polynomial".to_string(),
+                TermErrors::round_terms(terms, scale),
+            ));
+        }
+        if let Some(mut ising) = ising {
+            let mut ising_errors = TermErrors::default();
+            for c in ising
+                .h
+                .iter_mut()
+                .chain(ising.j.iter_mut().map(|(_, _, j)| j))
+            {
+                ising_errors.round(c, scale);
+            }
+            if ising.offset != 0.0 {
+                ising_errors.round(&mut ising.offset, scale);
+            }
+            request.rest.insert("ising".to_string(), ising.to_value());
+            errors.push(("ising".to_string(), ising_errors));
+        }
+        if !request.penalty_binary_polynomial.is_empty() {
+            errors.push((
+                "penalty_binary_polynomial".to_string(),
+                TermErrors::round_terms(&mut request.penalty_binary_polynomial, scale),
+            ));
+        }
+        for (k, inequality) in request.inequalities.iter_mut().enumerate() {
+            errors.push((
+                format!("inequalities/{}", k),
+                TermErrors::round_terms(&mut inequality.terms, scale),
+            ));
+        }
+
+        Ok(Quantization {
+            bits,
+            scale,
+            max_coefficient,
+            errors,
+        })
+    }
+
+    pub fn print(&self) {
+        println!(
+            "Scaled to {}-bit integers, scale: {} (max|c|: {})",
+            self.bits, self.scale, self.max_coefficient
+        );
+        for (name, errors) in &self.errors {
+            println!(
+                "  {}: {} terms, max error: {:e}, energy error <= {:e}",
+                name, errors.terms, errors.max_error, errors.energy_bound
+            );
+        }
+    }
+
+    // Scale and errors, for the metadata
+    pub fn to_value(&self) -> Value {
+        let errors: Map<String, Value> = self
+            .errors
+            .iter()
+            .map(|(name, errors)| (name.clone(), errors.to_value()))
+            .collect();
+        json!({
+            "bits": self.bits,
+            "scale": self.scale,
+            "max_coefficient": self.max_coefficient,
+            "errors": errors,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polynomial::tests::{configurations, random_polynomial};
+    use crate::polynomial::Polynomial;
+
+    // Random polynomial of 6 variables with a constant far past the largest coefficient
+    fn request() -> (Polynomial, Request) {
+        let mut raw_terms: Vec<(f64, Vec<i32>)> = random_polynomial(6, 7).raw_terms();
+        raw_terms.push((100.0, Vec::new()));
+        let polynomial = Polynomial::new(raw_terms);
+        let request: Request = Request::from_value(&polynomial.to_value()).unwrap();
+        (polynomial, request)
+    }
+
+    #[test]
+    fn coefficients_fit_the_bits() {
+        let (_, mut request) = request();
+        let quantization: Quantization = Quantization::apply(&mut request, 5).unwrap();
+        let terms: &[Term] = request.binary_polynomial.as_ref().unwrap();
+        let max: f64 = terms
+            .iter()
+            .filter(|term| !term.p.is_empty())
+            .fold(0.0, |max: f64, term| max.max(term.c.abs()));
+        assert_eq!(max, 15.0);
+        assert!(terms.iter().all(|term| term.c == term.c.round()));
+
+        // The constant is scaled but exempt from the range
+        let constant: &Term = terms.last().unwrap();
+        assert!(constant.p.is_empty());
+        assert!(constant.c.abs() > 15.0);
+        assert_eq!(quantization.errors[0].1.terms, terms.len());
+    }
+
+    #[test]
+    fn energy_error_is_bounded() {
+        let (polynomial, mut request) = request();
+        let quantization: Quantization = Quantization::apply(&mut request, 6).unwrap();
+        let scaled: Polynomial = request.to_polynomial().unwrap();
+        let bound: f64 = quantization.errors[0].1.energy_bound;
+        for x in configurations(6) {
+            let error: f64 = (scaled.energy(&x) / quantization.scale - polynomial.energy(&x)).abs();
+            assert!(error <= bound + 1e-9, "{} > {}", error, bound);
+        }
+    }
+
+    #[test]
+    fn bits_out_of_range() {
+        for bits in [1, 54] {
+            let (_, mut request) = request();
+            assert!(matches!(
+                Quantization::apply(&mut request, bits),
+                Err(Error::Parameter { .. })
+            ));
+        }
+    }
+}
@@ -1,62 +1,66 @@
-use rand::prelude::*;
+use crate::lattice::Lattice;
 use crate::Jxx;
-use crate::NODES;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+fn get_random(rng: &mut StdRng, max: f64) -> f64 {
+    rng.gen_range(0.0..max)
+}
 
-fn get_random(max: f64) -> f64 {
-    let mut rng = thread_rng();
-    let result = rng.gen_range(0.0..max);
-    return (result * 100.0).round() / 100.0;
+// Seeded generator of the local solvers, seeded from the OS when `seed` is None
+pub fn get_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
 }
 
-pub fn random_strength(jxx: &Jxx) {
+// Random strengths, reproducible with `seed`
+pub fn random_strength(jxx: &Jxx, lattice: &mut Lattice, seed: Option<u64>) {
     #![allow(non_snake_case)]
     let H: i32 = jxx.h; // Height of the triangular lattice.
     let L2: i32 = jxx.l * jxx.l; // L^2
 
+    let mut rng: StdRng = get_rng(seed);
     let mut rand_array = vec![0.0; (L2 * 3) as usize]; // Build a array of random numbers
     for i in 0..(L2 * 3) {
-        rand_array[i as usize] = get_random(100.0);
+        rand_array[i as usize] = get_random(&mut rng, 100.0);
     }
 
-    let layer_rand = get_random(100.0);
+    let layer_rand = get_random(&mut rng, 100.0);
+
+    // Set the nodes' strength to the random values (Only for the first layer)
+    let (mut ndx, mut idx): (usize, usize) = (0, 0);
+    loop {
+        if ndx >= L2 as usize {
+            break;
+        }
+        // println!("ndx: {}", ndx);
+        lattice.nodes[ndx].j_right = rand_array[idx];
+        lattice.nodes[ndx].j_bottom = rand_array[idx + 1];
+        lattice.nodes[ndx].j_btm_right = rand_array[idx + 2];
+        lattice.nodes[ndx].j_layer_up = layer_rand;
+        idx += 3;
+        ndx += 1;
+    }
 
-    unsafe {
-        // Set the nodes' strength to the random values (Only for the first layer)
+    // Set the nodes' strength to the random values (For the other layers)
+    for h in 1..H {
+        // Set from height == 1
         let (mut ndx, mut idx): (usize, usize) = (0, 0);
         loop {
             if ndx >= L2 as usize {
                 break;
             }
-            // println!("ndx: {}", ndx);
-            NODES[ndx].j_right = rand_array[idx];
-            NODES[ndx].j_bottom = rand_array[idx + 1];
-            NODES[ndx].j_btm_right = rand_array[idx + 2];
-            NODES[ndx].j_layer_up = layer_rand;
+            let nndx: usize = (h * L2) as usize + ndx;
+            lattice.nodes[nndx].j_right = rand_array[idx];
+            lattice.nodes[nndx].j_bottom = rand_array[idx + 1];
+            lattice.nodes[nndx].j_btm_right = rand_array[idx + 2];
+            lattice.nodes[nndx].j_layer_up = layer_rand;
             idx += 3;
             ndx += 1;
         }
     }
 
-    unsafe {
-        // Set the nodes' strength to the random values (For the other layers)
-        for h in 1..H {
-            // Set from height == 1
-            let (mut ndx, mut idx): (usize, usize) = (0, 0);
-            loop {
-                if ndx >= L2 as usize {
-                    break;
-                }
-                let nndx: usize = ((h * L2) as usize + ndx) as usize;
-                NODES[nndx].j_right = rand_array[idx];
-                NODES[nndx].j_bottom = rand_array[idx + 1];
-                NODES[nndx].j_btm_right = rand_array[idx + 2];
-                NODES[nndx].j_layer_up = layer_rand;
-                idx += 3;
-                ndx += 1;
-            }
-        }
-    }
-
     // print_node_info();
-    return;
 }
@@ -0,0 +1,416 @@
+// Purpose: Typed request of the Fujitsu DA3 API, with the constraint features and their builder helpers
+use crate::error::{Error, Result};
+use crate::ising::Ising;
+use crate::polynomial::Polynomial;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeSet;
+
+/*
+ * {
+ *   "fujitsuDA3": {"time_limit_sec": 10, "penalty_coef": 1, ...},
+ *   "binary_polynomial": {"terms": [{"c": 4.0, "p": [0, 1]}, {"c": 1.0}, ...]},      // Objective
+ *   "penalty_binary_polynomial": {"terms": [...]},                                   // Constraint, 0 when satisfied
+ *   "one_way_one_hot_groups": {"numbers": [3, 3]},                                   // Exactly one bit of each group
+ *   "two_way_one_hot_groups": {"numbers": [4]},                                      // n x n block, one bit per row and column
+ *   "inequalities": [{"terms": [{"c": 1.0, "p": [0]}, ..., {"c": -2.0}], "lambda": 1.0}] // \sum terms <= 0
+ * }
+ * The one-hot groups are consecutive blocks of variables from index 0, the one-way groups first.
+ * Every other key (e.g. the Ising form `ising`) is kept as is.
+ */
+#[derive(Debug, Clone)]
+pub struct Term {
+    pub c: f64,      // Coefficient
+    pub p: Vec<i32>, // Variable indices, empty for the constant term
+}
+
+#[derive(Debug, Clone)]
+pub struct Inequality {
+    pub terms: Vec<Term>, // Linear polynomial, \sum terms <= 0 when satisfied
+    pub lambda: f64,      // Weight of the violation
+}
+
+#[derive(Debug, Default)]
+pub struct Request {
+    pub da3: Map<String, Value>,              // fujitsuDA3 parameters
+    pub binary_polynomial: Option<Vec<Term>>, // Objective, None in the Ising form
+    pub penalty_binary_polynomial: Vec<Term>, // Penalty, weighted by penalty_coef
+    pub one_way_one_hot_groups: Vec<usize>,   // Size of each one-way group
+    pub two_way_one_hot_groups: Vec<usize>,   // Side of each two-way group
+    pub inequalities: Vec<Inequality>,        // Linear inequalities
+    pub rest: Map<String, Value>,             // Other keys of the request
+}
+
+// Terms of the polynomial at `pointer` (ex: "/binary_polynomial")
+fn terms_from_value(value: &Value, pointer: &str) -> Result<Vec<Term>> {
+    let terms = value["terms"]
+        .as_array()
+        .ok_or_else(|| Error::content(format!("{}/terms", pointer), "not an array"))?;
+    let mut term_list: Vec<Term> = Vec::with_capacity(terms.len());
+    for (k, term) in terms.iter().enumerate() {
+        let term_pointer: String = format!("{}/terms/{}", pointer, k);
+        let c: f64 = term["c"]
+            .as_f64()
+            .ok_or_else(|| Error::content(format!("{}/c", term_pointer), "not a number"))?;
+        let p: Vec<i32> = match term.get("p") {
+            Some(p) => p
+                .as_array()
+                .ok_or_else(|| Error::content(format!("{}/p", term_pointer), "not an array"))?
+                .iter()
+                .enumerate()
+                .map(|(n, i)| {
+                    i.as_i64().map(|i| i as i32).ok_or_else(|| {
+                        Error::content(format!("{}/p/{}", term_pointer, n), "not an integer")
+                    })
+                })
+                .collect::<Result<Vec<i32>>>()?,
+            None => Vec::new(),
+        };
+        term_list.push(Term { c, p });
+    }
+    Ok(term_list)
+}
+
+fn terms_to_value(terms: &[Term]) -> Value {
+    let term_list: Vec<Value> = terms
+        .iter()
+        .map(|term| {
+            if term.p.is_empty() {
+                json!({"c": term.c})
+            } else {
+                json!({"c": term.c, "p": term.p})
+            }
+        })
+        .collect();
+    json!({ "terms": term_list })
+}
+
+fn numbers_from_value(value: &Value, pointer: &str) -> Result<Vec<usize>> {
+    match value.get("numbers") {
+        Some(numbers) => numbers
+            .as_array()
+            .ok_or_else(|| Error::content(format!("{}/numbers", pointer), "not an array"))?
+            .iter()
+            .enumerate()
+            .map(|(k, n)| match n.as_u64() {
+                Some(n) if n > 0 => Ok(n as usize),
+                _ => Err(Error::content(
+                    format!("{}/numbers/{}", pointer, k),
+                    "not a positive integer",
+                )),
+            })
+            .collect(),
+        None => Ok(Vec::new()),
+    }
+}
+
+impl Request {
+    // Errors carry the JSON pointer of the invalid value, the caller names the file
+    pub fn from_value(fujitsu: &Value) -> Result<Request> {
+        let mut request = Request::default();
+        let object = fujitsu
+            .as_object()
+            .ok_or_else(|| Error::content("", "the request is not an object"))?;
+        for (key, value) in object {
+            let pointer: String = format!("/{}", key);
+            match key.as_str() {
+                "fujitsuDA3" => {
+                    request.da3 = value
+                        .as_object()
+                        .ok_or_else(|| Error::content(pointer, "not an object"))?
+                        .clone()
+                }
+                "binary_polynomial" => {
+                    request.binary_polynomial = Some(terms_from_value(value, &pointer)?)
+                }
+                "penalty_binary_polynomial" => {
+                    request.penalty_binary_polynomial = terms_from_value(value, &pointer)?
+                }
+                "one_way_one_hot_groups" => {
+                    request.one_way_one_hot_groups = numbers_from_value(value, &pointer)?
+                }
+                "two_way_one_hot_groups" => {
+                    request.two_way_one_hot_groups = numbers_from_value(value, &pointer)?
+                }
+                "inequalities" => {
+                    let inequalities = value
+                        .as_array()
+                        .ok_or_else(|| Error::content(pointer.clone(), "not an array"))?;
+                    for (k, inequality) in inequalities.iter().enumerate() {
+                        request.inequalities.push(Inequality {
+                            terms: terms_from_value(inequality, &format!("{}/{}", pointer, k))?,
+                            lambda: inequality["lambda"].as_f64().unwrap_or(1.0),
+                        });
+                    }
+                }
+                _ => {
+                    request.rest.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        Ok(request)
+    }
+
+    pub fn to_value(&self) -> Value {
+        let mut object: Map<String, Value> = self.rest.clone();
+        object.insert("fujitsuDA3".to_string(), Value::Object(self.da3.clone()));
+        if let Some(terms) = &self.binary_polynomial {
+            object.insert("binary_polynomial".to_string(), terms_to_value(terms));
+        }
+        if !self.penalty_binary_polynomial.is_empty() {
+            object.insert(
+                "penalty_binary_polynomial".to_string(),
+                terms_to_value(&self.penalty_binary_polynomial),
+            );
+        }
+        if !self.one_way_one_hot_groups.is_empty() {
+            object.insert(
+                "one_way_one_hot_groups".to_string(),
+                json!({"numbers": self.one_way_one_hot_groups}),
+            );
+        }
+        if !self.two_way_one_hot_groups.is_empty() {
+            object.insert(
+                "two_way_one_hot_groups".to_string(),
+                json!({"numbers": self.two_way_one_hot_groups}),
+            );
+        }
+        if !self.inequalities.is_empty() {
+            let list: Vec<Value> = self
+                .inequalities
+                .iter()
+                .map(|inequality| {
+                    let mut value: Value = terms_to_value(&inequality.terms);
+                    value["lambda"] = json!(inequality.lambda);
+                    value
+                })
+                .collect();
+            object.insert("inequalities".to_string(), Value::Array(list));
+        }
+        Value::Object(object)
+    }
+
+    // Penalty (\sum_i a_i x_i - target)^2, zero only when the equality holds
+    pub fn add_equality(&mut self, terms: &[(f64, i32)], target: f64, weight: f64) {
+        for (k, (a, i)) in terms.iter().enumerate() {
+            // x_i^2 = x_i
+            self.penalty_binary_polynomial.push(Term {
+                c: weight * (a * a - 2.0 * target * a),
+                p: vec![*i],
+            });
+            for (b, j) in &terms[k + 1..] {
+                self.penalty_binary_polynomial.push(Term {
+                    c: weight * 2.0 * a * b,
+                    p: vec![*i, *j],
+                });
+            }
+        }
+        self.penalty_binary_polynomial.push(Term {
+            c: weight * target * target,
+            p: Vec::new(),
+        });
+    }
+
+    // Inequality \sum_i a_i x_i <= bound
+    pub fn add_inequality(&mut self, terms: &[(f64, i32)], bound: f64, lambda: f64) {
+        let mut inequality_terms: Vec<Term> = terms
+            .iter()
+            .map(|(a, i)| Term { c: *a, p: vec![*i] })
+            .collect();
+        inequality_terms.push(Term {
+            c: -bound,
+            p: Vec::new(),
+        });
+        self.inequalities.push(Inequality {
+            terms: inequality_terms,
+            lambda,
+        });
+    }
+
+    // Equality \sum_i a_i x_i = target as the pair of DA inequalities <= target and >= target
+    pub fn add_equality_inequalities(&mut self, terms: &[(f64, i32)], target: f64, lambda: f64) {
+        let negated: Vec<(f64, i32)> = terms.iter().map(|(a, i)| (-a, *i)).collect();
+        self.add_inequality(terms, target, lambda);
+        self.add_inequality(&negated, -target, lambda);
+    }
+
+    /*
+     * Constraints file of `--constraints`, every key optional:
+     * {
+     *   "equalities": [{"terms": [[1.0, 0], [1.0, 1]], "target": 1.0, "weight": 2.0, "feature": "penalty"}],
+     *   "inequalities": [{"terms": [[1.0, 0], [1.0, 1]], "bound": 1.0, "lambda": 1.0}],
+     *   "one_way_one_hot": [3, 3],
+     *   "two_way_one_hot": [4]
+     * }
+     * terms are [coefficient, variable] pairs, an equality is a penalty (default) or a pair of "inequalities".
+     */
+    pub fn add_constraints(&mut self, constraints: &Value) -> Result<()> {
+        let pairs = |value: &Value, pointer: &str| -> Result<Vec<(f64, i32)>> {
+            let terms = value["terms"]
+                .as_array()
+                .ok_or_else(|| Error::content(format!("{}/terms", pointer), "not an array"))?;
+            let mut pair_list: Vec<(f64, i32)> = Vec::with_capacity(terms.len());
+            for (k, pair) in terms.iter().enumerate() {
+                match (pair[0].as_f64(), pair[1].as_i64()) {
+                    (Some(a), Some(i)) => pair_list.push((a, i as i32)),
+                    _ => {
+                        return Err(Error::content(
+                            format!("{}/terms/{}", pointer, k),
+                            "not a [coefficient, variable] pair",
+                        ))
+                    }
+                }
+            }
+            Ok(pair_list)
+        };
+        let list =
+            |key: &str| -> Vec<Value> { constraints[key].as_array().cloned().unwrap_or_default() };
+
+        for (k, equality) in list("equalities").iter().enumerate() {
+            let pointer: String = format!("/equalities/{}", k);
+            let target: f64 = equality["target"]
+                .as_f64()
+                .ok_or_else(|| Error::content(format!("{}/target", pointer), "not a number"))?;
+            let weight: f64 = equality["weight"].as_f64().unwrap_or(1.0);
+            match equality["feature"].as_str().unwrap_or("penalty") {
+                "penalty" => self.add_equality(&pairs(equality, &pointer)?, target, weight),
+                "inequalities" => {
+                    self.add_equality_inequalities(&pairs(equality, &pointer)?, target, weight)
+                }
+                feature => {
+                    return Err(Error::content(
+                        format!("{}/feature", pointer),
+                        format!("unknown feature {:?} (penalty, inequalities)", feature),
+                    ))
+                }
+            }
+        }
+        for (k, inequality) in list("inequalities").iter().enumerate() {
+            let pointer: String = format!("/inequalities/{}", k);
+            let bound: f64 = inequality["bound"]
+                .as_f64()
+                .ok_or_else(|| Error::content(format!("{}/bound", pointer), "not a number"))?;
+            let lambda: f64 = inequality["lambda"].as_f64().unwrap_or(1.0);
+            self.add_inequality(&pairs(inequality, &pointer)?, bound, lambda);
+        }
+        for (key, two_way) in [("one_way_one_hot", false), ("two_way_one_hot", true)] {
+            for (k, size) in list(key).iter().enumerate() {
+                let size: usize = match size.as_u64() {
+                    Some(size) if size > 0 => size as usize,
+                    _ => {
+                        return Err(Error::content(
+                            format!("/{}/{}", key, k),
+                            "not a positive integer",
+                        ))
+                    }
+                };
+                if two_way {
+                    self.add_two_way_one_hot(size);
+                } else {
+                    self.add_one_way_one_hot(size);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Next `size` variables (after the existing groups) with exactly one bit set
+    pub fn add_one_way_one_hot(&mut self, size: usize) {
+        if size == 0 {
+            panic!("A one-hot group needs at least 1 variable.");
+        }
+        self.one_way_one_hot_groups.push(size);
+    }
+
+    // Next `side` x `side` variables with exactly one bit set in each row and column
+    pub fn add_two_way_one_hot(&mut self, side: usize) {
+        if side == 0 {
+            panic!("A one-hot group needs at least 1 variable.");
+        }
+        self.two_way_one_hot_groups.push(side);
+    }
+
+    // Rows and columns of the one-hot groups, as lists of variable indices
+    fn one_hot_lines(&self) -> Vec<Vec<i32>> {
+        let mut lines: Vec<Vec<i32>> = Vec::new();
+        let mut start: i32 = 0;
+        for size in &self.one_way_one_hot_groups {
+            lines.push((start..start + *size as i32).collect());
+            start += *size as i32;
+        }
+        for side in &self.two_way_one_hot_groups {
+            let side: i32 = *side as i32;
+            for r in 0..side {
+                lines.push((0..side).map(|c| start + r * side + c).collect());
+                lines.push((0..side).map(|c| start + c * side + r).collect());
+            }
+            start += side * side;
+        }
+        lines
+    }
+
+    // Every variable index of the problem: objective (or Ising form), penalty, one-hot groups and inequalities
+    pub fn variables(&self) -> Result<BTreeSet<i32>> {
+        let mut variables: BTreeSet<i32> = BTreeSet::new();
+        match (&self.binary_polynomial, self.rest.get("ising")) {
+            (Some(terms), _) => variables.extend(terms.iter().flat_map(|t| t.p.iter().copied())),
+            (None, Some(ising)) => {
+                variables.extend(Ising::from_value(ising)?.to_polynomial().variables)
+            }
+            (None, None) => (),
+        }
+        variables.extend(
+            self.penalty_binary_polynomial
+                .iter()
+                .flat_map(|t| t.p.iter().copied()),
+        );
+        variables.extend(self.one_hot_lines().into_iter().flatten());
+        for inequality in &self.inequalities {
+            variables.extend(inequality.terms.iter().flat_map(|t| t.p.iter().copied()));
+        }
+        Ok(variables)
+    }
+
+    /*
+     * Unconstrained polynomial minimised by the local solvers:
+     *   objective + penalty_coef * (penalty + \sum_{one-hot lines} (\sum x - 1)^2)
+     * The inequalities are only handled by the Digital Annealer.
+     */
+    pub fn to_polynomial(&self) -> Result<Polynomial> {
+        if !self.inequalities.is_empty() {
+            return Err(Error::content(
+                "/inequalities",
+                "only supported by the Digital Annealer API",
+            ));
+        }
+        let mut raw_terms: Vec<(f64, Vec<i32>)> = match &self.binary_polynomial {
+            Some(terms) => terms.iter().map(|t| (t.c, t.p.clone())).collect(),
+            None => match self.rest.get("ising") {
+                Some(ising) => Ising::from_value(ising)?.to_polynomial().raw_terms(),
+                None => {
+                    return Err(Error::content(
+                        "",
+                        "the request has neither binary_polynomial nor ising",
+                    ))
+                }
+            },
+        };
+
+        let mut penalty = Request {
+            penalty_binary_polynomial: self.penalty_binary_polynomial.clone(),
+            ..Default::default()
+        };
+        for line in self.one_hot_lines() {
+            let terms: Vec<(f64, i32)> = line.iter().map(|i| (1.0, *i)).collect();
+            penalty.add_equality(&terms, 1.0, 1.0);
+        }
+        let penalty_coef: f64 = self
+            .da3
+            .get("penalty_coef")
+            .and_then(|c| c.as_f64())
+            .unwrap_or(1.0);
+        for term in penalty.penalty_binary_polynomial {
+            raw_terms.push((penalty_coef * term.c, term.p));
+        }
+        Ok(Polynomial::new(raw_terms))
+    }
+}
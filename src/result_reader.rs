@@ -0,0 +1,552 @@
+// Purpose: Stream the solutions of a DA result file without loading the whole file
+use crate::solution_set::{is_solution_set, SolutionSetFile};
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/*
+ * Only `qubo_solution.solutions` is decoded, every other value is skipped while reading:
+ *   {"qubo_solution": {"solutions": [{"configuration": {"0": true, ...}, "energy", "frequency"}, ...], ...}, ...}
+ * Each solution is handed to the callback, then dropped, so the memory stays bounded by one solution.
+ * An index at or past the number of variables of the problem, or a solution without its energy, stops
+ * the reading with a content error (the vector of a configuration is never longer than the problem).
+ */
+pub struct SolutionRecord {
+    pub configuration: Vec<Option<bool>>, // Value of each variable, indexed by the variable index (missing = None)
+    pub energy: f64,                      // Energy reported by the solver
+    pub frequency: i32,                   // Times the configuration was found
+}
+
+// Parses the string key "12" without allocating it
+struct IndexVisitor;
+
+impl<'de> Visitor<'de> for IndexVisitor {
+    type Value = usize;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a variable index")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, key: &str) -> Result<usize, E> {
+        key.parse()
+            .map_err(|_| E::custom(format!("invalid index {:?}", key)))
+    }
+}
+
+struct IndexSeed;
+
+impl<'de> DeserializeSeed<'de> for IndexSeed {
+    type Value = usize;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+        deserializer.deserialize_str(IndexVisitor)
+    }
+}
+
+// Why the reading of a solution stopped, turned into a content error by for_each_solution
+#[derive(Clone, Copy)]
+enum Invalid {
+    Index(usize), // Variable index past the problem
+    NoEnergy,     // Solution without "energy"
+}
+
+// Decodes {"0": true, "1": false, ...} straight into a vector indexed by the variable index
+struct ConfigurationVisitor<'a> {
+    variables: usize,                   // Indices are below it
+    invalid: &'a Cell<Option<Invalid>>, // Set when the reading is stopped
+}
+
+impl<'de> Visitor<'de> for ConfigurationVisitor<'_> {
+    type Value = Vec<Option<bool>>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a map of variable index to bool")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Vec<Option<bool>>, A::Error> {
+        let mut bits: Vec<Option<bool>> = Vec::new();
+        while let Some(index) = map.next_key_seed(IndexSeed)? {
+            let value: bool = map.next_value()?;
+            if index >= self.variables {
+                self.invalid.set(Some(Invalid::Index(index)));
+                return Err(serde::de::Error::custom(format!("invalid index {}", index)));
+            }
+            if index >= bits.len() {
+                bits.resize(index + 1, None);
+            }
+            bits[index] = Some(value);
+        }
+        Ok(bits)
+    }
+}
+
+struct ConfigurationSeed<'a>(ConfigurationVisitor<'a>);
+
+impl<'de> DeserializeSeed<'de> for ConfigurationSeed<'_> {
+    type Value = Vec<Option<bool>>;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Vec<Option<bool>>, D::Error> {
+        deserializer.deserialize_map(self.0)
+    }
+}
+
+struct SolutionVisitor<'a> {
+    variables: usize,
+    invalid: &'a Cell<Option<Invalid>>,
+}
+
+impl<'de> Visitor<'de> for SolutionVisitor<'_> {
+    type Value = SolutionRecord;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a solution object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<SolutionRecord, A::Error> {
+        let mut record = SolutionRecord {
+            configuration: Vec::new(),
+            energy: 0.0,
+            frequency: 1,
+        };
+        let mut has_energy: bool = false;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "configuration" => {
+                    record.configuration =
+                        map.next_value_seed(ConfigurationSeed(ConfigurationVisitor {
+                            variables: self.variables,
+                            invalid: self.invalid,
+                        }))?
+                }
+                "energy" => {
+                    record.energy = map.next_value()?;
+                    has_energy = true;
+                }
+                "frequency" => record.frequency = map.next_value()?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        if !has_energy {
+            self.invalid.set(Some(Invalid::NoEnergy));
+            return Err(serde::de::Error::missing_field("energy"));
+        }
+        Ok(record)
+    }
+}
+
+struct SolutionSeed<'a>(SolutionVisitor<'a>);
+
+impl<'de> DeserializeSeed<'de> for SolutionSeed<'_> {
+    type Value = SolutionRecord;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<SolutionRecord, D::Error> {
+        deserializer.deserialize_map(self.0)
+    }
+}
+
+// Calls `f(index, solution)` for each element of the solutions array, returns the count
+struct SolutionsSeed<'a, F: FnMut(usize, SolutionRecord)> {
+    f: &'a mut F,
+    variables: usize,
+    invalid: &'a Cell<Option<Invalid>>,
+    count: &'a Cell<usize>, // Solutions handed to `f`, the index of the invalid one
+}
+
+impl<'de, F: FnMut(usize, SolutionRecord)> Visitor<'de> for SolutionsSeed<'_, F> {
+    type Value = usize;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a list of solutions")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<usize, A::Error> {
+        while let Some(record) = seq.next_element_seed(SolutionSeed(SolutionVisitor {
+            variables: self.variables,
+            invalid: self.invalid,
+        }))? {
+            (self.f)(self.count.get(), record);
+            self.count.set(self.count.get() + 1);
+        }
+        Ok(self.count.get())
+    }
+}
+
+impl<'de, F: FnMut(usize, SolutionRecord)> DeserializeSeed<'de> for SolutionsSeed<'_, F> {
+    type Value = usize;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+// Walks an object down to `path`, skipping the other keys, then deserializes it with `seed`
+struct PathSeed<'p, S> {
+    path: &'p [&'p str],
+    seed: S,
+}
+
+impl<'de, S: DeserializeSeed<'de, Value = usize>> Visitor<'de> for PathSeed<'_, S> {
+    type Value = usize;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an object with {:?}", self.path)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<usize, A::Error> {
+        let mut seed: Option<S> = Some(self.seed);
+        let mut count: Option<usize> = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match seed.take() {
+                Some(inner) if key == self.path[0] => {
+                    count = Some(if self.path.len() == 1 {
+                        map.next_value_seed(inner)?
+                    } else {
+                        map.next_value_seed(PathSeed {
+                            path: &self.path[1..],
+                            seed: inner,
+                        })?
+                    });
+                }
+                other => {
+                    seed = other;
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        count.ok_or_else(|| serde::de::Error::custom(format!("missing field `{}`", self.path[0])))
+    }
+}
+
+impl<'de, S: DeserializeSeed<'de, Value = usize>> DeserializeSeed<'de> for PathSeed<'_, S> {
+    type Value = usize;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+// Reads the top-level "metadata" of a result, stopping there or at "qubo_solution": the keys are
+// written sorted, so the metadata saved with a result comes before its solutions
+struct MetadataVisitor<'a> {
+    metadata: &'a Cell<Option<serde_json::Value>>,
+    stopped: &'a Cell<bool>, // The reading stopped before the end, not an error
+}
+
+impl<'de> Visitor<'de> for MetadataVisitor<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a result object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "metadata" => self.metadata.set(Some(map.next_value()?)),
+                "qubo_solution" => {}
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                    continue;
+                }
+            }
+            self.stopped.set(true);
+            return Err(serde::de::Error::custom("stopped"));
+        }
+        Ok(())
+    }
+}
+
+// The "metadata" saved with a result (or in the header of a packed .sols file), if any
+pub fn result_metadata<P: AsRef<Path>>(
+    path: P,
+) -> Result<Option<serde_json::Value>, Box<dyn Error>> {
+    let packed_path: String = path.as_ref().to_string_lossy().to_string();
+    if is_solution_set(&packed_path) {
+        let set: SolutionSetFile = SolutionSetFile::open(&packed_path)?;
+        return Ok(set.header().get("metadata").cloned());
+    }
+
+    let file = File::open(path)?;
+    let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(file));
+    let (metadata, stopped): (Cell<Option<serde_json::Value>>, Cell<bool>) =
+        (Cell::new(None), Cell::new(false));
+    let read = deserializer.deserialize_map(MetadataVisitor {
+        metadata: &metadata,
+        stopped: &stopped,
+    });
+    match read {
+        Err(_) if stopped.get() => Ok(metadata.take()),
+        read => {
+            read?;
+            Ok(None)
+        }
+    }
+}
+
+// Stream the solutions of a result file (or of a packed .sols file) of a problem of `variables`
+// variables (indices 0 to variables - 1), returns the number of solutions
+pub fn for_each_solution<P: AsRef<Path>, F: FnMut(usize, SolutionRecord)>(
+    path: P,
+    variables: usize,
+    mut f: F,
+) -> Result<usize, Box<dyn Error>> {
+    let packed_path: String = path.as_ref().to_string_lossy().to_string();
+    if is_solution_set(&packed_path) {
+        let mut set: SolutionSetFile = SolutionSetFile::open(&packed_path)?;
+        // The variables are sorted, the last one is the largest
+        if let Some(last) = set
+            .variables
+            .last()
+            .filter(|last| **last as usize >= variables)
+        {
+            return Err(Box::new(out_of_range(
+                "/variables",
+                *last as usize,
+                variables,
+            )));
+        }
+        for k in 0..set.len() {
+            let record = SolutionRecord {
+                configuration: set.configuration_by_index(k)?,
+                energy: set.energies[k],
+                frequency: set.frequencies[k],
+            };
+            f(k, record);
+        }
+        return Ok(set.len());
+    }
+
+    let file = File::open(path)?;
+    let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(file));
+    let (invalid, count): (Cell<Option<Invalid>>, Cell<usize>) = (Cell::new(None), Cell::new(0));
+    let read = PathSeed {
+        path: &["qubo_solution", "solutions"],
+        seed: SolutionsSeed {
+            f: &mut f,
+            variables,
+            invalid: &invalid,
+            count: &count,
+        },
+    }
+    .deserialize(&mut deserializer);
+    let pointer: String = format!("/qubo_solution/solutions/{}", count.get());
+    match (read, invalid.get()) {
+        (Err(_), Some(Invalid::Index(index))) => Err(Box::new(out_of_range(
+            &format!("{}/configuration", pointer),
+            index,
+            variables,
+        ))),
+        (Err(_), Some(Invalid::NoEnergy)) => Err(Box::new(crate::error::Error::content(
+            format!("{}/energy", pointer),
+            "missing",
+        ))),
+        (read, _) => {
+            let count: usize = read?;
+            deserializer.end()?;
+            Ok(count)
+        }
+    }
+}
+
+fn out_of_range(pointer: &str, index: usize, variables: usize) -> crate::error::Error {
+    crate::error::Error::content(
+        pointer,
+        format!(
+            "variable {} is outside the {} variables of the problem",
+            index, variables
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gamma_analysis::calc_c6_order_p;
+    use crate::lattice::Lattice;
+    use crate::solution_set::SolutionSet;
+    use serde_json::{json, Value};
+    use std::fs;
+
+    // Variable 1 is missing from the first solution, the other keys are skipped
+    fn result() -> Value {
+        json!({
+            "status": "Done",
+            "qubo_solution": {
+                "progress": [{"energy": -1.0}],
+                "solutions": [
+                    {"energy": -2.0, "frequency": 3, "configuration": {"0": true, "2": false, "3": true}},
+                    {"configuration": {"0": false, "1": true, "2": true, "3": false}, "energy": 1.5, "frequency": 1},
+                ],
+                "timing": {"solve_time": "10"},
+            },
+        })
+    }
+
+    fn read(name: &str, value: &Value) -> Vec<(usize, SolutionRecord)> {
+        let directory = std::env::temp_dir().join(format!("fujitsu-reader-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(name);
+        fs::write(&path, value.to_string()).unwrap();
+        let mut records: Vec<(usize, SolutionRecord)> = Vec::new();
+        let count: usize =
+            for_each_solution(&path, 4, |k, record| records.push((k, record))).unwrap();
+        assert_eq!(count, records.len());
+        records
+    }
+
+    #[test]
+    fn streams_the_solutions() {
+        let records = read("result.json", &result());
+        assert_eq!(records.len(), 2);
+        let (k, first) = &records[0];
+        assert_eq!(*k, 0);
+        assert_eq!(
+            first.configuration,
+            vec![Some(true), None, Some(false), Some(true)]
+        );
+        assert_eq!((first.energy, first.frequency), (-2.0, 3));
+        let (k, second) = &records[1];
+        assert_eq!(*k, 1);
+        assert_eq!(
+            second.configuration,
+            vec![Some(false), Some(true), Some(true), Some(false)]
+        );
+        assert_eq!((second.energy, second.frequency), (1.5, 1));
+    }
+
+    #[test]
+    fn packed_set_streams_the_same() {
+        let mut value: Value = result();
+        value["qubo_solution"]["solutions"][0]["configuration"]["1"] = json!(false);
+        let json = read("same.json", &value);
+        let directory = std::env::temp_dir().join(format!("fujitsu-reader-{}", std::process::id()));
+        let path = directory.join("same.sols");
+        SolutionSet::from_value(&value)
+            .unwrap()
+            .write(path.to_str().unwrap())
+            .unwrap();
+        let mut packed: Vec<(usize, SolutionRecord)> = Vec::new();
+        for_each_solution(&path, 4, |k, record| packed.push((k, record))).unwrap();
+        assert_eq!(packed.len(), json.len());
+        for ((k, a), (l, b)) in json.iter().zip(&packed) {
+            assert_eq!(k, l);
+            assert_eq!(a.configuration, b.configuration);
+            assert_eq!((a.energy, a.frequency), (b.energy, b.frequency));
+        }
+    }
+
+    #[test]
+    fn metadata_is_read_before_the_solutions() {
+        let directory = std::env::temp_dir().join(format!("fujitsu-reader-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("no_metadata.json");
+        fs::write(&path, result().to_string()).unwrap();
+        assert_eq!(result_metadata(&path).unwrap(), None);
+
+        let mut value: Value = result();
+        value["qubo_solution"]["solutions"][0]["configuration"]["1"] = json!(false);
+        value["metadata"] = json!({"Scaling": {"scale": 2.0}});
+        let path = directory.join("metadata.json");
+        crate::write_json(path.to_str().unwrap(), &value).unwrap();
+        assert_eq!(
+            result_metadata(&path).unwrap(),
+            Some(value["metadata"].clone())
+        );
+
+        let path = directory.join("metadata.sols");
+        SolutionSet::from_value(&value)
+            .unwrap()
+            .write(path.to_str().unwrap())
+            .unwrap();
+        assert_eq!(
+            result_metadata(&path).unwrap(),
+            Some(value["metadata"].clone())
+        );
+    }
+
+    #[test]
+    fn missing_field_is_an_error() {
+        let directory = std::env::temp_dir().join(format!("fujitsu-reader-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("no_solutions.json");
+        fs::write(&path, json!({"qubo_solution": {}}).to_string()).unwrap();
+        assert!(for_each_solution(&path, 4, |_, _| ()).is_err());
+    }
+
+    // Content error of the file `name` holding `value`, read as a problem of 4 variables
+    fn content_error(name: &str, value: &Value) -> String {
+        let directory = std::env::temp_dir().join(format!("fujitsu-reader-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(name);
+        fs::write(&path, value.to_string()).unwrap();
+        let mut count: usize = 0;
+        let e = for_each_solution(&path, 4, |_, _| count += 1).unwrap_err();
+        assert_eq!(count, 1); // The first solution was read
+        match e.downcast::<crate::error::Error>() {
+            Ok(e) => match *e {
+                e @ crate::error::Error::Content { .. } => e.to_string(),
+                e => panic!("not a content error: {}", e),
+            },
+            Err(e) => panic!("not a content error: {}", e),
+        }
+    }
+
+    #[test]
+    fn index_past_the_problem_is_refused_before_allocating() {
+        let mut value: Value = result();
+        value["qubo_solution"]["solutions"][1]["configuration"]["4000000000"] = json!(true);
+        let e: String = content_error("far_index.json", &value);
+        assert!(
+            e.contains("/qubo_solution/solutions/1/configuration"),
+            "{}",
+            e
+        );
+        assert!(e.contains("4000000000"), "{}", e);
+
+        let directory = std::env::temp_dir().join(format!("fujitsu-reader-{}", std::process::id()));
+        let path = directory.join("small.sols");
+        let mut value: Value = result();
+        value["qubo_solution"]["solutions"][0]["configuration"]["1"] = json!(false);
+        SolutionSet::from_value(&value)
+            .unwrap()
+            .write(path.to_str().unwrap())
+            .unwrap();
+        assert!(for_each_solution(&path, 3, |_, _| ()).is_err());
+        assert!(for_each_solution(&path, 4, |_, _| ()).is_ok());
+    }
+
+    #[test]
+    fn solution_without_energy_is_an_error() {
+        let mut value: Value = result();
+        value["qubo_solution"]["solutions"][1]
+            .as_object_mut()
+            .unwrap()
+            .remove("energy");
+        let e: String = content_error("no_energy.json", &value);
+        assert!(e.contains("/qubo_solution/solutions/1/energy"), "{}", e);
+    }
+
+    #[test]
+    fn missing_sites_are_not_counted() {
+        // 3 x 3 x 1 lattice all up: a missing site leaves each sublattice fully magnetised
+        let site_table: Vec<(usize, usize)> = Lattice::new(3, 1).site_table();
+        let mut configuration: Vec<Option<bool>> = vec![Some(true); 9];
+        let full = calc_c6_order_p(&configuration, &site_table, 1);
+        configuration[1] = None;
+        assert_eq!(calc_c6_order_p(&configuration, &site_table, 1), full);
+        configuration[1] = Some(false);
+        assert_ne!(calc_c6_order_p(&configuration, &site_table, 1), full);
+    }
+}
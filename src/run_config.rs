@@ -0,0 +1,240 @@
+// Purpose: Run configuration file (--config), merged under the flags and dumped resolved into the output directory
+use crate::args::GenerateArgs;
+use crate::da3_params::Da3Params;
+use crate::error::{Error, Result};
+use crate::Jxx;
+use serde_json::{json, Map, Value};
+use structopt::clap::ArgMatches;
+
+/*
+ * Run file of `generate`, TOML (*.toml) or JSON, every section and key optional:
+ *   [lattice]     length = 18, height = 6, without_cycle = false
+ *   [couplings]   J = 1.0, gamma = 0.5, layer_strength = 0.386
+ *   [disorder]    random = true, seed = 7
+ *   [constraints] file = "c.json", magnetization = 0, sub_magnetization = "3,3,-3", mode = "objective", fix = "blue=down", ...
+ *   [solver]      name = "tabu", time = 60, iterations = 20000, seed = 1, initial_state = "uud", ...
+ *   [output]      dir = "./workspace", form = "qubo", export = "qubo", debug = false
+ *   [fujitsuDA3]  time_limit_sec = 60, num_run = 8, ...
+ * A key is used only when its flag is not given, so the flags override the file.
+ */
+// (section, key, field of Options, flag)
+const CONFIG_KEYS: [(&str, &str, &str, &str); 42] = [
+    ("lattice", "length", "L", "--length"),
+    ("lattice", "height", "H", "--height"),
+    (
+        "lattice",
+        "without_cycle",
+        "without_cycle",
+        "--without-cycle",
+    ),
+    ("couplings", "J", "J", "--J"),
+    ("couplings", "gamma", "Gamma", "--gamma"),
+    (
+        "couplings",
+        "layer_strength",
+        "layer_strength",
+        "--layer-strength",
+    ),
+    ("disorder", "random", "use_random", "--use-random"),
+    ("disorder", "seed", "disorder_seed", "--disorder-seed"),
+    ("constraints", "file", "constraints_path", "--constraints"),
+    (
+        "constraints",
+        "magnetization",
+        "magnetization",
+        "--magnetization",
+    ),
+    (
+        "constraints",
+        "sub_magnetization",
+        "sub_magnetization",
+        "--sub-magnetization",
+    ),
+    (
+        "constraints",
+        "mode",
+        "magnetization_mode",
+        "--magnetization-mode",
+    ),
+    (
+        "constraints",
+        "penalty_weight",
+        "penalty_weight",
+        "--penalty-weight",
+    ),
+    (
+        "constraints",
+        "penalty_scale",
+        "penalty_scale",
+        "--penalty-scale",
+    ),
+    ("constraints", "fix", "fix", "--fix"),
+    ("constraints", "fix_mode", "fix_mode", "--fix-mode"),
+    ("solver", "name", "solver", "--solver"),
+    ("solver", "time", "T", "--time"),
+    ("solver", "sweeps", "sweeps", "--sweeps"),
+    ("solver", "gamma_start", "gamma_start", "--gamma-start"),
+    ("solver", "temp_start", "temp_start", "--temp-start"),
+    ("solver", "temp_end", "temp_end", "--temp-end"),
+    ("solver", "wolff", "wolff", "--wolff"),
+    ("solver", "worldline", "worldline", "--worldline"),
+    (
+        "solver",
+        "max_variables",
+        "max_variables",
+        "--max-variables",
+    ),
+    ("solver", "tenure", "tenure", "--tenure"),
+    ("solver", "iterations", "iterations", "--iterations"),
+    ("solver", "restarts", "restarts", "--restarts"),
+    ("solver", "steps", "steps", "--steps"),
+    ("solver", "dt", "dt", "--dt"),
+    (
+        "solver",
+        "pump_exponent",
+        "pump_exponent",
+        "--pump-exponent",
+    ),
+    ("solver", "agents", "agents", "--agents"),
+    ("solver", "cycles", "cycles", "--cycles"),
+    ("solver", "packed", "packed", "--packed"),
+    ("solver", "seed", "seed", "--seed"),
+    (
+        "solver",
+        "initial_state",
+        "initial_state",
+        "--initial-state",
+    ),
+    ("output", "dir", "out_dir", "--out-dir"),
+    ("output", "form", "form", "--form"),
+    ("output", "export", "export", "--export"),
+    ("output", "scale_bits", "scale_bits", "--scale-bits"),
+    ("output", "debug", "debug_output", "--debug-output"),
+    ("output", "da3_params", "da3_params_path", "--da3-params"),
+];
+const DA3_SECTION: &str = "fujitsuDA3";
+
+// Flags of the run file missing from the command line, as "--flag=value" (or "--flag" for true)
+pub fn config_args(config_path: &str, config: &Value, matches: &ArgMatches) -> Result<Vec<String>> {
+    let sections = config
+        .as_object()
+        .ok_or_else(|| Error::syntax(config_path, "not a table of sections"))?;
+    let mut args: Vec<String> = Vec::new();
+    for (section, table) in sections {
+        if section == DA3_SECTION {
+            continue;
+        }
+        let table = table.as_object().ok_or_else(|| {
+            Error::content(format!("/{}", section), "not a table").in_file(config_path)
+        })?;
+        for (key, value) in table {
+            let (_, _, field, flag) = CONFIG_KEYS
+                .iter()
+                .find(|entry| entry.0 == section && entry.1 == key)
+                .ok_or_else(|| {
+                    Error::content(format!("/{}/{}", section, key), "unknown key")
+                        .in_file(config_path)
+                })?;
+            // Name of the clap argument, the field name in kebab-case
+            if matches.occurrences_of(field.to_lowercase().replace('_', "-")) > 0 {
+                continue; // The flag wins
+            }
+            match value {
+                Value::Bool(true) => args.push(flag.to_string()),
+                Value::Bool(false) | Value::Null => (),
+                Value::String(text) => args.push(format!("{}={}", flag, text)),
+                Value::Number(number) => args.push(format!("{}={}", flag, number)),
+                _ => {
+                    return Err(Error::content(
+                        format!("/{}/{}", section, key),
+                        "not a string, a number or a bool",
+                    )
+                    .in_file(config_path))
+                }
+            }
+        }
+    }
+    Ok(args)
+}
+
+// The fujitsuDA3 table of the run file, if any
+pub fn config_da3(config: &Value) -> Option<Value> {
+    config.get(DA3_SECTION).cloned()
+}
+
+// Every setting of the run as a run file, the lattice and couplings as used (after the defaults of main)
+// and the fujitsuDA3 parameters as written, so `--config` of the dump repeats the run
+pub fn resolved_config(options: &GenerateArgs, jxx: &Jxx, da3_params: &Da3Params) -> Value {
+    let fields: Value = serde_json::to_value(options).unwrap();
+    let mut config: Map<String, Value> = Map::new();
+    for (section, key, field, _) in CONFIG_KEYS {
+        let value: Value = match (section, key) {
+            ("lattice", "length") => json!(jxx.l),
+            ("lattice", "height") => json!(jxx.h),
+            ("couplings", "J") => json!(jxx.j),
+            // Both, as the layer strength does not follow the default Gamma
+            ("couplings", "gamma") => json!(jxx.gamma),
+            ("couplings", "layer_strength") => json!(jxx.jl),
+            _ => fields[field].clone(),
+        };
+        if value.is_null() || field == "da3_params_path" {
+            continue; // Unset, or already resolved in fujitsuDA3
+        }
+        config
+            .entry(section)
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+            .unwrap()
+            .insert(key.to_string(), value);
+    }
+    config.insert(
+        DA3_SECTION.to_string(),
+        Value::Object(da3_params.values.clone()),
+    );
+    Value::Object(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::get_jxx;
+    use structopt::StructOpt;
+
+    // Couplings (defaults of generate) and config.json of `generate flags`
+    fn resolve<S: AsRef<str>>(flags: &[S]) -> (Jxx, Value) {
+        let args: Vec<&str> = std::iter::once("generate")
+            .chain(flags.iter().map(AsRef::as_ref))
+            .collect();
+        let options = GenerateArgs::from_iter(&args);
+        let mut jxx = Jxx {
+            j: 1.0,
+            jl: 1.0,
+            l: 3,
+            h: 3,
+            gamma: 0.2,
+        };
+        get_jxx(&mut jxx, &options).unwrap();
+        let da3_params: Da3Params = Da3Params::from_options(&options.da3).unwrap();
+        let config: Value = resolved_config(&options, &jxx, &da3_params);
+        (jxx, config)
+    }
+
+    #[test]
+    fn config_repeats_the_couplings() {
+        let runs: [&[&str]; 4] = [
+            &[],
+            &["-G", "0.5"],
+            &["-G", "0.7", "--layer-strength", "2"],
+            &["--J=-1", "-L", "6", "-H", "2"],
+        ];
+        for flags in runs {
+            let (jxx, config) = resolve(flags);
+            assert_eq!(config["couplings"]["layer_strength"], json!(jxx.jl));
+            assert_eq!(config["couplings"]["gamma"], json!(jxx.gamma));
+            let matches = GenerateArgs::clap().get_matches_from(["generate"]);
+            let replay: Vec<String> = config_args("config.json", &config, &matches).unwrap();
+            let (again, _) = resolve(&replay);
+            assert_eq!(format!("{:?}", again), format!("{:?}", jxx), "{:?}", flags);
+        }
+    }
+}
@@ -0,0 +1,271 @@
+// Purpose: Shape the output of the local solvers like a Fujitsu DA3 result
+use crate::args::WorkspaceArgs;
+use crate::error::{Error, Result};
+use crate::polynomial::Polynomial;
+use crate::solution_set::{is_solution_set, SolutionSet};
+use crate::Jxx;
+use serde_json::{json, Map, Value};
+use std::path::Path;
+use std::time::Duration;
+
+pub struct Solution {
+    pub configuration: Vec<bool>, // Value of each variable, in the same order as `variables`
+    pub energy: f64,              // Energy of the configuration
+    pub frequency: i32,           // Times the configuration was found
+}
+
+// Build the result payload read by `analysis`, merging identical configurations
+pub fn qubo_solution(
+    variables: &[i32],
+    mut solutions: Vec<Solution>,
+    progress: Vec<Value>,
+    solve_time: Duration,
+) -> Value {
+    solutions.sort_by(|a, b| {
+        a.energy
+            .total_cmp(&b.energy)
+            .then_with(|| a.configuration.cmp(&b.configuration))
+    });
+    solutions.dedup_by(|next, prev| {
+        if next.configuration == prev.configuration {
+            prev.frequency += next.frequency;
+            return true;
+        }
+        false
+    });
+
+    let solve_time: String = solve_time.as_millis().to_string();
+    let header: Value = json!({
+        "qubo_solution": {
+            "progress": progress,
+            "result_status": true,
+            "solutions": [],
+            "timing": {"solve_time": solve_time, "total_elapsed_time": solve_time},
+        },
+        "status": "Done"
+    });
+    let mut set = SolutionSet::new(variables.to_vec(), header);
+    for solution in &solutions {
+        set.push(&solution.configuration, solution.energy, solution.frequency);
+    }
+    set.to_value()
+}
+
+// Map of string index to bool, e.g. {"0": true, "1": false, ...}
+pub fn configuration_value(variables: &[i32], configuration: &[bool]) -> Value {
+    let mut map = Map::new();
+    for (index, value) in variables.iter().zip(configuration) {
+        map.insert(index.to_string(), Value::from(*value));
+    }
+    Value::Object(map)
+}
+
+// Recompute the energy of every returned solution, returns the number of mismatches
+pub fn verify_energies(polynomial: &Polynomial, result: &Value) -> Result<usize> {
+    const TOLERANCE: f64 = 1e-6; // Relative to max(1, |energy|)
+    let set: SolutionSet = SolutionSet::from_value(result)?;
+    let positions: Vec<Option<usize>> = polynomial
+        .variables
+        .iter()
+        .map(|index| set.variables.binary_search(index).ok())
+        .collect();
+    if let Some(p) = positions.iter().position(Option::is_none) {
+        if set.len() > 0 {
+            return Err(Error::content(
+                format!(
+                    "/qubo_solution/solutions/0/configuration/{}",
+                    polynomial.variables[p]
+                ),
+                "variable of the problem missing",
+            ));
+        }
+    }
+
+    let mut mismatches: usize = 0;
+    for k in 0..set.len() {
+        let x: Vec<bool> = positions
+            .iter()
+            .map(|position| set.bit(k, position.unwrap()))
+            .collect();
+        let reported: f64 = set.energies[k];
+        let computed: f64 = polynomial.energy(&x);
+        if (reported - computed).abs() > TOLERANCE * computed.abs().max(1.0) {
+            println!(
+                "solution {}: reported {} but computed {}",
+                k, reported, computed
+            );
+            mismatches += 1;
+        }
+    }
+    println!("{}/{} energies verified", set.len() - mismatches, set.len());
+    Ok(mismatches)
+}
+
+// Save the result next to the ones retrieved by `submit` and `api.sh --get`, packed (.sols) if asked,
+// with the metadata.json of its run under "metadata" (scale, fixed spins, ...) for its analysis
+// ex: ./workspace/Gamma0.2/Strength1.0_Lattice12_12_3_SQA.json
+pub fn write_result(
+    workspace: &WorkspaceArgs,
+    jxx: &Jxx,
+    solver: &str,
+    result: &Value,
+    packed: bool,
+) -> Result<String> {
+    let target_dir: String = workspace.gamma_dir(&format!("{:?}", jxx.gamma))?;
+
+    let target_file = format!(
+        "{}/Strength{:?}_Lattice{}_{}_{}_{}.{}",
+        target_dir,
+        jxx.j,
+        jxx.l,
+        jxx.l,
+        jxx.h,
+        solver,
+        extension(packed)
+    );
+    let mut result: Value = result.clone();
+    if let Some(meta) = run_metadata(workspace, (jxx.gamma, jxx.j, jxx.l, jxx.h))? {
+        result["metadata"] = meta;
+    }
+    save(&target_file, &result)?;
+    Ok(target_file)
+}
+
+// metadata.json of the workspace when it is of the run (Gamma, Strength, L, H)
+pub fn run_metadata(workspace: &WorkspaceArgs, run: (f64, f64, i32, i32)) -> Result<Option<Value>> {
+    let meta_path: String = workspace.metadata_path();
+    if !Path::new(&meta_path).exists() {
+        return Ok(None);
+    }
+    let meta: Value = crate::read_json(&meta_path)?;
+    let same_run: bool = meta["Gamma"].as_f64() == Some(run.0)
+        && meta["Strength"].as_f64() == Some(run.1)
+        && meta["Side_length"].as_i64() == Some(run.2 as i64)
+        && meta["Height"].as_i64() == Some(run.3 as i64);
+    Ok(Some(meta).filter(|_| same_run))
+}
+
+// Save the result of a request file next to it, packed (.sols) if asked
+// ex: ./problems/G1.json -> ./problems/G1_TABU.json
+pub fn write_result_beside(
+    input_path: &str,
+    solver: &str,
+    result: &Value,
+    packed: bool,
+) -> Result<String> {
+    let path = Path::new(input_path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let target_file = path
+        .with_file_name(format!("{}_{}.{}", stem, solver, extension(packed)))
+        .to_string_lossy()
+        .to_string();
+    save(&target_file, result)?;
+    Ok(target_file)
+}
+
+fn extension(packed: bool) -> &'static str {
+    match packed {
+        true => "sols",
+        false => "json",
+    }
+}
+
+fn save(target_file: &str, result: &Value) -> Result<()> {
+    println!("Saving to {}...", target_file);
+    match is_solution_set(target_file) {
+        true => SolutionSet::from_value(result)?.write(target_file),
+        false => crate::write_json(target_file, result),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polynomial::tests::random_polynomial;
+
+    fn solutions(polynomial: &Polynomial) -> Vec<Solution> {
+        (0..4)
+            .map(|k| {
+                let configuration: Vec<bool> =
+                    (0..polynomial.len()).map(|i| (i + k) % 3 == 0).collect();
+                Solution {
+                    energy: polynomial.energy(&configuration),
+                    configuration,
+                    frequency: 1,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn verify_counts_the_mismatches() {
+        let polynomial: Polynomial = random_polynomial(6, 3);
+        let result: Value = qubo_solution(
+            &polynomial.variables,
+            solutions(&polynomial),
+            Vec::new(),
+            Duration::ZERO,
+        );
+        assert_eq!(verify_energies(&polynomial, &result).unwrap(), 0);
+
+        let mut wrong: Vec<Solution> = solutions(&polynomial);
+        wrong[1].energy += 1.0;
+        wrong[3].energy -= 1e-3;
+        let result: Value = qubo_solution(&polynomial.variables, wrong, Vec::new(), Duration::ZERO);
+        assert_eq!(verify_energies(&polynomial, &result).unwrap(), 2);
+    }
+
+    #[test]
+    fn identical_configurations_are_merged() {
+        let polynomial: Polynomial = random_polynomial(6, 3);
+        let mut twice: Vec<Solution> = solutions(&polynomial);
+        twice.extend(solutions(&polynomial));
+        let result: Value = qubo_solution(&polynomial.variables, twice, Vec::new(), Duration::ZERO);
+        let list = result["qubo_solution"]["solutions"].as_array().unwrap();
+        assert_eq!(list.len(), 3); // k = 0 and 3 are the same configuration
+        assert!(list.iter().all(|s| s["frequency"].as_i64().unwrap() >= 2));
+        let energies: Vec<f64> = list.iter().map(|s| s["energy"].as_f64().unwrap()).collect();
+        assert!(energies.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn result_keeps_the_metadata_of_its_run() {
+        let directory =
+            std::env::temp_dir().join(format!("fujitsu-solution-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let workspace = WorkspaceArgs {
+            out_dir: directory.to_str().unwrap().to_string(),
+        };
+        std::fs::create_dir_all(&directory).unwrap();
+        let meta: Value = json!({
+            "Gamma": 0.5,
+            "Strength": 1.0,
+            "Side_length": 3,
+            "Height": 1,
+            "Scaling": {"scale": 2.0},
+        });
+        crate::write_json(&workspace.metadata_path(), &meta).unwrap();
+        let polynomial: Polynomial = random_polynomial(6, 3);
+        let result: Value = qubo_solution(
+            &polynomial.variables,
+            solutions(&polynomial),
+            Vec::new(),
+            Duration::ZERO,
+        );
+        let mut jxx = Jxx {
+            j: 1.0,
+            jl: -1.0,
+            l: 3,
+            h: 1,
+            gamma: 0.5,
+        };
+        let path: String = write_result(&workspace, &jxx, "TABU", &result, false).unwrap();
+        assert_eq!(crate::read_json(&path).unwrap()["metadata"], meta);
+
+        // metadata.json is of another run
+        jxx.gamma = 0.2;
+        let path: String = write_result(&workspace, &jxx, "TABU", &result, false).unwrap();
+        assert!(crate::read_json(&path).unwrap().get("metadata").is_none());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
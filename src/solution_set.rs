@@ -0,0 +1,551 @@
+// Purpose: Packed storage of a solution set, converted losslessly to and from the DA result JSON
+use crate::error;
+use crate::solution::configuration_value;
+use serde_json::{json, Map, Value};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+/*
+ * Binary file (.sols), little endian:
+ *   magic "FJSS", version u32
+ *   variables u32, solutions u64, header length u64
+ *   header: the result JSON without the solutions (status, timing, progress, ...)
+ *   variables: i32 x variables
+ *   energies: f64 x solutions, frequencies: i32 x solutions
+ *   configurations: u64 x ceil(variables / 64) for each solution, bit i = variable i
+ * Each configuration has the same size, so solution k is read without decoding the others:
+ * SolutionSetFile keeps the configurations on disk and seeks to the one asked for (the energies
+ * and frequencies, 12 bytes a solution, are loaded), SolutionSet holds the whole set in memory.
+ */
+const MAGIC: &[u8; 4] = b"FJSS";
+const VERSION: u32 = 1;
+const FIXED_LEN: u64 = 28; // Bytes before the header
+const SOLUTION_KEYS: [&str; 3] = ["configuration", "energy", "frequency"];
+
+pub struct SolutionSet {
+    pub variables: Vec<i32>,   // Sorted variable indices, bit position -> index
+    pub energies: Vec<f64>,    // Energy of each solution
+    pub frequencies: Vec<i32>, // Frequency of each solution
+    words: usize,              // u64 words of each configuration
+    bits: Vec<u64>,            // Packed configurations, solution after solution
+    header: Value,             // Everything else of the result, kept for the JSON round trip
+}
+
+impl SolutionSet {
+    pub fn new(variables: Vec<i32>, header: Value) -> SolutionSet {
+        let words: usize = variables.len().div_ceil(64);
+        SolutionSet {
+            variables,
+            energies: Vec::new(),
+            frequencies: Vec::new(),
+            words,
+            bits: Vec::new(),
+            header,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.energies.len()
+    }
+
+    pub fn push(&mut self, configuration: &[bool], energy: f64, frequency: i32) {
+        if configuration.len() != self.variables.len() {
+            panic!(
+                "Configuration of {} variables in a set of {} variables.",
+                configuration.len(),
+                self.variables.len()
+            );
+        }
+        let start: usize = self.bits.len();
+        self.bits.resize(start + self.words, 0);
+        for (i, value) in configuration.iter().enumerate() {
+            if *value {
+                self.bits[start + i / 64] |= 1 << (i % 64);
+            }
+        }
+        self.energies.push(energy);
+        self.frequencies.push(frequency);
+    }
+
+    // Value of variable position `i` in solution `k`
+    pub fn bit(&self, k: usize, i: usize) -> bool {
+        (self.bits[k * self.words + i / 64] >> (i % 64)) & 1 == 1
+    }
+
+    // Configuration of solution `k`, in the same order as `variables`
+    pub fn configuration(&self, k: usize) -> Vec<bool> {
+        (0..self.variables.len()).map(|i| self.bit(k, i)).collect()
+    }
+
+    // Configuration of solution `k` indexed by the variable index (missing = None), like the streaming reader
+    pub fn configuration_by_index(&self, k: usize) -> Vec<Option<bool>> {
+        by_index(&self.variables, &self.configuration(k))
+    }
+
+    // `{"0": true, ...}` of solution `k`, as in the DA result
+    pub fn configuration_value(&self, k: usize) -> Value {
+        configuration_value(&self.variables, &self.configuration(k))
+    }
+
+    // Errors carry the JSON pointer of the invalid value, the caller names the file
+    pub fn from_value(result: &Value) -> error::Result<SolutionSet> {
+        const SOLUTIONS: &str = "/qubo_solution/solutions";
+        let solutions = result["qubo_solution"]["solutions"]
+            .as_array()
+            .ok_or_else(|| error::Error::content(SOLUTIONS, "not an array"))?;
+        let configuration = |k: usize| -> error::Result<&Map<String, Value>> {
+            solutions[k]["configuration"].as_object().ok_or_else(|| {
+                error::Error::content(format!("{}/{}/configuration", SOLUTIONS, k), "not a map")
+            })
+        };
+
+        let variables: Vec<i32> = match solutions.first() {
+            Some(_) => {
+                let mut variables: Vec<i32> = configuration(0)?
+                    .keys()
+                    .map(|key| match key.parse::<i32>() {
+                        Ok(index) if index >= 0 => Ok(index),
+                        _ => Err(error::Error::content(
+                            format!("{}/0/configuration/{}", SOLUTIONS, key),
+                            "key is not a variable index",
+                        )),
+                    })
+                    .collect::<error::Result<Vec<i32>>>()?;
+                variables.sort();
+                variables
+            }
+            None => Vec::new(),
+        };
+
+        // Energies stay integers in the JSON when they all were
+        let integer_energy: bool = solutions.iter().all(|s| s["energy"].is_i64());
+        // Keys other than configuration, energy and frequency, kept per solution if any
+        let extras: Vec<Value> = solutions
+            .iter()
+            .map(|solution| {
+                let mut extra: Map<String, Value> =
+                    solution.as_object().cloned().unwrap_or_default();
+                for key in SOLUTION_KEYS {
+                    extra.remove(key);
+                }
+                Value::Object(extra)
+            })
+            .collect();
+
+        let mut header: Value = result.clone();
+        header["qubo_solution"]["solutions"] = json!([]);
+        if extras
+            .iter()
+            .any(|extra| !extra.as_object().unwrap().is_empty())
+        {
+            header["qubo_solution"]["solutions"] = Value::Array(extras);
+        }
+        if integer_energy && !solutions.is_empty() {
+            header["integer_energy"] = json!(true);
+        }
+
+        let mut set = SolutionSet::new(variables, header);
+        for (k, solution) in solutions.iter().enumerate() {
+            let pointer: String = format!("{}/{}", SOLUTIONS, k);
+            let configuration = configuration(k)?;
+            if configuration.len() != set.variables.len() {
+                return Err(error::Error::content(
+                    format!("{}/configuration", pointer),
+                    "not the variables of solution 0",
+                ));
+            }
+            let x: Vec<bool> = set
+                .variables
+                .iter()
+                .map(
+                    |index| match configuration.get(&index.to_string()).map(Value::as_bool) {
+                        Some(Some(value)) => Ok(value),
+                        _ => Err(error::Error::content(
+                            format!("{}/configuration/{}", pointer, index),
+                            "missing or not a bool",
+                        )),
+                    },
+                )
+                .collect::<error::Result<Vec<bool>>>()?;
+            let energy: f64 = solution["energy"].as_f64().ok_or_else(|| {
+                error::Error::content(format!("{}/energy", pointer), "not a number")
+            })?;
+            set.push(
+                &x,
+                energy,
+                solution["frequency"].as_i64().unwrap_or(1) as i32,
+            );
+        }
+        Ok(set)
+    }
+
+    pub fn to_value(&self) -> Value {
+        let mut result: Value = self.header.clone();
+        let integer_energy: bool = result
+            .as_object_mut()
+            .and_then(|map| map.remove("integer_energy"))
+            .is_some();
+        let extras: Vec<Value> = result["qubo_solution"]["solutions"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+
+        let solution_list: Vec<Value> = (0..self.len())
+            .map(|k| {
+                let mut solution: Map<String, Value> = match extras.get(k) {
+                    Some(Value::Object(extra)) => extra.clone(),
+                    _ => Map::new(),
+                };
+                solution.insert("configuration".to_string(), self.configuration_value(k));
+                let energy: Value = if integer_energy {
+                    json!(self.energies[k] as i64)
+                } else {
+                    json!(self.energies[k])
+                };
+                solution.insert("energy".to_string(), energy);
+                solution.insert("frequency".to_string(), json!(self.frequencies[k]));
+                Value::Object(solution)
+            })
+            .collect();
+        result["qubo_solution"]["solutions"] = Value::Array(solution_list);
+        result
+    }
+
+    pub fn write(&self, path: &str) -> error::Result<()> {
+        let io_error = |e: std::io::Error| error::Error::io(path, e);
+        let mut file = BufWriter::new(File::create(path).map_err(io_error)?);
+        let header: Vec<u8> = self.header.to_string().into_bytes();
+        let mut bytes: Vec<u8> = Vec::with_capacity(FIXED_LEN as usize + header.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.variables.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&(header.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&header);
+        for index in &self.variables {
+            bytes.extend_from_slice(&index.to_le_bytes());
+        }
+        for energy in &self.energies {
+            bytes.extend_from_slice(&energy.to_le_bytes());
+        }
+        for frequency in &self.frequencies {
+            bytes.extend_from_slice(&frequency.to_le_bytes());
+        }
+        file.write_all(&bytes).map_err(io_error)?;
+        for word in &self.bits {
+            file.write_all(&word.to_le_bytes()).map_err(io_error)?;
+        }
+        file.flush().map_err(io_error)
+    }
+
+    // Whole set in memory, see SolutionSetFile to read the solutions one at a time
+    pub fn read(path: &str) -> error::Result<SolutionSet> {
+        let mut file: SolutionSetFile = SolutionSetFile::open(path)?;
+        let mut set = SolutionSet::new(file.variables.clone(), file.header.clone());
+        set.bits = file.read_words(0, file.len())?;
+        set.energies = std::mem::take(&mut file.energies);
+        set.frequencies = std::mem::take(&mut file.frequencies);
+        Ok(set)
+    }
+}
+
+// Configuration in the order of `variables` indexed by the variable index (missing = None)
+fn by_index(variables: &[i32], configuration: &[bool]) -> Vec<Option<bool>> {
+    let size: usize = variables.last().map_or(0, |v| *v as usize + 1);
+    let mut bits: Vec<Option<bool>> = vec![None; size];
+    for (index, value) in variables.iter().zip(configuration) {
+        bits[*index as usize] = Some(*value);
+    }
+    bits
+}
+
+// Packed file opened for random access, the configurations stay on disk
+pub struct SolutionSetFile {
+    path: String,
+    file: BufReader<File>,
+    pub variables: Vec<i32>, // Sorted variable indices, bit position -> index
+    pub energies: Vec<f64>,  // Energy of each solution
+    pub frequencies: Vec<i32>, // Frequency of each solution
+    words: usize,            // u64 words of each configuration
+    header: Value,           // Everything else of the result
+    start: u64,              // Offset of the first configuration
+    next: u64,               // Offset the reader is at
+}
+
+impl SolutionSetFile {
+    // Read everything but the configurations, the sizes are checked against the file length
+    pub fn open(path: &str) -> error::Result<SolutionSetFile> {
+        let file: File = File::open(path).map_err(|e| error::Error::io(path, e))?;
+        let file_len: u64 = file
+            .metadata()
+            .map_err(|e| error::Error::io(path, e))?
+            .len();
+        let mut file = BufReader::new(file);
+        let mut read_bytes = |n: usize| -> error::Result<Vec<u8>> {
+            let mut buffer: Vec<u8> = vec![0; n];
+            file.read_exact(&mut buffer)
+                .map_err(|e| error::Error::io(path, e))?;
+            Ok(buffer)
+        };
+
+        if file_len < FIXED_LEN || read_bytes(4)? != MAGIC {
+            return Err(error::Error::syntax(path, "not a solution set file"));
+        }
+        let version: u32 = u32::from_le_bytes(read_bytes(4)?.try_into().unwrap());
+        if version != VERSION {
+            return Err(error::Error::syntax(
+                path,
+                format!("unsupported solution set version {}", version),
+            ));
+        }
+        let n: u64 = u32::from_le_bytes(read_bytes(4)?.try_into().unwrap()) as u64;
+        let m: u64 = u64::from_le_bytes(read_bytes(8)?.try_into().unwrap());
+        let header_len: u64 = u64::from_le_bytes(read_bytes(8)?.try_into().unwrap());
+        if header_len > file_len - FIXED_LEN {
+            return Err(error::Error::content(
+                "header length",
+                format!(
+                    "{} bytes, past the end of the file of {} bytes",
+                    header_len, file_len
+                ),
+            )
+            .in_file(path));
+        }
+        let words: u64 = n.div_ceil(64);
+        // In u128, a corrupt count cannot overflow
+        let expected: u128 =
+            (FIXED_LEN + header_len + 4 * n) as u128 + m as u128 * (12 + 8 * words as u128);
+        if expected != file_len as u128 {
+            return Err(error::Error::content(
+                "size",
+                format!(
+                    "{} variables and {} solutions take {} bytes, the file has {}",
+                    n, m, expected, file_len
+                ),
+            )
+            .in_file(path));
+        }
+
+        let header: Value = serde_json::from_slice(&read_bytes(header_len as usize)?)
+            .map_err(|e| error::Error::syntax(path, e))?;
+        let variables: Vec<i32> = read_bytes(4 * n as usize)?
+            .chunks_exact(4)
+            .map(|b| i32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        if let Some(p) = (0..variables.len())
+            .find(|p| variables[*p] < 0 || (*p > 0 && variables[*p] <= variables[*p - 1]))
+        {
+            return Err(error::Error::content(
+                format!("variables/{}", p),
+                format!("{} is not a sorted variable index", variables[p]),
+            )
+            .in_file(path));
+        }
+        let energies: Vec<f64> = read_bytes(8 * m as usize)?
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        let frequencies: Vec<i32> = read_bytes(4 * m as usize)?
+            .chunks_exact(4)
+            .map(|b| i32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        let start: u64 = file_len - 8 * m * words;
+        Ok(SolutionSetFile {
+            path: path.to_string(),
+            file,
+            variables,
+            energies,
+            frequencies,
+            words: words as usize,
+            header,
+            start,
+            next: start,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.energies.len()
+    }
+
+    // Packed words of the solutions `from..to`, seeking only when not read in order
+    fn read_words(&mut self, from: usize, to: usize) -> error::Result<Vec<u64>> {
+        let offset: u64 = self.start + (8 * self.words * from) as u64;
+        let path: &str = &self.path;
+        if offset != self.next {
+            self.file
+                .seek(SeekFrom::Start(offset))
+                .map_err(|e| error::Error::io(path, e))?;
+        }
+        let mut buffer: Vec<u8> = vec![0; 8 * self.words * (to - from)];
+        self.file
+            .read_exact(&mut buffer)
+            .map_err(|e| error::Error::io(path, e))?;
+        self.next = offset + buffer.len() as u64;
+        Ok(buffer
+            .chunks_exact(8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+            .collect())
+    }
+
+    // Configuration of solution `k`, in the same order as `variables`
+    pub fn configuration(&mut self, k: usize) -> error::Result<Vec<bool>> {
+        let words: Vec<u64> = self.read_words(k, k + 1)?;
+        Ok((0..self.variables.len())
+            .map(|i| (words[i / 64] >> (i % 64)) & 1 == 1)
+            .collect())
+    }
+
+    // Configuration of solution `k` indexed by the variable index (missing = None)
+    pub fn configuration_by_index(&mut self, k: usize) -> error::Result<Vec<Option<bool>>> {
+        let configuration: Vec<bool> = self.configuration(k)?;
+        Ok(by_index(&self.variables, &configuration))
+    }
+
+    // The result without its solutions
+    pub fn header(&self) -> &Value {
+        &self.header
+    }
+}
+
+pub fn is_solution_set(path: &str) -> bool {
+    path.ends_with(".sols")
+}
+
+// Read a result file, packed (.sols) or DA JSON
+pub fn read_solution_set(path: &str) -> error::Result<SolutionSet> {
+    if is_solution_set(path) {
+        SolutionSet::read(path)
+    } else {
+        SolutionSet::from_value(&crate::read_json(path)?).map_err(|e| e.in_file(path))
+    }
+}
+
+// Convert a result between the DA JSON and the packed form, next to it
+// ex: ./workspace/Gamma0.2/Strength1.0_Lattice12_12_3_DA.json <-> ./workspace/Gamma0.2/Strength1.0_Lattice12_12_3_DA.sols
+pub fn convert(path: &str) -> error::Result<String> {
+    let (target_file, extension): (String, &str) = if is_solution_set(path) {
+        (path.trim_end_matches(".sols").to_string() + ".json", "json")
+    } else {
+        (path.trim_end_matches(".json").to_string() + ".sols", "sols")
+    };
+    println!("Converting {} to {}...", path, target_file);
+    let set: SolutionSet = read_solution_set(path)?;
+    match extension {
+        "json" => crate::write_json(&target_file, &set.to_value())?,
+        _ => set.write(&target_file)?,
+    }
+    Ok(target_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // 70 variables (two words), the indices 0, 2, 4, ...
+    fn result() -> Value {
+        let solutions: Vec<Value> = (0..3)
+            .map(|k| {
+                let configuration: Map<String, Value> = (0..70)
+                    .map(|i| ((2 * i).to_string(), Value::from((i + k) % 3 == 0)))
+                    .collect();
+                json!({"configuration": configuration, "energy": -10 + k, "frequency": k + 1})
+            })
+            .collect();
+        json!({
+            "qubo_solution": {
+                "progress": [{"energy": -10, "penalty_energy": 0}],
+                "result_status": true,
+                "solutions": solutions,
+                "timing": {"solve_time": "7", "total_elapsed_time": "9"},
+            },
+            "status": "Done",
+        })
+    }
+
+    fn temp_path(name: &str) -> String {
+        let directory = std::env::temp_dir().join(format!("fujitsu-sols-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        directory.join(name).to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let value: Value = result();
+        assert_eq!(SolutionSet::from_value(&value).unwrap().to_value(), value);
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let value: Value = result();
+        let path: String = temp_path("round_trip.sols");
+        SolutionSet::from_value(&value)
+            .unwrap()
+            .write(&path)
+            .unwrap();
+        assert_eq!(SolutionSet::read(&path).unwrap().to_value(), value);
+    }
+
+    #[test]
+    fn reads_one_solution_at_a_time() {
+        let set: SolutionSet = SolutionSet::from_value(&result()).unwrap();
+        let path: String = temp_path("random_access.sols");
+        set.write(&path).unwrap();
+        let mut file: SolutionSetFile = SolutionSetFile::open(&path).unwrap();
+        assert_eq!(file.len(), 3);
+        for k in [2, 0, 1, 1] {
+            assert_eq!(file.configuration(k).unwrap(), set.configuration(k));
+            assert_eq!(
+                file.configuration_by_index(k).unwrap(),
+                set.configuration_by_index(k)
+            );
+        }
+        assert_eq!(set.configuration_by_index(0)[1], None);
+    }
+
+    fn content_error(path: &str) -> bool {
+        matches!(
+            SolutionSetFile::open(path),
+            Err(error::Error::Content { .. })
+        )
+    }
+
+    #[test]
+    fn corrupt_sizes_are_content_errors() {
+        let path: String = temp_path("corrupt.sols");
+        SolutionSet::from_value(&result())
+            .unwrap()
+            .write(&path)
+            .unwrap();
+        let bytes: Vec<u8> = fs::read(&path).unwrap();
+
+        let mut header_len: Vec<u8> = bytes.clone();
+        header_len[20..28].copy_from_slice(&u64::MAX.to_le_bytes());
+        fs::write(&path, &header_len).unwrap();
+        assert!(content_error(&path));
+
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(content_error(&path));
+
+        let mut solutions: Vec<u8> = bytes.clone();
+        solutions[12..20].copy_from_slice(&(1u64 << 62).to_le_bytes());
+        fs::write(&path, &solutions).unwrap();
+        assert!(content_error(&path));
+    }
+
+    #[test]
+    fn negative_indices_are_rejected() {
+        let mut value: Value = result();
+        value["qubo_solution"]["solutions"][0]["configuration"]["-1"] = json!(true);
+        assert!(matches!(
+            SolutionSet::from_value(&value),
+            Err(error::Error::Content { .. })
+        ));
+
+        let path: String = temp_path("negative.sols");
+        let mut set: SolutionSet = SolutionSet::new(vec![-3, 1], json!({}));
+        set.push(&[true, false], -1.0, 1);
+        set.write(&path).unwrap();
+        assert!(content_error(&path));
+    }
+}
@@ -0,0 +1,296 @@
+// Purpose: Simulated quantum annealing (path-integral Monte Carlo) on the layered lattice
+use crate::cluster::{
+    autocorrelation_time, wolff_update, worldline_update, ClusterBuffer, MoveStats,
+};
+use crate::error::{Error, Result};
+use crate::lattice::Lattice;
+use crate::random::get_rng;
+use crate::solution::{qubo_solution, Solution};
+use rand::prelude::*;
+use serde_json::{json, Value};
+use std::time::Instant;
+
+#[derive(Debug)]
+pub struct SqaParams {
+    pub sweeps: i32,             // Number of Monte Carlo sweeps
+    pub gamma: (f64, f64),       // Gamma at the first and the last sweep
+    pub temperature: (f64, f64), // Temperature at the first and the last sweep
+    pub wolff: i32,              // Wolff cluster updates per sweep
+    pub worldline: i32,          // Imaginary-time line updates per sweep
+    pub seed: Option<u64>,       // Seed of the random number generator
+    pub initial: bool, // Start from the spins of the lattice (--initial-state) instead of random ones
+}
+
+impl SqaParams {
+    // Check the schedule before annealing `lattice`
+    fn check(&self, lattice: &Lattice) -> Result<()> {
+        if self.sweeps <= 0 {
+            return Err(Error::parameter("--sweeps", "must be greater than 0"));
+        }
+        if self.temperature.0 <= 0.0 || self.temperature.1 <= 0.0 {
+            return Err(Error::parameter(
+                "--temp-start / --temp-end",
+                "must be greater than 0",
+            ));
+        }
+        if !self.anneals_gamma(lattice) {
+            return Ok(());
+        }
+        if self.gamma.0 <= 0.0 || self.gamma.1 <= 0.0 {
+            return Err(Error::parameter("--gamma-start", "must be greater than 0"));
+        }
+        // The schedule ends at the K' of the lattice, the one of the reported energy
+        let (k_end, k_lattice): (f64, f64) =
+            (layer_strength(self.gamma.1), lattice.nodes[0].j_layer_up);
+        if (k_end - k_lattice).abs() > 1e-12 * k_lattice.abs().max(1.0) {
+            return Err(Error::parameter(
+                "--gamma-start",
+                format!(
+                    "the schedule ends at K' = {} but the lattice has K' = {} (--layer-strength)",
+                    k_end, k_lattice
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn anneals_gamma(&self, lattice: &Lattice) -> bool {
+        self.gamma.0 != self.gamma.1 && lattice.h > 1
+    }
+}
+
+// K' = -0.5 * ln(tanh(Gamma)), the strength between Trotter layers
+pub fn layer_strength(gamma: f64) -> f64 {
+    -(0.5) * gamma.tanh().ln()
+}
+
+// Linear schedule from `range.0` at sweep 0 to `range.1` at the last sweep
+fn schedule(range: (f64, f64), sweep: i32, sweeps: i32) -> f64 {
+    if sweeps <= 1 {
+        return range.1;
+    }
+    range.0 + (range.1 - range.0) * (sweep as f64 / (sweeps - 1) as f64)
+}
+
+/*
+ * Anneal the spins of the lattice (in place) and return the result in the DA3 format.
+ * Delta E of flipping s_{i, n} = -2 s_{i, n} ( \sum{K s_{j, n}} - K' (s_{i, n+1} + s_{i, n-1}) )
+ * When gamma.0 == gamma.1 the strength between layers is the one of the lattice (j_layer_up),
+ * otherwise K' follows the Gamma schedule, which ends at the K' of the lattice.
+ */
+pub fn simulated_quantum_annealing(lattice: &mut Lattice, params: &SqaParams) -> Result<Value> {
+    params.check(lattice)?;
+    let anneal_gamma: bool = params.anneals_gamma(lattice);
+
+    let mut rng = get_rng(params.seed);
+    let start = Instant::now();
+
+    // Initial configuration, random unless it is the one of the lattice
+    let mut spins: Vec<f64> = lattice
+        .nodes
+        .iter()
+        .map(|node| match params.initial {
+            true => node.spin,
+            false => rng.gen::<bool>(),
+        })
+        .map(|spin| if spin { 1.0 } else { -1.0 })
+        .collect();
+
+    let mut progress: Vec<Value> = Vec::new();
+    let mut energies: Vec<f64> = Vec::with_capacity(params.sweeps as usize); // Energy after each sweep
+    let (mut single_flip, mut wolff, mut worldline) = (
+        MoveStats::default(),
+        MoveStats::default(),
+        MoveStats::default(),
+    );
+    let mut buffer = ClusterBuffer::new(spins.len());
+    let report_every: i32 = (params.sweeps / 10).max(1);
+
+    for sweep in 0..params.sweeps {
+        let temperature: f64 = schedule(params.temperature, sweep, params.sweeps);
+        let k_layer: f64 = if anneal_gamma {
+            layer_strength(schedule(params.gamma, sweep, params.sweeps))
+        } else {
+            lattice.nodes[0].j_layer_up
+        };
+
+        for index in 0..lattice.nodes.len() as i32 {
+            let field: f64 = lattice.in_plane_field(&spins, index)
+                - k_layer * lattice.layer_field(&spins, index);
+            let delta_e: f64 = -2.0 * spins[index as usize] * field;
+
+            // Metropolis acceptance: min(1, exp(-Delta E / T))
+            single_flip.attempted += 1;
+            single_flip.cluster_size += 1;
+            if delta_e <= 0.0 || rng.gen::<f64>() < (-delta_e / temperature).exp() {
+                spins[index as usize] = -spins[index as usize];
+                single_flip.accepted += 1;
+            }
+        }
+
+        for _ in 0..params.wolff {
+            wolff_update(
+                lattice,
+                &mut spins,
+                k_layer,
+                temperature,
+                &mut rng,
+                &mut buffer,
+                &mut wolff,
+            );
+        }
+        if lattice.h > 1 {
+            for _ in 0..params.worldline {
+                worldline_update(
+                    lattice,
+                    &mut spins,
+                    k_layer,
+                    temperature,
+                    &mut rng,
+                    &mut buffer,
+                    &mut worldline,
+                );
+            }
+        }
+
+        energies.push(lattice.hamiltonian_energy(&spins));
+        if (sweep + 1) % report_every == 0 {
+            progress.push(json!({
+                "energy": energies[sweep as usize],
+                "time": start.elapsed().as_secs_f64(),
+            }));
+        }
+    }
+
+    // Statistics of the moves and the integrated autocorrelation time of the energy (in sweeps)
+    let tau: f64 = autocorrelation_time(&energies);
+    println!("single flip: {}", single_flip);
+    println!("wolff: {}", wolff);
+    println!("worldline: {}", worldline);
+    println!("energy autocorrelation time: {} sweeps", tau);
+
+    lattice.set_spins(&spins);
+
+    // Energy of every Trotter slice (in-plane part only)
+    let slice_energies: Vec<f64> = (0..lattice.h)
+        .map(|layer| lattice.layer_energy(&spins, layer))
+        .collect();
+    for (layer, energy) in slice_energies.iter().enumerate() {
+        println!("layer: {}, energy: {}", layer, energy);
+    }
+
+    let energy: f64 = lattice.hamiltonian_energy(&spins);
+    println!("energy: {}", energy);
+
+    let variables: Vec<i32> = (0..lattice.nodes.len() as i32).collect();
+    let solution = Solution {
+        configuration: spins.iter().map(|s| *s > 0.0).collect(),
+        energy,
+        frequency: 1,
+    };
+    let mut result: Value = qubo_solution(&variables, vec![solution], progress, start.elapsed());
+    result["qubo_solution"]["solutions"][0]["slice_energies"] = json!(slice_energies);
+    result["statistics"] = json!({
+        "single_flip": single_flip.to_value(),
+        "wolff": wolff.to_value(),
+        "worldline": worldline.to_value(),
+        "energy_autocorrelation_time": tau,
+    });
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::initial_state::set_state;
+    use crate::{create_vector, Jxx};
+
+    fn lattice(l: i32, h: i32, gamma: f64) -> Lattice {
+        create_vector(&Jxx {
+            j: 1.0,
+            jl: if gamma == 0.0 {
+                0.0
+            } else {
+                layer_strength(gamma)
+            },
+            l,
+            h,
+            gamma,
+        })
+    }
+
+    fn params(sweeps: i32, temperature: (f64, f64), initial: bool) -> SqaParams {
+        SqaParams {
+            sweeps,
+            gamma: (0.5, 0.5),
+            temperature,
+            wolff: 1,
+            worldline: 1,
+            seed: Some(1),
+            initial,
+        }
+    }
+
+    #[test]
+    fn schedule_ends_at_both_values() {
+        assert_eq!(schedule((3.0, 0.5), 0, 11), 3.0);
+        assert_eq!(schedule((3.0, 0.5), 10, 11), 0.5);
+        assert_eq!(schedule((3.0, 0.5), 0, 1), 0.5);
+    }
+
+    #[test]
+    fn anneals_a_single_layer_to_the_ground_state() {
+        // Triangular antiferromagnet: one frustrated bond per triangle, E = -N
+        let mut lattice: Lattice = lattice(6, 1, 0.0);
+        let result: Value =
+            simulated_quantum_annealing(&mut lattice, &params(500, (2.0, 0.05), false)).unwrap();
+        let solution: &Value = &result["qubo_solution"]["solutions"][0];
+        assert_eq!(solution["energy"], json!(-36.0));
+        let spins: Vec<f64> = lattice
+            .nodes
+            .iter()
+            .map(|node| if node.spin { 1.0 } else { -1.0 })
+            .collect();
+        assert_eq!(lattice.hamiltonian_energy(&spins), -36.0);
+    }
+
+    #[test]
+    fn keeps_a_ground_state_at_low_temperature() {
+        let mut lattice: Lattice = lattice(6, 3, 0.5);
+        set_state(&mut lattice, "uud", None);
+        let result: Value =
+            simulated_quantum_annealing(&mut lattice, &params(50, (1e-3, 1e-3), true)).unwrap();
+        let slice_energies = &result["qubo_solution"]["solutions"][0]["slice_energies"];
+        assert_eq!(slice_energies, &json!([-36.0, -36.0, -36.0]));
+    }
+
+    #[test]
+    fn check_rejects_bad_schedules() {
+        let (single, layered): (Lattice, Lattice) = (lattice(3, 1, 0.5), lattice(3, 2, 0.5));
+        assert!(params(0, (1.0, 1.0), false).check(&single).is_err());
+        assert!(params(10, (0.0, 1.0), false).check(&single).is_err());
+        let mut gamma_to_zero: SqaParams = params(10, (1.0, 1.0), false);
+        gamma_to_zero.gamma = (1.0, 0.0);
+        assert!(gamma_to_zero.check(&layered).is_err());
+        assert!(gamma_to_zero.check(&single).is_ok()); // No Gamma schedule on a single layer
+    }
+
+    #[test]
+    fn schedule_ends_at_the_layer_strength_of_the_lattice() {
+        let mut annealed: SqaParams = params(10, (1.0, 1.0), false);
+        annealed.gamma = (3.0, 0.5);
+        let mut layered: Lattice = lattice(3, 2, 0.5);
+        assert!(annealed.check(&layered).is_ok());
+
+        // --layer-strength replaced the K' of Gamma 0.5
+        for node in layered.nodes.iter_mut() {
+            node.j_layer_up = 2.0;
+        }
+        assert!(matches!(
+            simulated_quantum_annealing(&mut layered, &annealed),
+            Err(Error::Parameter { .. })
+        ));
+        annealed.gamma = (0.5, 0.5); // Fixed K', the one of the lattice
+        assert!(simulated_quantum_annealing(&mut layered, &annealed).is_ok());
+    }
+}
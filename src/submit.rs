@@ -0,0 +1,196 @@
+// Purpose: Post a request to the Fujitsu DA3 async API and save the result of the job, like api.sh
+use crate::args::{SubmitArgs, WorkspaceArgs};
+use crate::error::{Error, Result};
+use crate::fixing::Fixing;
+use crate::solution::{write_result, write_result_beside};
+use crate::Jxx;
+use serde_json::Value;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/*
+ * POST <base>/da/v3/async/qubo/solve        -> {"job_id": "..."}
+ * GET  <base>/da/v3/async/jobs/result/<id>  -> {"status": "Waiting" | "Running"} or the result
+ * The result of the request of the workspace is saved like `api.sh --get`, named from its metadata.json
+ * (with the spins substituted out of the problem put back):
+ *   <workspace>/Gamma<G>/Strength<J>_Lattice<L>_<L>_<H>_Time<T>.json
+ * and the result of any other request next to it (<request>_<job_id>.json).
+ */
+const SOLVE_PATH: &str = "/da/v3/async/qubo/solve";
+const RESULT_PATH: &str = "/da/v3/async/jobs/result/";
+const PENDING: [&str; 2] = ["Waiting", "Running"];
+
+fn call(request: ureq::Request, url: &str, body: Option<&Value>) -> Result<Value> {
+    let response = match body {
+        Some(body) => request.send_json(body),
+        None => request.call(),
+    };
+    match response {
+        Ok(response) => response
+            .into_json()
+            .map_err(|e| Error::api(url, format!("invalid response: {}", e))),
+        Err(ureq::Error::Status(code, response)) => Err(Error::api(
+            url,
+            format!(
+                "HTTP {}: {}",
+                code,
+                response.into_string().unwrap_or_default()
+            ),
+        )),
+        Err(ureq::Error::Transport(transport)) => Err(Error::api(
+            url,
+            match transport.message() {
+                Some(message) => format!("{}: {}", transport.kind(), message),
+                None => transport.kind().to_string(),
+            },
+        )),
+    }
+}
+
+fn request_path(options: &SubmitArgs) -> String {
+    match &options.request_path {
+        Some(request_path) => request_path.clone(),
+        None => options.workspace.request_path(),
+    }
+}
+
+// The API only accepts the QUBO form, a request written with --form ising is refused before posting it
+fn check_form(request: &Value, request_path: &str) -> Result<()> {
+    if request.get("binary_polynomial").is_none() && request.get("ising").is_some() {
+        return Err(Error::parameter(
+            "--form",
+            format!(
+                "{} is in the Ising form, which the API does not accept; write it with --form qubo",
+                request_path
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn post(options: &SubmitArgs) -> Result<String> {
+    let request_path: String = request_path(options);
+    let request: Value = crate::read_json(&request_path)?;
+    check_form(&request, &request_path)?;
+    let url: String = format!("{}{}", options.base_url.trim_end_matches('/'), SOLVE_PATH);
+    println!("Posting {} to {}...", request_path, url);
+    let response: Value = call(
+        ureq::post(&url)
+            .set("X-Api-Key", &options.api_key)
+            .set("Accept", "application/json"),
+        &url,
+        Some(&request),
+    )?;
+    match response["job_id"].as_str() {
+        Some(job_id) => Ok(job_id.to_string()),
+        None => Err(Error::api(&url, format!("no job_id in {}", response))),
+    }
+}
+
+fn get(options: &SubmitArgs, job_id: &str) -> Result<Value> {
+    let url: String = format!(
+        "{}{}{}",
+        options.base_url.trim_end_matches('/'),
+        RESULT_PATH,
+        job_id
+    );
+    loop {
+        let response: Value = call(
+            ureq::get(&url)
+                .set("X-Api-Key", &options.api_key)
+                .set("Accept", "application/json"),
+            &url,
+            None,
+        )?;
+        let status: &str = response["status"].as_str().unwrap_or_default();
+        if !options.wait || !PENDING.contains(&status) {
+            return Ok(response);
+        }
+        println!("{}: {}", job_id, status);
+        thread::sleep(Duration::from_secs(options.poll));
+    }
+}
+
+// Save the result of the job to the workspace, or next to a request from elsewhere
+fn save(options: &SubmitArgs, job_id: &str, result: &Value) -> Result<String> {
+    let workspace: &WorkspaceArgs = &options.workspace;
+    let meta_path: String = workspace.metadata_path();
+    let in_workspace: bool = match &options.request_path {
+        Some(request_path) => Path::new(request_path) == Path::new(&workspace.request_path()),
+        None => true,
+    };
+    if !in_workspace || !Path::new(&meta_path).exists() {
+        return write_result_beside(&request_path(options), job_id, result, false);
+    }
+
+    let meta: Value = crate::read_json(&meta_path)?;
+    let number = |key: &str| -> Result<f64> {
+        meta[key]
+            .as_f64()
+            .ok_or_else(|| Error::content(format!("/{}", key), "not a number").in_file(&meta_path))
+    };
+    let jxx = Jxx {
+        j: number("Strength")?,
+        jl: number("Layer_strength")?,
+        l: number("Side_length")? as i32,
+        h: number("Height")? as i32,
+        gamma: number("Gamma")?,
+    };
+    let time: i64 = number("Time_limit_sec")? as i64;
+    let mut result: Value = result.clone();
+    if let Some(fixing) = Fixing::from_metadata(&meta) {
+        fixing.expand_result(&mut result); // Full lattice for the analysis
+    }
+    write_result(workspace, &jxx, &format!("Time{}", time), &result, false)
+}
+
+pub fn submit(options: &SubmitArgs) -> Result<()> {
+    let job_id: String = match &options.job_id {
+        Some(job_id) => job_id.clone(),
+        None => {
+            let job_id: String = post(options)?;
+            println!("job_id: {}", job_id);
+            if !options.wait {
+                return Ok(());
+            }
+            job_id
+        }
+    };
+
+    let result: Value = get(options, &job_id)?;
+    let status: &str = result["status"].as_str().unwrap_or_default();
+    println!("{}: {}", job_id, status);
+    match status {
+        "Done" => {
+            save(options, &job_id, &result)?;
+            Ok(())
+        }
+        "Waiting" | "Running" => Ok(()), // Checked once, without --wait
+        _ => Err(Error::api(
+            &options.base_url,
+            format!(
+                "job {} ended with status {:?} {}",
+                job_id, status, result["message"]
+            ),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn ising_form_is_refused() {
+        let ising: Value =
+            json!({"fujitsuDA3": {}, "ising": {"h": {"0": 1.0}, "J": [], "offset": 0.0}});
+        match check_form(&ising, "input_ISING.json") {
+            Err(e @ Error::Parameter { .. }) => assert!(e.to_string().contains("--form qubo")),
+            _ => panic!("the Ising form must be refused"),
+        }
+        let qubo: Value = json!({"fujitsuDA3": {}, "binary_polynomial": {"terms": []}});
+        assert!(check_form(&qubo, "input.json").is_ok());
+    }
+}
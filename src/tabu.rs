@@ -0,0 +1,152 @@
+// Purpose: One-flip tabu search on an arbitrary binary polynomial
+use crate::polynomial::Polynomial;
+use crate::random::get_rng;
+use crate::solution::{qubo_solution, Solution};
+use rand::prelude::*;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub struct TabuParams {
+    pub tenure: Option<usize>, // Iterations a flipped variable stays tabu (default: N / 8 + 1)
+    pub iterations: usize,     // Iterations of each restart
+    pub restarts: usize,       // Number of restarts from a random configuration
+    pub time_limit_sec: i32,   // Stop every restart once the time limit is reached
+    pub seed: Option<u64>,     // Seed of the random number generator
+    pub initial: Option<Vec<Option<bool>>>, // Start of the first restart by position (guidance_config), random if None
+}
+
+/*
+ * Every iteration flips the non-tabu variable with the lowest flip delta; a tabu variable
+ * is allowed when it leads to a new best energy of the restart (aspiration).
+ * The best configuration of every restart is reported. The first restart starts from the
+ * guidance_config of the request when it has one.
+ */
+pub fn tabu_search(polynomial: &Polynomial, params: &TabuParams) -> Value {
+    let n: usize = polynomial.len();
+    if n == 0 {
+        panic!("The binary polynomial has no variable.");
+    }
+    let tenure: usize = params.tenure.unwrap_or(n / 8 + 1).min(n - 1);
+    let time_limit = Duration::from_secs(params.time_limit_sec.max(0) as u64);
+    let neighbors: Vec<Vec<usize>> = polynomial.neighbors();
+
+    let mut rng = get_rng(params.seed);
+    let start = Instant::now();
+
+    let mut solutions: Vec<Solution> = Vec::new();
+    let mut progress: Vec<Value> = Vec::new();
+    let mut overall_best: f64 = f64::INFINITY;
+
+    for restart in 0..params.restarts {
+        if start.elapsed() >= time_limit {
+            println!("Time limit reached after {} restarts", restart);
+            break;
+        }
+
+        let mut x: Vec<bool> = (0..n)
+            .map(|i| match (restart, &params.initial) {
+                (0, Some(initial)) => initial[i].unwrap_or_else(|| rng.gen()),
+                _ => rng.gen(),
+            })
+            .collect();
+        let mut energy: f64 = polynomial.energy(&x);
+        let mut delta: Vec<f64> = (0..n).map(|i| polynomial.flip_delta(&x, i)).collect();
+        let mut tabu_until: Vec<usize> = vec![0; n];
+        let (mut best, mut best_x): (f64, Vec<bool>) = (energy, x.clone());
+
+        for iteration in 1..=params.iterations {
+            // Checking the clock every iteration is expensive for small problems
+            if iteration % 256 == 0 && start.elapsed() >= time_limit {
+                break;
+            }
+
+            let mut chosen: Option<usize> = None;
+            for i in 0..n {
+                let allowed: bool = tabu_until[i] < iteration || energy + delta[i] < best;
+                if allowed && chosen.is_none_or(|c| delta[i] < delta[c]) {
+                    chosen = Some(i);
+                }
+            }
+            let Some(var) = chosen else {
+                continue; // Every variable is tabu
+            };
+
+            energy += delta[var];
+            x[var] = !x[var];
+            delta[var] = -delta[var];
+            for other in &neighbors[var] {
+                delta[*other] = polynomial.flip_delta(&x, *other);
+            }
+            tabu_until[var] = iteration + tenure;
+
+            if energy < best {
+                best = energy;
+                best_x.clone_from(&x);
+            }
+        }
+
+        // Recompute, the incremental energy carries rounding errors
+        let best: f64 = polynomial.energy(&best_x);
+        if best < overall_best {
+            overall_best = best;
+            progress.push(json!({"energy": best, "time": start.elapsed().as_secs_f64()}));
+        }
+        solutions.push(Solution {
+            configuration: best_x,
+            energy: best,
+            frequency: 1,
+        });
+    }
+
+    println!("best energy: {}", overall_best);
+    qubo_solution(&polynomial.variables, solutions, progress, start.elapsed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polynomial::tests::{configurations, random_polynomial};
+    use crate::solution_set::SolutionSet;
+
+    fn params(restarts: usize, initial: Option<Vec<Option<bool>>>) -> TabuParams {
+        TabuParams {
+            tenure: None,
+            iterations: 200,
+            restarts,
+            time_limit_sec: 60,
+            seed: Some(5),
+            initial,
+        }
+    }
+
+    #[test]
+    fn finds_the_ground_state_of_small_polynomials() {
+        for seed in 1..=3 {
+            let polynomial: Polynomial = random_polynomial(10, seed);
+            let lowest: f64 = configurations(polynomial.len())
+                .map(|x| polynomial.energy(&x))
+                .fold(f64::INFINITY, f64::min);
+            let set: SolutionSet =
+                SolutionSet::from_value(&tabu_search(&polynomial, &params(10, None))).unwrap();
+            assert!((set.energies[0] - lowest).abs() < 1e-9);
+            // Every restart reports one solution, with the energy of its configuration
+            assert_eq!(set.frequencies.iter().sum::<i32>(), 10);
+            for k in 0..set.len() {
+                assert!((polynomial.energy(&set.configuration(k)) - set.energies[k]).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn first_restart_starts_from_the_initial_configuration() {
+        let polynomial: Polynomial = random_polynomial(8, 4);
+        let ground: Vec<bool> = configurations(polynomial.len())
+            .min_by(|a, b| polynomial.energy(a).total_cmp(&polynomial.energy(b)))
+            .unwrap();
+        let mut params: TabuParams = params(1, Some(ground.iter().map(|x| Some(*x)).collect()));
+        params.iterations = 0;
+        let set: SolutionSet = SolutionSet::from_value(&tabu_search(&polynomial, &params)).unwrap();
+        assert_eq!(set.configuration(0), ground);
+    }
+}
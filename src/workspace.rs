@@ -0,0 +1,54 @@
+// Purpose: Layout of the workspace directory (--out-dir / --workspace) shared by every command
+use crate::args::WorkspaceArgs;
+use crate::error::{Error, Result};
+use std::path::Path;
+
+/*
+ * <workspace>/                                               (./workspace without --out-dir)
+ *   input.json                                               request (generate, solve of an external instance)
+ *   metadata.json                                            lattice, couplings and fujitsuDA3 of the request, guidance choice
+ *   config.json                                              resolved run file of generate
+ *   Gamma<G>/Strength<J>_Lattice<L>_<L>_<H>_<SOLVER>.json    result of a local solver (generate --solver)
+ *   Gamma<G>/Strength<J>_Lattice<L>_<L>_<H>_Time<T>.json     result of the API (submit)
+ *   Gamma<G>/<J>_<L>_<L>_<H>.txt                             Gamma analysis (analyze)
+ * guidance reads metadata.json and updates input.json (and the Guidance of metadata.json), submit posts input.json.
+ */
+impl WorkspaceArgs {
+    pub fn request_path(&self) -> String {
+        format!("{}/input.json", self.out_dir)
+    }
+
+    pub fn metadata_path(&self) -> String {
+        format!("{}/metadata.json", self.out_dir)
+    }
+
+    pub fn config_path(&self) -> String {
+        format!("{}/config.json", self.out_dir)
+    }
+
+    // Directory of the results and analyses of one Gamma, created if missing
+    pub fn gamma_dir(&self, gamma: &str) -> Result<String> {
+        let dir: String = format!("{}/Gamma{}", self.out_dir, gamma);
+        crate::create_dir(&dir)?;
+        Ok(dir)
+    }
+
+    // The workspace itself, created if missing
+    pub fn create(&self) -> Result<()> {
+        crate::create_dir(&self.out_dir)
+    }
+
+    // Check that a file of the workspace exists, naming the flag to fix otherwise
+    pub fn existing(&self, path: String) -> Result<String> {
+        if Path::new(&path).exists() {
+            return Ok(path);
+        }
+        Err(Error::parameter(
+            "--out-dir",
+            format!(
+                "{} not found, run generate with the same --out-dir first",
+                path
+            ),
+        ))
+    }
+}